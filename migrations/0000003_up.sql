@@ -0,0 +1,2 @@
+-- RULES OF THE ROOM (purse, squad sizes, foreign players limit and rtms), NULL MEANS THE DEFAULT RULES
+ALTER TABLE rooms ADD COLUMN rules JSONB;
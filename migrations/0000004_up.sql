@@ -0,0 +1,3 @@
+-- BOT PARTICIPANTS ADDED BY THE ROOM CREATOR, THEY WERE NOT HAVING ANY USER
+ALTER TABLE participants ALTER COLUMN user_id DROP NOT NULL;
+ALTER TABLE participants ADD COLUMN is_bot BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,7 @@
+-- IDEMPOTENCY KEYS OF THE AUCTION ROOM BACKGROUND TASKS WHICH WERE COMMITTED, A REPLAYED TASK WITH AN APPLIED KEY WAS SKIPPED
+CREATE TABLE applied_tasks (
+    idempotency_key TEXT NOT NULL,
+    task_type VARCHAR(50) NOT NULL,
+    applied_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (idempotency_key, task_type)
+);
@@ -0,0 +1,8 @@
+-- DEAD LETTER TASKS WERE REPLAYED OR CLOSED BY THE ADMIN, ONLY THE PENDING ONES CAN BE REPLAYED OR CLOSED
+ALTER TABLE dead_letter_tasks
+    ADD COLUMN status TEXT NOT NULL DEFAULT 'pending'
+        CHECK (status IN ('pending', 'replayed', 'resolved', 'discarded'));
+ALTER TABLE dead_letter_tasks ADD COLUMN resolved_at TIMESTAMP;
+
+CREATE INDEX idx_dead_letter_tasks_status_created_at
+    ON dead_letter_tasks (status, created_at DESC, id DESC);
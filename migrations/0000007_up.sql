@@ -0,0 +1,10 @@
+-- SKIPS OF THE PLAYERS AND THE POOLS, SUCH THAT A ROOM REBUILT FROM POSTGRES KEEPS ITS SKIP STATE
+-- KIND WAS player (TARGET WAS THE PLAYER ID) OR pool (TARGET WAS THE POOL NO)
+CREATE TABLE skip_votes (
+    room_id UUID NOT NULL REFERENCES rooms(id) ON DELETE CASCADE,
+    participant_id INT NOT NULL REFERENCES participants(id) ON DELETE CASCADE,
+    kind VARCHAR(10) NOT NULL CHECK (kind IN ('player', 'pool')),
+    target INT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (room_id, participant_id, kind, target)
+);
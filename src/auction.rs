@@ -1,27 +1,55 @@
 use std::collections::HashMap;
 use std::sync::Arc;
+use tokio::sync::mpsc::UnboundedSender;
 use axum::body::Bytes;
-use axum::extract::{Path, State, WebSocketUpgrade};
+use axum::extract::{Path, Query, State, WebSocketUpgrade};
 use axum::extract::ws::{WebSocket, Message};
-use axum::response::IntoResponse;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
 use crate::models::app_state::AppState;
-use crate::models::auction_models::{AuctionParticipant, Bid, ChatMessage, ParticipantAudio, RoomMeta};
+use crate::models::auction_models::{AuctionParticipant, Bid, ChatMessage, ParticipantAudio, RoomMeta, RoomRules, TimeSync};
 use futures_util::stream::StreamExt;
 use futures_util::SinkExt;
 use crate::{services};
 use crate::models::background_db_tasks::{DBCommandsAuctionRoom, CompletedRoom};
-use crate::models::room_models::Participant;
+use crate::models::room_models::{FanOut, Participant, RehydrationError, RoomCommand, RoomEvent};
+use crate::middlewares::authentication::{decode_token, forbidden, unauthorized, websocket_token, WEBSOCKET_AUTH_PROTOCOL};
+use crate::models::protocol_models::{message_for_version, negotiate_protocol_version, ClientMessage, ErrorCode, ServerMessage, WebSocketParams};
 use crate::models::webRTC_models::SignalingMessage;
 
 
-pub async fn ws_handler(ws: WebSocketUpgrade, Path((room_id, participant_id)): Path<(String, i32)>, State(app_state): State<Arc<AppState>>) -> impl IntoResponse {
-    ws.on_upgrade(move |socket| socket_handler(socket, room_id, participant_id, app_state))
+pub async fn ws_handler(ws: WebSocketUpgrade, Path((room_id, participant_id)): Path<(String, i32)>, Query(params): Query<WebSocketParams>, headers: HeaderMap, State(app_state): State<Arc<AppState>>) -> Response {
+    // while shutting down the clients were sent to the other servers
+    if app_state.is_shutting_down() {
+        return (StatusCode::SERVICE_UNAVAILABLE, "Server was shutting down").into_response();
+    }
+    // the route was not under the auth_check middleware, as the token can come from the header, subprotocol or the query
+    let Some(token) = websocket_token(&headers, params.token.as_deref()) else {
+        return unauthorized("Authorization token is missing");
+    } ;
+    let Some(claims) = decode_token(&token) else {
+        return unauthorized("Token has expired or is invalid");
+    } ;
+    // the participant should belong to the user, else any one can connect as that team
+    match app_state.database_connection.is_participant_of_user(participant_id, claims.user_id, &room_id).await {
+        Ok(true) => {},
+        Ok(false) => {
+            tracing::warn!("user {} was not the owner of the participant {} in room {}", claims.user_id, participant_id, room_id) ;
+            return forbidden("Your not a participant of this room");
+        },
+        Err(err) => {
+            tracing::error!("unable to verify the participant {} of the user {} error was {}", participant_id, claims.user_id, err) ;
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    }
+    let protocol_version = negotiate_protocol_version(params.protocol) ;
+    // when the token came as a subprotocol, the same protocol should be selected in the response
+    ws.protocols([WEBSOCKET_AUTH_PROTOCOL])
+        .on_upgrade(move |socket| socket_handler(socket, room_id, participant_id, protocol_version, app_state))
 }
 
-async fn socket_handler(web_socket: WebSocket, room_id: String,participant_id: i32, app_state: Arc<AppState>) {
-    tracing::info!("A new websocket connection has been established");
-    // if room doesn't exist, we are going to create a broadcast channel over here
-    let mut rooms = app_state.rooms.write().await;
+async fn socket_handler(web_socket: WebSocket, room_id: String,participant_id: i32, protocol_version: u8, app_state: Arc<AppState>) {
+    tracing::info!("A new websocket connection has been established with protocol version {}", protocol_version);
     let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<Message>() ;
     let (mut sender, mut receiver) = web_socket.split() ;
 
@@ -49,7 +77,15 @@ async fn socket_handler(web_socket: WebSocket, room_id: String,participant_id: i
        Ok(room_mode) => room_mode,
         Err(err) =>{
             tracing::error!("error in getting room mode {}", err) ;
-            sender.send(Message::text("Server Side Error, Unable to get room-mode")).await.expect("unable to send message");
+            sender.send(ServerMessage::connection_error(ErrorCode::ServerError, "Server Side Error, Unable to get room-mode", protocol_version)).await.expect("unable to send message");
+            return;
+        }
+    } ;
+    let room_rules = match app_state.database_connection.get_room_rules(&room_id).await {
+        Ok(room_rules) => room_rules,
+        Err(err) => {
+            tracing::error!("error in getting room rules {}", err) ;
+            sender.send(ServerMessage::connection_error(ErrorCode::ServerError, "Server Side Error, Unable to get room-rules", protocol_version)).await.expect("unable to send message");
             return;
         }
     } ;
@@ -57,7 +93,7 @@ async fn socket_handler(web_socket: WebSocket, room_id: String,participant_id: i
         Ok(room_status) => room_status,
         Err(err) => {
             tracing::error!("error in getting room_status in ws {}", err) ;
-            sender.send(Message::text("Server Side Error, Unable to create connection")).await.expect("unable to send message");
+            sender.send(ServerMessage::connection_error(ErrorCode::ServerError, "Server Side Error, Unable to create connection", protocol_version)).await.expect("unable to send message");
             return;
         }
     } ;
@@ -67,68 +103,94 @@ async fn socket_handler(web_socket: WebSocket, room_id: String,participant_id: i
             result
         },Err(err ) => {
             tracing::warn!("unable to get team_selected by a participant {}", err) ;
-            sender.send(Message::text("Server Side Error, Unable to create connection")).await.expect("unable to send message");
-            drop(rooms) ;
+            sender.send(ServerMessage::connection_error(ErrorCode::ServerError, "Server Side Error, Unable to create connection", protocol_version)).await.expect("unable to send message");
             return;
         }
     } ;
 
 
-    if let Some(vec) = rooms.get_mut(&room_id) {
-        {
-            // tracing::info!("*** doing a double check if user was opened in multiple tabs") ;
-            // let mut index = 0 ;
-            // for participant in vec.iter() {
-            //     if participant.0 == participant_id {
-            //         tracing::info!("we got a duplicate") ;
-            //         break;
-            //     }
-            //     index += 1 ;
-            // }
-            // if index < vec.len() {
-            //     tracing::info!(" as index is less than the number of participants, closing the transaction") ;
-            //     let mut previous_value = vec.remove(index) ;
-            //     let _ = previous_value.1.send(Message::text("close-connection"));
-            //     // we need this close frame, and wait for this to complete
-            //     /*
-            //         Browser refresh
-            //         │
-            //         ├─ Old WS still open
-            //         │
-            //         ├─ New WS connects (same participant_id)
-            //         │
-            //         ├─ Server removes old tx and sends "close-connection"
-            //         │
-            //         ├─ Browser receives CLOSE frame for OLD socket
-            //         │   ❌ Browser may treat it as connection-level close
-            //         │
-            //         ├─ New socket handshake is still in progress
-            //         │
-            //         └─ Browser reports WebSocket error
-            //
-            //         So we are waiting to execute the whole thing
-            //     */
-            // } this logic still failing sometimes that too only in chrome browser, but working in firefox
-        }
-        vec.push((participant_id, tx)) ;
-        tracing::info!("Room exists, adding participant {}", participant_id);
-    }else{
-        tracing::info!("Room doesn't exist, creating a new room {}", room_id);
-        rooms.insert(room_id.clone(), vec![(participant_id, tx)]);
-        tracing::info!("Creating room in redis") ;
+    {
+        // tracing::info!("*** doing a double check if user was opened in multiple tabs") ;
+        // let mut index = 0 ;
+        // for participant in vec.iter() {
+        //     if participant.0 == participant_id {
+        //         tracing::info!("we got a duplicate") ;
+        //         break;
+        //     }
+        //     index += 1 ;
+        // }
+        // if index < vec.len() {
+        //     tracing::info!(" as index is less than the number of participants, closing the transaction") ;
+        //     let mut previous_value = vec.remove(index) ;
+        //     let _ = previous_value.1.send(Message::text("close-connection"));
+        //     // we need this close frame, and wait for this to complete
+        //     /*
+        //         Browser refresh
+        //         │
+        //         ├─ Old WS still open
+        //         │
+        //         ├─ New WS connects (same participant_id)
+        //         │
+        //         ├─ Server removes old tx and sends "close-connection"
+        //         │
+        //         ├─ Browser receives CLOSE frame for OLD socket
+        //         │   ❌ Browser may treat it as connection-level close
+        //         │
+        //         ├─ New socket handshake is still in progress
+        //         │
+        //         └─ Browser reports WebSocket error
+        //
+        //         So we are waiting to execute the whole thing
+        //     */
+        // } this logic still failing sometimes that too only in chrome browser, but working in firefox
+    }
+    // the room actor was created if this was the first connection of the room on this server
+    let room = services::room_actor::room_handle(&room_id, &app_state).await ;
+    // the sender was kept to remove this connection alone on disconnect, other tabs of the participant were not affected
+    let connection = tx.clone() ;
+    room.fan_out.send(FanOut::Connect { participant_id, sender: tx }).expect("fan out task of the room was stopped") ;
+    tracing::info!("participant {} was added to the room {}", participant_id, room_id);
+    // reconnected within the grace period, so no leave will be broadcasted for the previous connection
+    let reconnected = app_state.redis_connection.stop_reconnect_grace(&room_id, participant_id).await
+        .inspect_err(|err| tracing::error!("unable to end the grace period of the participant {} {}", participant_id, err))
+        .unwrap_or(false) ;
+    if reconnected {
+        tracing::info!("participant {} reconnected within the grace period", participant_id) ;
     }
-    drop(rooms); // release lock early
+    // only the version 2 connections receives the welcome, version 1 was not having it
+    send_server_message(&ServerMessage::Welcome {
+        protocol_version,
+        participant_id
+    }, participant_id, &room_id, &app_state).await ;
 
     if room_status == "not_started" || room_status == "in_progress" {
         // over here we are going to check room-status if room-status was not-started or pending, if it is finished, then return
          // we stored the tx, which is used to send the data to the receiver channel
 
-        if  !redis_connection.check_room_existence(&room_id).await.unwrap() {
+        let room_exists = redis_connection.check_room_existence(&room_id).await.unwrap() ;
+        if !room_exists && room_status == "in_progress" {
+            // redis lost the room while the auction was running, so it was rebuilt from postgres
+            tracing::warn!("room {} was in progress but not in redis, rebuilding it", room_id) ;
+            match services::room_rehydration::rehydrate_room(&room_id, &app_state).await {
+                Ok(rehydrated_room) => tracing::info!("room was rebuilt, resumes from the player {}", rehydrated_room.next_player_id),
+                Err(RehydrationError::InProgress) => {
+                    tracing::info!("room {} was being rebuilt by another join, waiting for it", room_id) ;
+                    services::room_rehydration::wait_for_rehydration(&room_id, &app_state).await ;
+                },
+                Err(err) => tracing::error!("unable to rebuild the room {}", err.message())
+            }
+        } else if room_exists && room_status == "in_progress" {
+            // timers were paused if the last server of the room was shut down
+            if redis_connection.resume_timers(&room_id).await.unwrap_or(false) {
+                tracing::info!("timers of the room {} paused on shutdown were resumed", room_id) ;
+            }
+        } else if !room_exists {
             tracing::info!("creating room in redis as it doesn't exists in redis") ;
             redis_connection.set_room_meta(&room_id, RoomMeta {
                 room_creator_id: participant_id,
                 pause: false
             }).await.expect("Room unable to Create");
+            redis_connection.set_room_rules(&room_id, &room_rules).await.expect("Unable to store room rules");
         }
         let participant_exists = redis_connection.check_participant(&room_id, participant_id).await ;
         let participant_exists = match participant_exists {
@@ -136,17 +198,18 @@ async fn socket_handler(web_socket: WebSocket, room_id: String,participant_id: i
             Err(err) => {
                 tracing::error!("unable to get the check participant in redis") ;
                 tracing::warn!("error was {}", err) ;
-                sender.send(Message::text("Server Side Error, Unable to create connection")).await.expect("unable to send message");
+                sender.send(ServerMessage::connection_error(ErrorCode::ServerError, "Server Side Error, Unable to create connection", protocol_version)).await.expect("unable to send message");
                 return;
             }
         } ;
         // over here we are going to add participant to the redis
         if !participant_exists && room_status == "not_started" {
-            let result = redis_connection.set_participant(&room_id, AuctionParticipant::new(
+            let participant = AuctionParticipant::new(
                 participant_id,
                 team_name.clone(),
-                3 // by default for each and every team having 3 rtms
-            )).await ;
+                &room_rules // purse and rtms of each team comes from the room rules
+            ) ;
+            let result = redis_connection.set_participant(&room_id, participant.clone()).await ;
 
             match result {
                 Ok(val) => {
@@ -154,42 +217,38 @@ async fn socket_handler(web_socket: WebSocket, room_id: String,participant_id: i
                 }  ,
                 Err(err) => {
                     tracing::warn!("error in the adding participant to the redis was {}", err) ;
-                    sender.send(Message::text("Server Side Error, Unable to create connection")).await.expect("unable to send message");
+                    sender.send(ServerMessage::connection_error(ErrorCode::ServerError, "Server Side Error, Unable to create connection", protocol_version)).await.expect("unable to send message");
                     return;
                 }
             };
             tracing::info!("sending the new participant to all other participants") ;
-            let participant = AuctionParticipant {
-                id: participant_id,
-                team_name: team_name.clone(),
-                balance: 100.00,
-                total_players_brought: 0,
-                remaining_rtms: 3,
-                is_unmuted: true,
-                foreign_players_brought: 0
-            } ;
-            broadcast_handler(Message::from(serde_json::to_string(&participant).unwrap()), &room_id, &app_state).await;
+            broadcast_server_message(&ServerMessage::ParticipantJoined(participant), &room_id, &app_state).await;
             tracing::info!("new member has joined in the room {} and with team {}", room_id, team_name) ;
 
         }else if !participant_exists {
           tracing::info!("participant not exists and room_status was in_progress") ;
-            send_himself(Message::text("Auction Started Room was close"), participant_id, &room_id, &app_state).await ;
+            send_error(ErrorCode::RoomClosed, "Auction Started Room was close", participant_id, &room_id, &app_state).await ;
             return;
         } else{
             let Some(participant) = redis_connection.get_participant(&room_id,participant_id).await.unwrap() else {
                 tracing::error!("The participant was in the redis room but we are not getting the participant from the get_participant") ;
-                sender.send(Message::text("Your not in the room")).await.expect("unable to send message");
+                sender.send(ServerMessage::connection_error(ErrorCode::NotPermitted, "Your not in the room", protocol_version)).await.expect("unable to send message");
                 return;
             } ;
             tracing::info!("sending the new participant to all other participants") ;
             // here we are going to get the details of the old participant, and sending the old participant details
-            broadcast_handler(Message::from(serde_json::to_string(&participant).unwrap()), &room_id, &app_state).await;
+            broadcast_server_message(&ServerMessage::ParticipantJoined(participant), &room_id, &app_state).await;
+            // the participant was back, so the autopilot of his team stops
+            if redis_connection.stop_autopilot(&room_id, participant_id).await.unwrap_or(false) {
+                tracing::info!("participant {} reconnected, autopilot stopped", participant_id) ;
+                broadcast_server_message(&ServerMessage::AutopilotStopped { team_name: team_name.clone() }, &room_id, &app_state).await;
+            }
             tracing::info!("new member has joined in the room {} and with team {}", room_id, team_name) ;
 
         }
 
     }else if room_status == "completed" {
-        sender.send(Message::text("Auction was completed, Room was Closed")).await.expect("unable to send the message to the sender") ;
+        sender.send(ServerMessage::connection_error(ErrorCode::RoomClosed, "Auction was completed, Room was Closed", protocol_version)).await.expect("unable to send the message to the sender") ;
     }
 
 
@@ -208,33 +267,41 @@ async fn socket_handler(web_socket: WebSocket, room_id: String,participant_id: i
     } ;
         // first convert to hashmap
         let mut hashmap = HashMap::new() ;
-    {
-        for participant in app_state.rooms.read().await.get(&room_id).unwrap().iter() {
-            hashmap.insert(participant.0, true) ;
-        }
+    for connected in services::room_actor::present_participants(&room_id, &app_state).await {
+        hashmap.insert(connected, true) ;
     }
-        participants.retain(|p| hashmap.contains_key(&p));
+        // bots and the teams on autopilot were not having any connection, but they were in the room
+        let bots = services::bot_participants::automated_participants(&room_id, &app_state).await ;
+        participants.retain(|p| hashmap.contains_key(&p) || bots.contains(p));
     let mut participant_object: Vec<AuctionParticipant> = vec![];
     for participant in participants.iter() {
         let participant_obj = redis_connection.get_participant(&room_id, *participant).await.unwrap().unwrap() ;
         participant_object.push(participant_obj) ;
     }
-        send_himself(
-            Message::from(serde_json::to_string(&participant_object).unwrap()),participant_id, &room_id, &app_state
+        send_server_message(
+            &ServerMessage::Participants { participants: participant_object },participant_id, &room_id, &app_state
         ).await ; //> sending remaining participants their team name and participant_id
         tracing::info!("sent all active participants list to the participant") ;
 
-    let msg ;
+    // rules of the room along with the increment ladder, such that the UI can show the next bid amount
+    send_server_message(
+        &ServerMessage::RoomRules(room_rules), participant_id, &room_id, &app_state
+    ).await ;
+
     if room_mode {
-        msg = Message::text("strict-mode") ;
-        send_himself(msg, participant_id, &room_id, &app_state).await ;
+        send_server_message(&ServerMessage::StrictMode, participant_id, &room_id, &app_state).await ;
     }
 
+    // current player, bid, timers, pause, rtm and skip states, such that a reconnecting client can continue the auction
+    services::auction_logic_executor::send_room_snapshot(&room_id, participant_id, &app_state, room_mode).await ;
+
 
     tokio::spawn({
         let room_id = room_id.clone();
         let participant_id = participant_id;
         let app_state = app_state.clone();
+        // a weak sender, else the channel will never be closed when the connection was dropped
+        let connection = connection.downgrade();
 
         async move {
             while let Some(msg) = rx.recv().await {
@@ -251,23 +318,26 @@ async fn socket_handler(web_socket: WebSocket, room_id: String,participant_id: i
                         let _ = sender.send(Message::Close(None)).await;
 
                         // Removing participant from room (DROPING sender)
-                        let mut rooms = app_state.rooms.write().await;
-                        if let Some(vec) = rooms.get_mut(&room_id) {
-                            vec.retain(|(id, _)| *id != participant_id);
+                        if let Some(connection) = connection.upgrade() {
+                            services::room_actor::fan_out(&room_id, FanOut::Disconnect { participant_id, sender: connection }, &app_state).await;
                         }
 
                         break; // exiting loop → rx dropped → task ends
                     }
                 }
 
+                // converting to the protocol version of this connection
+                let Some(msg) = message_for_version(msg, protocol_version) else {
+                    continue;
+                };
+
                 // 4️⃣ Normal forwarding
                 if let Err(err) = sender.send(msg).await {
                     tracing::warn!("WebSocket send failed: {}", err);
 
                     // Cleanup on failure
-                    let mut rooms = app_state.rooms.write().await;
-                    if let Some(vec) = rooms.get_mut(&room_id) {
-                        vec.retain(|(id, _)| *id != participant_id);
+                    if let Some(connection) = connection.upgrade() {
+                        services::room_actor::fan_out(&room_id, FanOut::Disconnect { participant_id, sender: connection }, &app_state).await;
                     }
 
                     break;
@@ -293,9 +363,6 @@ async fn socket_handler(web_socket: WebSocket, room_id: String,participant_id: i
     */
 
 
-    let expiry_time = std::env::var("BID_EXPIRY").unwrap().parse::<u8>().unwrap();
-    let timer_key = format!("auction:timer:{}", room_id); // if this key exists in the redis then no bids takes place
-    let rtm_timer_key = format!("auction:timer:rtms:{}", room_id) ;
     // let's read continuous messages from the client
     while let Some(message) = receiver.next().await {
         match message {
@@ -304,360 +371,477 @@ async fn socket_handler(web_socket: WebSocket, room_id: String,participant_id: i
                 match message {
                     Message::Text(text) => {
                         tracing::info!("Received text message: {}", text);
-                        let text = text.to_string() ;
-                        // if a bid message was sent, then we are going to check for allowance
-                        if text == "ping" {
-                            tracing::info!("a ping message in room {}", room_id) ;
-                            send_himself(Message::Pong(Bytes::from_static(b"pong")), participant_id,&room_id,&app_state).await ;
-                            continue;
-                        }else if text == "mute" || text == "unmute" {
-                            let value = text ;
-                            tracing::info!("{} message was received", value) ;
-                            let x = team_name.to_string()+ " " + &value.clone() + "d" ; // the message will be mute-12, means participant 12 has muted himself
-                            broadcast_handler(Message::text(x), &room_id, &app_state).await ;
-                            // from now we are going to store the mute and unmute states
-                            let val ;
-                            if value == "mute" {
-                                val = false ;
-                            }else {
-                                val = true ;
+                        // json messages of the protocol version 2 and the string commands of version 1 were parsed into ClientMessage
+                        let client_message = match ClientMessage::parse(text.as_str()) {
+                            Ok(client_message) => client_message,
+                            Err(err) => {
+                                tracing::warn!("unable to parse the message {} error was {}", text, err) ;
+                                send_error(ErrorCode::InvalidMessage, &err, participant_id, &room_id, &app_state).await ;
+                                continue;
                             }
-                            redis_connection.toggle_mute(&room_id, participant_id, val).await.expect("Unable to update mute and unmute status") ;
-                            broadcast_handler(Message::from(serde_json::to_string(&ParticipantAudio {
-                                participant_id,
-                                is_unmuted: val
-                            }).unwrap()), &room_id, &app_state).await ;
-                        }else if text == "start" {
-
-                            tracing::info!("going to call the start auction function") ;
-                            services::auction_logic_executor::start_auction(&room_id, participant_id, &app_state, expiry_time, room_mode).await ;
-
-                        }else if text == "bid" {
-                            // room_id: String, participant_id: i32,app_state: &AppState, timer_key: String, team_name: &str, room_mode: bool, expiry_time: u8
-                            tracing::info!("going to execute the bid logic") ;
-                            services::auction_logic_executor::bid(&room_id, participant_id,&app_state, &timer_key, &team_name, room_mode, expiry_time).await ;
-
-                        } else if text == "end" {
-                            // ending the auction
-                            // when we click on end we are getting only exit as the message without any reason
-                            // check whether he was the creator of the room
-
-                                    if redis_connection.get_room_meta(&room_id).await.unwrap().unwrap().room_creator_id != participant_id {
-                                        send_himself(Message::text("Only Creator can have permission"), participant_id, &room_id, &app_state).await ;
-                                    }else if redis_connection.check_key_exists(&rtm_timer_key).await.unwrap() {
-                                        send_himself(Message::text("During RTM You cannot End the Auction"), participant_id, &room_id, &app_state).await ;
-                                    }else{
-                                        tracing::info!("deleting the timer key ") ;
-                                        redis_connection.atomic_delete(&timer_key).await.unwrap() ;
-                                        // second, check whether all the participants having least 15 players in their squad
-                                        // no need to have a condition to have at least 15 players in each squad
-                                        tracing::info!("cleaning up the redis keys related to the auction") ;
-                                        let res = redis_connection.auction_clean_up(&room_id).await ;
-                                        let message ;
-                                        match res {
-                                            Ok(res) => {
-                                                if res {
-                                                    // when front-end has disconnected automatically it's going to be the end.
-                                                    // we are going to change the state of the auction to completed such that this room get's invalid
-                                                    match app_state.database_connection.update_room_status(&room_id, "completed").await {
-                                                        Ok(_) => {
-                                                            tracing::info!("room status changed to completed") ;
-                                                            // here we are going to remove the data from redis
-                                                            tracing::info!("successfully removed the room from redis") ;
-                                                            // we are going to make sure add the completed_at field and also unsold players list from this auction
-
-                                                            // deleting the unsold players list
-                                                            //app_state.database_connection.remove_unsold_players(&room_id).await.expect("error occurred while deleting unsold players") ;
-                                                            let completed_room = CompletedRoom {
-                                                                room_id: room_id.clone(),
-                                                                retry_count: 0,
-                                                                last_error: "".to_string()
-                                                            } ;
-                                                            app_state.auction_room_database_task_executor.send(
-                                                                DBCommandsAuctionRoom::CompletedRoomSoldPlayers(completed_room.clone())
-                                                            ).unwrap() ;
-                                                            app_state.auction_room_database_task_executor.send(
-                                                                DBCommandsAuctionRoom::CompletedRoomUnsoldPlayers(completed_room.clone())
-                                                            ).unwrap() ;
-                                                            app_state.auction_room_database_task_executor.send(
-                                                                DBCommandsAuctionRoom::CompletedRoomCompletedAt(completed_room)
-                                                            ).unwrap() ;
-                                                            /*
-                                                                over here we are going to assign a background task which removes unsold players from the unsold_players table
-                                                                and store it in another table , called list of completed rooms unsold players.
-
-                                                                And then if the room was created and was not been in completed status after 24 hours a cron job,
-                                                                will executed and change the status and remove the redis logic and everything, for every 24 hours.
-                                                                or when ever i run the logic explicitly
-
-                                                                another logic, was asking the user to allow location when they are signing in.
-
-                                                                lastly adding connector_id to disable multiple joining of room.
-                                                            */
-                                                            // updating the set_completed_at
-                                                            
-
-                                                            message = Message::text("exit") ; // in front-end when this message was executed then it must stop the ws connection with server
-                                                        },
-                                                        Err(err) => {
-                                                            tracing::info!("unable to update the room status to completed") ;
-                                                            tracing::error!("{}",err) ;
-                                                            message = Message::text("Technical Issue") ;
-                                                        }
-                                                    }
-
-                                                }else {
-                                                    message = Message::text("Unable to End Auction, Due to Technical Problem") ;
-                                                }
-                                            },
-                                            Err(_) => {
-                                                tracing::info!("Unable to get the room") ;
-                                                message = Message::text("Till all participants brought at least 15 player") ;
-                                            }
-                                        } ;
-                                        broadcast_handler(message,&room_id,&app_state).await ;
-                                    }
+                        } ;
+                        // state changes of the room were applied one after the other by the room actor
+                        services::room_actor::send_command(&room_id, RoomCommand::Client {
+                            participant_id,
+                            team_name: team_name.clone(),
+                            room_mode,
+                            message: client_message
+                        }, &app_state).await ;
 
-                        }else if text == "pause" {
-
-                            // we are going to pause the auction, such that when clicked create again, going to start from the last player
-                                    if redis_connection.get_room_meta(&room_id).await.unwrap().unwrap().room_creator_id == participant_id {
-                                        // we are going to pause auction after the current bid
-                                        match redis_connection.set_pause(&room_id, true).await {
-                                            Ok(_) => {
-                                                tracing::info!("successfully set the status to pause") ;
-                                                send_himself(Message::text("After the Current Bid Auction will be Paused"), participant_id, &room_id, &app_state).await ;
-                                            },
-                                            Err(err) => {
-                                                tracing::error!("error occurred while setting the pause status") ;
-                                                tracing::error!("err was {}", err) ;
-                                                send_himself(Message::text("Technical Problem"), participant_id, &room_id, &app_state).await ;
-                                            }
+                    },
+                    Message::Close(_) => {
+                        tracing::info!("Client disconnected");
+                        handle_disconnect(&room_id, participant_id, team_name, connection, &app_state).await ;
+                        return;
+                    }
+                    Message::Binary(_) => {
+                        send_error(ErrorCode::InvalidMessage, "Binary messages were not supported", participant_id, &room_id, &app_state).await ;
+                    },
+                    Message::Ping(bytes) => {
+                        // Browser will NEVER reach here.
+                        // Only servers / Node clients can trigger this.
+                    },
+                    Message::Pong(_) => {
+                        // replies to the pings of the server, nothing to do
+                    },
+                }
+            },
+            Err(err) => {
+                tracing::warn!("disrupt disconnect, mostly on page refreshes") ;
+                handle_disconnect(&room_id, participant_id, team_name, connection, &app_state).await ;
+                return
+            },
+        }
+    }
+}
+
+// client messages were applied by the room actor, one after the other
+pub async fn handle_client_message(room_id: String, participant_id: i32, team_name: String, room_mode: bool, client_message: ClientMessage, app_state: Arc<AppState>) {
+    let redis_connection = app_state.redis_connection.clone() ;
+    let expiry_time = std::env::var("BID_EXPIRY").unwrap().parse::<u8>().unwrap();
+    let timer_key = format!("auction:timer:{}", room_id); // if this key exists in the redis then no bids takes place
+    let rtm_timer_key = format!("auction:timer:rtms:{}", room_id) ;
+    match client_message {
+        ClientMessage::Ping => {
+            tracing::info!("a ping message in room {}", room_id) ;
+            send_himself(Message::Pong(Bytes::from_static(b"pong")), participant_id,&room_id,&app_state).await ;
+        },
+        ClientMessage::Mute | ClientMessage::Unmute => {
+            // from now we are going to store the mute and unmute states
+            let is_unmuted = matches!(client_message, ClientMessage::Unmute) ;
+            tracing::info!("{} message was received", if is_unmuted { "unmute" } else { "mute" }) ;
+            broadcast_server_message(&ServerMessage::TeamMuted { team_name: team_name.clone(), is_unmuted }, &room_id, &app_state).await ;
+            redis_connection.toggle_mute(&room_id, participant_id, is_unmuted).await.expect("Unable to update mute and unmute status") ;
+            broadcast_server_message(&ServerMessage::ParticipantAudio(ParticipantAudio {
+                participant_id,
+                is_unmuted
+            }), &room_id, &app_state).await ;
+        },
+        ClientMessage::Start => {
+
+            tracing::info!("going to call the start auction function") ;
+            services::auction_logic_executor::start_auction(&room_id, participant_id, &app_state, expiry_time, room_mode).await ;
+            services::bot_participants::schedule_bot_bids(room_id.clone(), app_state.clone()) ;
+
+        },
+        ClientMessage::Autopilot { enabled } => {
+
+            // opt in for the autopilot, such that the team keeps bidding when the participant got disconnected
+            services::bot_participants::set_autopilot(&room_id, participant_id, &app_state, enabled).await ;
+
+        },
+        ClientMessage::AddBot => {
+
+            // creator adds a bot from the lobby, bot takes one of the remaining teams
+            services::bot_participants::add_bot(&room_id, participant_id, &app_state).await ;
+
+        },
+        ClientMessage::Bid { amount } => {
+            // amount was only for the jump bids, eg : bid-5.50 means bidding 5.50cr directly
+            if amount.is_some_and(|amount| !amount.is_finite() || amount <= 0.0) {
+                tracing::warn!("invalid jump bid amount {:?}", amount) ;
+                send_error(ErrorCode::BidRejected, "Invalid Bid Amount", participant_id, &room_id, &app_state).await ;
+                return;
+            }
+            tracing::info!("going to execute the bid logic") ;
+            services::auction_logic_executor::bid(&room_id, participant_id,&app_state, &timer_key, room_mode, expiry_time, amount).await ;
+            // the teams who were outbid may have proxy bids
+            services::proxy_bidding::run_proxy_bids(&room_id, &app_state, &timer_key, room_mode, expiry_time).await ;
+            services::bot_participants::schedule_bot_bids(room_id.clone(), app_state.clone()) ;
+
+        },
+        ClientMessage::Proxy { player_id, max_amount } => {
+
+            services::proxy_bidding::register_proxy_bid(&room_id, participant_id, &app_state, player_id, Some(max_amount), room_mode, expiry_time).await ;
+            services::bot_participants::schedule_bot_bids(room_id.clone(), app_state.clone()) ;
+
+        },
+        ClientMessage::ProxyCancel { player_id } => {
+
+            services::proxy_bidding::register_proxy_bid(&room_id, participant_id, &app_state, player_id, None, room_mode, expiry_time).await ;
+
+        },
+        ClientMessage::End => {
+        // ending the auction
+        // when we click on end we are getting only exit as the message without any reason
+        // check whether he was the creator of the room
+
+                if redis_connection.get_room_meta(&room_id).await.unwrap().unwrap().room_creator_id != participant_id {
+                    send_error(ErrorCode::NotPermitted, "Only Creator can have permission", participant_id, &room_id, &app_state).await ;
+                }else if redis_connection.check_key_exists(&rtm_timer_key).await.unwrap() {
+                    send_error(ErrorCode::NotPermitted, "During RTM You cannot End the Auction", participant_id, &room_id, &app_state).await ;
+                }else{
+                    tracing::info!("deleting the timer key ") ;
+                    redis_connection.cancel_timer(&timer_key).await.unwrap() ;
+                    // second, check whether all the participants having least min_squad_size players in their squad
+                    // no need to have a condition to have at least min_squad_size players in each squad
+                    // rules were read before the clean up removes them
+                    let min_squad_size = redis_connection.get_room_rules(&room_id).await.unwrap_or_default().min_squad_size ;
+                    tracing::info!("cleaning up the redis keys related to the auction") ;
+                    let res = redis_connection.auction_clean_up(&room_id).await ;
+                    let message ;
+                    match res {
+                        Ok(res) => {
+                            if res {
+                                // when front-end has disconnected automatically it's going to be the end.
+                                // we are going to change the state of the auction to completed such that this room get's invalid
+                                match services::auction::DatabaseAccess::update_room_status(&app_state.database_connection.connection, &room_id, "completed").await {
+                                    Ok(_) => {
+                                        tracing::info!("room status changed to completed") ;
+                                        // here we are going to remove the data from redis
+                                        tracing::info!("successfully removed the room from redis") ;
+                                        // we are going to make sure add the completed_at field and also unsold players list from this auction
+
+                                        // deleting the unsold players list
+                                        //app_state.database_connection.remove_unsold_players(&room_id).await.expect("error occurred while deleting unsold players") ;
+                                        let completed_room = CompletedRoom {
+                                            room_id: room_id.clone(),
+                                            idempotency_key: format!("completed:{}", room_id),
+                                            enqueued_at: chrono::Utc::now().timestamp_millis(),
+                                            retry_count: 0,
+                                            last_error: "".to_string()
                                         } ;
-                                    }else {
-                                        send_himself(Message::text("Only Creator can have permission"), participant_id, &room_id, &app_state).await ;
-                                    }
+                                        let commands = [
+                                            DBCommandsAuctionRoom::CompletedRoomSoldPlayers(completed_room.clone()),
+                                            DBCommandsAuctionRoom::CompletedRoomUnsoldPlayers(completed_room.clone()),
+                                            DBCommandsAuctionRoom::CompletedRoomCompletedAt(completed_room)
+                                        ] ;
+                                        for command in commands {
+                                            // the room was already completed, the admin runs the tasks which were not queued
+                                            if let Err(err) = app_state.auction_room_database_task_executor.send(command).await {
+                                                tracing::error!("{}, run /admin/auction_completed_tasks_execution for the room {}", err, room_id) ;
+                                            }
+                                        }
+                                        /*
+                                            over here we are going to assign a background task which removes unsold players from the unsold_players table
+                                            and store it in another table , called list of completed rooms unsold players.
+
+                                            And then if the room was created and was not been in completed status after 24 hours a cron job,
+                                            will executed and change the status and remove the redis logic and everything, for every 24 hours.
+                                            or when ever i run the logic explicitly
+
+                                            another logic, was asking the user to allow location when they are signing in.
 
-                        }else if text == "rtm-accept" { // need to check RTM, why even timer was there it was failing and also need to check whether the RTM timer was the expiry time
-                            // can only be called, if the key was rtms
-                            if redis_connection.check_key_exists(&rtm_timer_key).await.unwrap() {
-                                tracing::info!("rtm was being accepted") ;
-                                redis_connection.atomic_delete(&rtm_timer_key).await.unwrap();
-                                // accepting the bid
-                                let bid = redis_connection.get_current_bid(&room_id).await.unwrap().unwrap() ;
-                                let bid = Bid::new(participant_id, bid.player_id, bid.bid_amount, bid.base_price, false, true) ;
-                                // adding the bid to the redis
-                                match redis_connection.update_current_bid(&room_id, bid, 1, participant_id, room_mode, true).await {
-                                    Ok(_) => {},
+                                            lastly adding connector_id to disable multiple joining of room.
+                                        */
+                                        // updating the set_completed_at
+                                        
+
+                                        message = ServerMessage::Exit ; // in front-end when this message was executed then it must stop the ws connection with server
+                                    },
                                     Err(err) => {
-                                        if err.contains("Bid not allowed") {
-                                            send_himself(Message::text(&err), participant_id, &room_id, &app_state).await ;
-                                        }else {
-                                            send_himself(Message::text("Technical Issue"), participant_id, &room_id, &app_state).await ;
-                                        }
+                                        tracing::info!("unable to update the room status to completed") ;
+                                        tracing::error!("{}",err) ;
+                                        message = ServerMessage::error(ErrorCode::ServerError, "Technical Issue") ;
                                     }
-                                };
+                                }
+
                             }else {
-                                send_message_to_participant(participant_id, String::from("Invalid RTM was not taken place"), &room_id, &app_state).await ;
+                                message = ServerMessage::error(ErrorCode::ServerError, "Unable to End Auction, Due to Technical Problem") ;
                             }
-                        }else if text == "instant-rtm-cancel" {
+                        },
+                        Err(_) => {
+                            tracing::info!("Unable to get the room") ;
+                            let error = format!("Till all participants brought at least {} players", min_squad_size) ;
+                            message = ServerMessage::error(ErrorCode::ServerError, &error) ;
+                        }
+                    } ;
+                    broadcast_server_message(&message,&room_id,&app_state).await ;
+                    if matches!(message, ServerMessage::Exit) {
+                        // published after the exit, so the connections receives it before being closed
+                        publish_room_event(Some(RoomEvent::close_room()), &room_id, &app_state).await ;
+                    }
+                }
 
-                            services::auction_logic_executor::instant_rtm_cancel(&room_id, participant_id, &app_state, room_mode, &rtm_timer_key).await ;
+        },
+        ClientMessage::Pause => {
+
+        // we are going to pause the auction, such that when clicked create again, going to start from the last player
+                if redis_connection.get_room_meta(&room_id).await.unwrap().unwrap().room_creator_id == participant_id {
+                    // we are going to pause auction after the current bid
+                    match redis_connection.set_pause(&room_id, true).await {
+                        Ok(_) => {
+                            tracing::info!("successfully set the status to pause") ;
+                            send_server_message(&ServerMessage::PauseScheduled, participant_id, &room_id, &app_state).await ;
+                        },
+                        Err(err) => {
+                            tracing::error!("error occurred while setting the pause status") ;
+                            tracing::error!("err was {}", err) ;
+                            send_error(ErrorCode::ServerError, "Technical Problem", participant_id, &room_id, &app_state).await ;
+                        }
+                    } ;
+                }else {
+                    send_error(ErrorCode::NotPermitted, "Only Creator can have permission", participant_id, &room_id, &app_state).await ;
+                }
 
-                        } else if text.contains("rtm-cancel") {
+        },
+        ClientMessage::RtmAccept => { // need to check RTM, why even timer was there it was failing and also need to check whether the RTM timer was the expiry time
+        // can only be called, if the key was rtms
+        if redis_connection.check_key_exists(&rtm_timer_key).await.unwrap() {
+            tracing::info!("rtm was being accepted") ;
+            // accepting the bid
+            let seen_bid = redis_connection.get_current_bid(&room_id).await.unwrap().unwrap() ;
+            let bid = Bid::new(participant_id, seen_bid.player_id, seen_bid.bid_amount, seen_bid.base_price, false, true) ;
+            // adding the bid to the redis, along with removing the rtm timer
+            services::auction_logic_executor::settle_rtm(&room_id, participant_id, &app_state, &rtm_timer_key, &seen_bid, bid, 1).await ;
+        }else {
+            send_error(ErrorCode::RtmRejected, "Invalid RTM was not taken place", participant_id, &room_id, &app_state).await ;
+        }
+        },
+        ClientMessage::InstantRtmCancel => {
 
-                            services::auction_logic_executor::rtm_cancel(&room_id, participant_id, &app_state, room_mode, &rtm_timer_key).await ;
+        services::auction_logic_executor::instant_rtm_cancel(&room_id, participant_id, &app_state, room_mode, &rtm_timer_key).await ;
 
-                        } else if text.contains("rtm") {
+        },
+        ClientMessage::RtmCancel => {
 
-                            // room_id: &str, participant_id: i32, app_state: &AppState, room_mode: bool, rtm_timer_key: &str, text: String, expiry_time: u8
-                            services::auction_logic_executor::use_rtm(&room_id, participant_id, &app_state, room_mode, &rtm_timer_key, text, expiry_time).await ;
+        services::auction_logic_executor::rtm_cancel(&room_id, participant_id, &app_state, room_mode, &rtm_timer_key).await ;
 
-                        }else if text == "skip" {
+        },
+        ClientMessage::Rtm { amount } => {
 
-                            // room_id: &str, participant_id: i32, app_state: &AppState, timer_key: &str, room_mode: bool, text: String, team_name: &str
-                            tracing::info!("executing skip") ;
-                            services::auction_logic_executor::skip(&room_id, participant_id, &app_state, &timer_key, room_mode, text, &team_name).await ;
+        // room_id: &str, participant_id: i32, app_state: &AppState, room_mode: bool, rtm_timer_key: &str, amount: f32, expiry_time: u8
+        services::auction_logic_executor::use_rtm(&room_id, participant_id, &app_state, room_mode, &rtm_timer_key, amount, expiry_time).await ;
 
-                        }else if text.contains("chat-") {
-                          tracing::info!("received the message from the team {}", team_name) ;
-                            // chat-message
-                            let message = text.split("-").collect::<Vec<&str>>()[1].parse::<String>().unwrap() ;
-                            tracing::info!("message was {}", message) ;
+        },
+        ClientMessage::Skip { reason } => {
 
-                            // no stroing chats in the db, because it was an emergency case, when the audio communication was lost
-                            broadcast_handler(Message::from(serde_json::to_string(
-                                &ChatMessage{
-                                    team_name: team_name.clone(),
-                                    message
-                                }
-                            ).unwrap()),&room_id,&app_state).await ;
-
-                            /*
-                                we need to make sure notify the other users, with no of chats , with chat symbol on top for
-                                mobile users , what ever message they got. when the chat symbol is clicked it will be hovered on
-                                auction, with transparent background, they can see chats as well as the auction.
-                            */
-
-                        }else if text == "skip-current-pool" {
-
-                            // going to add a new feature, so maintain a separate state in the redis
-                            /*
-                               -> using a hash set for storing list of users skipped the current pool
-                               -> so if every one skipped the pool , then we will jump into the next pool
-                               in between even a player skipped the pool is allowed to bid for the players
-                               in pool, once every one has skipped then the pool will be jumped to another pool.
-                               -> If all skipped, from the next player we are going to jump to the next pool.
-                            */
-                            tracing::info!("*=* skip current pool called");
-                            redis_connection.mark_participant_skipped_pool(&room_id, participant_id).await.map_err(
-                                |e| {
-                                    tracing::error!("marking participant skipped pool room_id {} and participant_id {}", room_id, participant_id) ;
-                                    tracing::error!("error was {} for room_id {}", e, room_id) ;
-                                }
-                            ).unwrap() ;
-                            broadcast_handler(Message::text(&format!("{} want's to skip the current-pool", team_name)),&room_id,&app_state).await ;
-
-                        }else if text == "get-is-skipped-pool" {
-                            tracing::info!("asking whether he {} skipped pool or not", participant_id) ;
-
-                            let result = redis_connection.is_participant_skipped_pool(&room_id, participant_id).await.map_err(|err| {
-
-                            }).unwrap() ;
-                            let message ;
-                            if result {
-                                tracing::info!("he skipped") ;
-                                message = Message::text("is_skipped:true") ;
-                            }else{
-                                tracing::info!("he not skipped yet") ;
-                                message = Message::text("is_skipped:false") ;
-                            }
-                            send_himself(message, participant_id, &room_id, &app_state).await ;
-                        }
-                        else {
-                            let message ;
-                            let to_participant ;
-                            tracing::info!("******************* Message for WeB RTC was ***************************") ;
-                            tracing::info!("here is the front-end passed message {}", text.to_string()) ;
-                            tracing::info!("****************************** *****************************************") ;
-                            if !(text.starts_with('{') && text.ends_with('}')) {
-                                tracing::warn!("Ignoring non-JSON message: {}", text);
-                                continue;
-                            }
-                            let parsed: SignalingMessage = match serde_json::from_str(&text) {
-                                Ok(msg) => msg,
-                                Err(err) => {
-                                    eprintln!("Failed to parse signaling message: {}", err);
-                                    send_himself(Message::text("Unable to parse what you have sent"), participant_id, &room_id, &app_state).await ;
-                                    continue;
-                                }
-                            };
-
-                            match parsed {
-
-                                SignalingMessage::Offer { from, to, payload } => {
-                                    tracing::info!("got the offer from {} and to {}", from, to) ;
-                                    message = Message::from(serde_json::to_string(&SignalingMessage::Offer {from, to, payload}).unwrap()) ;
-                                    to_participant = to ;
-                                },
-                                SignalingMessage::Answer { from, to, payload } => {
-                                    // forward to participant `to`
-                                    tracing::info!("got the answer from {} and to {}", from, to) ;
-                                    message = Message::from(serde_json::to_string(&SignalingMessage::Answer {from, to, payload}).unwrap()) ;
-                                    to_participant = to ;
-                                }
+        // room_id: &str, participant_id: i32, app_state: &AppState, timer_key: &str, room_mode: bool, reason: Option<String>, team_name: &str
+        tracing::info!("executing skip") ;
+        services::auction_logic_executor::skip(&room_id, participant_id, &app_state, &timer_key, room_mode, reason, &team_name).await ;
 
-                                SignalingMessage::IceCandidate { from, to, payload } => {
-                                    // forward to participant `to`
-                                    tracing::info!("got the ice-candidate from {} and to {}", from, to) ;
-                                    message = Message::from(serde_json::to_string(&SignalingMessage::IceCandidate {from, to, payload}).unwrap()) ;
-                                    to_participant = to ;
-                                }
+        },
+        ClientMessage::Chat { message } => {
+        tracing::info!("received the message from the team {}", team_name) ;
+        tracing::info!("message was {}", message) ;
 
-                                _ => {
-                                    message = Message::text("Invalid Message") ;
-                                    to_participant = participant_id ;
-                                }
-                            };
+        // no stroing chats in the db, because it was an emergency case, when the audio communication was lost
+        broadcast_server_message(&ServerMessage::Chat(ChatMessage{
+                team_name: team_name.clone(),
+                message
+            }),&room_id,&app_state).await ;
+
+        /*
+            we need to make sure notify the other users, with no of chats , with chat symbol on top for
+            mobile users , what ever message they got. when the chat symbol is clicked it will be hovered on
+            auction, with transparent background, they can see chats as well as the auction.
+        */
+
+        },
+        ClientMessage::SkipCurrentPool => {
+
+        // going to add a new feature, so maintain a separate state in the redis
+        /*
+           -> using a hash set for storing list of users skipped the current pool
+           -> so if every one skipped the pool , then we will jump into the next pool
+           in between even a player skipped the pool is allowed to bid for the players
+           in pool, once every one has skipped then the pool will be jumped to another pool.
+           -> If all skipped, from the next player we are going to jump to the next pool.
+        */
+        tracing::info!("*=* skip current pool called");
+        redis_connection.mark_participant_skipped_pool(&room_id, participant_id).await.map_err(
+            |e| {
+                tracing::error!("marking participant skipped pool room_id {} and participant_id {}", room_id, participant_id) ;
+                tracing::error!("error was {} for room_id {}", e, room_id) ;
+            }
+        ).unwrap() ;
+        // bots and autopilot were going with the pool skip requested by the participants
+        let automated_participants = services::bot_participants::automated_participants(&room_id, &app_state).await ;
+        for bot_id in automated_participants.iter() {
+            redis_connection.mark_participant_skipped_pool(&room_id, *bot_id).await.expect("unable to mark the bot skipped pool") ;
+        }
+        if let Some(current_player) = redis_connection.get_current_player(&room_id).await.unwrap() {
+            for voter_id in std::iter::once(participant_id).chain(automated_participants) {
+                services::auction_logic_executor::record_skip_vote(&room_id, voter_id, "pool", i32::from(current_player.pool_no), &app_state).await ;
+            }
+        }
+        broadcast_server_message(&ServerMessage::PoolSkipRequested { team_name: team_name.clone() },&room_id,&app_state).await ;
+
+        },
+        ClientMessage::TimeSync { client_time } => {
+
+        // the client finds the clock skew with the redis clock, which the timer deadlines were from
+        let server_time = redis_connection.server_time().await
+            .unwrap_or_else(|_| chrono::Utc::now().timestamp_millis()) ;
+        send_server_message(&ServerMessage::TimeSync(TimeSync {
+            client_time,
+            server_time
+        }), participant_id, &room_id, &app_state).await ;
+
+        },
+        ClientMessage::GetSnapshot => {
+
+        services::auction_logic_executor::send_room_snapshot(&room_id, participant_id, &app_state, room_mode).await ;
+
+        },
+        ClientMessage::GetIsSkippedPool => {
+        tracing::info!("asking whether he {} skipped pool or not", participant_id) ;
+
+        let is_skipped = redis_connection.is_participant_skipped_pool(&room_id, participant_id).await.map_err(|err| {
+
+        }).unwrap() ;
+        tracing::info!("skipped pool {}", is_skipped) ;
+        send_server_message(&ServerMessage::IsSkippedPool { is_skipped }, participant_id, &room_id, &app_state).await ;
+        },
+        ClientMessage::Signaling(parsed) => {
+        let message ;
+        let to_participant ;
+        tracing::info!("******************* Message for WeB RTC was ***************************") ;
+        tracing::info!("here is the front-end passed message {:?}", parsed) ;
+        tracing::info!("****************************** *****************************************") ;
+
+        match parsed {
+
+            SignalingMessage::Offer { from, to, payload } => {
+                tracing::info!("got the offer from {} and to {}", from, to) ;
+                message = Message::from(serde_json::to_string(&SignalingMessage::Offer {from, to, payload}).unwrap()) ;
+                to_participant = to ;
+            },
+            SignalingMessage::Answer { from, to, payload } => {
+                // forward to participant `to`
+                tracing::info!("got the answer from {} and to {}", from, to) ;
+                message = Message::from(serde_json::to_string(&SignalingMessage::Answer {from, to, payload}).unwrap()) ;
+                to_participant = to ;
+            }
 
-                            send_himself(message, to_participant, &room_id, &app_state).await ;
+            SignalingMessage::IceCandidate { from, to, payload } => {
+                // forward to participant `to`
+                tracing::info!("got the ice-candidate from {} and to {}", from, to) ;
+                message = Message::from(serde_json::to_string(&SignalingMessage::IceCandidate {from, to, payload}).unwrap()) ;
+                to_participant = to ;
+            }
+        };
 
-                        }
+        send_himself(message, to_participant, &room_id, &app_state).await ;
 
-                    },
-                    Message::Close(_) => {
-                        tracing::info!("Client disconnected");
-                        handle_disconnect(&room_id, participant_id, team_name, &app_state).await ;
-                        return;
-                    }
-                    Message::Binary(bytes) => todo!(),
-                    Message::Ping(bytes) => {
-                        // Browser will NEVER reach here.
-                        // Only servers / Node clients can trigger this.
-                    },
-                    Message::Pong(bytes) => todo!(),
-                }
-            },
-            Err(err) => {
-                tracing::warn!("disrupt disconnect, mostly on page refreshes") ;
-                handle_disconnect(&room_id, participant_id, team_name, &app_state).await ;
-                return
-            },
         }
     }
 }
 
-
-pub async fn handle_disconnect(room_id: &str, participant_id: i32, team_name: String,app_state: &AppState) {
+pub async fn handle_disconnect(room_id: &str, participant_id: i32, team_name: String, connection: UnboundedSender<Message>, app_state: &Arc<AppState>) {
     // we are removing the disconnected client, such that the unbounded channel will not overload if queue is filled with multiple disconnected message to client
-    let mut value = app_state.rooms.write().await ;
-    let mut index: u8 = 0 ;
-    let mut user_exists = false ;
-    for participant in value.get(room_id).unwrap().iter() {
-        if participant.0 == participant_id {
-            user_exists = true ;
-            break
-        }
-        index += 1 ;
-    }
+    services::room_actor::fan_out(room_id, FanOut::Disconnect { participant_id, sender: connection }, app_state).await ;
 
     /*
         need to broadcast which participant has been disconnected, and when joins we are any way sending the message
     */
 
-    if user_exists {
-        value.get_mut(room_id).unwrap().remove(index as usize);
+    // on page refreshes the new connection may come before the old one was closed, may be on another server too
+    let still_connected = services::room_actor::present_participants(room_id, app_state).await.contains(&participant_id) ;
+    if still_connected {
+        return;
     }
-    drop(value) ;
-    broadcast_handler(Message::from(serde_json::to_string(&Participant { participant_id, team_name }).unwrap()), room_id, &app_state).await ;
-}
 
-pub async fn broadcast_handler(msg: Message,room_id: &str, state: &AppState) { // if we want to send a message to all the participants in the room, we use broadcaster
-    // over here we are going to get all the participants from the room-id
-    // and send the message to all the participants
-    let mut rooms = state.rooms.read().await;
-    for sender in rooms.get(room_id).unwrap().iter() {
-        if let Ok(_) = sender.1.send(msg.clone()) {
-            tracing::info!("Message sent to participant successfully");
-        }else{
-            tracing::info!("Failed to send message to participant");
+    /*
+        the participant was still counted as present for the grace period, such that a page refresh will not
+        broadcast the leave or make the room fall under 3 participants. if he not reconnects within it, then he left.
+    */
+    let grace_period = reconnect_grace_period() ;
+    let until = chrono::Utc::now().timestamp_millis() + grace_period.as_millis() as i64 ;
+    if let Err(err) = app_state.redis_connection.start_reconnect_grace(room_id, participant_id, until).await {
+        tracing::error!("unable to start the grace period of the participant {} {}", participant_id, err) ;
+    }
+    let room_id = room_id.to_string() ;
+    let app_state = app_state.clone() ;
+    tokio::spawn(async move {
+        tokio::time::sleep(grace_period).await ;
+        // a reconnect to any of the servers removes it, and if he disconnected again then the latest disconnect decides
+        let left = app_state.redis_connection.end_reconnect_grace(&room_id, participant_id, until).await
+            .inspect_err(|err| tracing::error!("unable to end the grace period of the participant {} {}", participant_id, err))
+            .unwrap_or(false) ;
+        // he may have reconnected to another server, whose grace period was not having him
+        let reconnected = services::room_actor::present_participants(&room_id, &app_state).await.contains(&participant_id) ;
+        if left && !reconnected {
+            services::room_actor::send_command(&room_id, RoomCommand::ParticipantLeft { participant_id, team_name }, &app_state).await ;
         }
+    });
+}
+
+fn reconnect_grace_period() -> std::time::Duration {
+    let seconds = std::env::var("RECONNECT_GRACE_SECS").ok()
+        .and_then(|seconds| seconds.parse::<u64>().ok())
+        .unwrap_or(10) ;
+    std::time::Duration::from_secs(seconds)
+}
+
+pub async fn participant_left(room_id: &str, participant_id: i32, team_name: String, app_state: &AppState) {
+    tracing::info!("participant {} not reconnected within the grace period", participant_id) ;
+    broadcast_server_message(&ServerMessage::ParticipantLeft(Participant { participant_id, team_name: team_name.clone() }), room_id, app_state).await ;
+
+    let redis_connection = app_state.redis_connection.clone() ;
+    if redis_connection.is_autopilot_opted_in(room_id, participant_id).await.unwrap_or(false) {
+        redis_connection.start_autopilot(room_id, participant_id).await.expect("unable to start the autopilot") ;
+        tracing::info!("participant {} was disconnected, his team was on autopilot", participant_id) ;
+        broadcast_server_message(&ServerMessage::AutopilotStarted { team_name }, room_id, app_state).await ;
     }
-} // lock drops over here
+}
+
+// connected participants of all the servers along with the bots of the room, used for the min participants and skip counts
+pub async fn participants_count(room_id: &str, state: &AppState) -> usize {
+    let present = services::room_actor::present_participants(room_id, state).await ;
+    let bots = state.redis_connection.get_bots_count(room_id).await.unwrap_or(0) ;
+    // disconnected teams on autopilot were still present in the room
+    let autopilot = state.redis_connection.get_autopilot_count(room_id).await.unwrap_or(0) ;
+    // so as the participants who were disconnected within the grace period
+    let reconnecting = state.redis_connection.reconnecting_participants(room_id).await.unwrap_or_default()
+        .iter().filter(|participant_id| !present.contains(participant_id)).count() ;
+    present.len() + bots + autopilot + reconnecting
+}
+
+pub async fn broadcast_handler(msg: Message,room_id: &str, state: &AppState) { // if we want to send a message to all the participants in the room, we use broadcaster
+    // published to the channel of the room, the fan out task of every server sends it to its connections of the room
+    publish_room_event(RoomEvent::new(None, msg), room_id, state).await ;
+}
 
 pub async fn send_himself(msg: Message, participant_id: i32,room_id: &str, state: &AppState) {
-    let mut rooms = state.rooms.read().await;
-    for sender in rooms.get(room_id).unwrap().iter() {
-        if participant_id == sender.0 {
-            if let Ok(_) = sender.1.send(msg.clone()) {
-                tracing::info!("Message sent to participant successfully");
-            }else{
-                tracing::error!("Failed to send message to participant");
-            }
-        }
+    // the participant may be connected to another server too (multiple tabs)
+    publish_room_event(RoomEvent::new(Some(participant_id), msg), room_id, state).await ;
+}
+
+async fn publish_room_event(event: Option<RoomEvent>, room_id: &str, state: &AppState) {
+    let Some(event) = event else {
+        return;
+    } ;
+    if let Err(err) = state.redis_connection.publish_room_event(room_id, &event).await {
+        // at least the connections of this server receives it
+        tracing::error!("unable to publish the message of the room {} {}", room_id, err) ;
+        services::room_actor::fan_out(room_id, event.into_fan_out(), state).await ;
     }
 }
 
+// typed messages were sent as json, the forwarding task converts them to the protocol version of the connection
+pub async fn broadcast_server_message(message: &ServerMessage, room_id: &str, state: &AppState) {
+    broadcast_handler(message.to_message(), room_id, state).await ;
+}
+
+pub async fn send_server_message(message: &ServerMessage, participant_id: i32, room_id: &str, state: &AppState) {
+    send_himself(message.to_message(), participant_id, room_id, state).await ;
+}
+
+pub async fn send_error(code: ErrorCode, message: &str, participant_id: i32, room_id: &str, state: &AppState) {
+    send_server_message(&ServerMessage::error(code, message), participant_id, room_id, state).await ;
+}
+
 
 #[tracing::instrument(
     name = "bid_allowance_handler",
@@ -673,9 +857,10 @@ pub async fn bid_allowance_handler(
     balance: f32,
     total_players_brought: u8,
     strict_mode: bool,
+    rules: &RoomRules,
 ) -> bool {
 
-    if total_players_brought >= 25 {
+    if total_players_brought >= rules.max_squad_size {
         return false ;
     }
 
@@ -690,13 +875,18 @@ pub async fn bid_allowance_handler(
         // -------------------------
         // RULE A: GLOBAL LIMITS
         // -------------------------
+        // the limits were designed for a 100cr purse, so they are scaled to the purse of the room
+        let purse_scale = rules.purse / 100.0;
+        // and the segments to the minimum squad of the room (5, 10 and 15 players for a squad of 15)
+        let (first_segment, second_segment, last_segment) = rules.strict_segments();
+        // the first two fifths of the last segment still keeps a small reserve
+        let late_segment = second_segment + (last_segment - second_segment) * 2 / 5;
         let min_required_balance = match total_players_brought {
-            0..=4 => 50.0,
-            5..=9 => 10.0,
-            10..=11 => 4.0,
-            12..=14 => 0.0,
+            brought if brought < first_segment => 50.0,
+            brought if brought < second_segment => 10.0,
+            brought if brought < late_segment => 4.0,
             _ => 0.0,
-        };
+        } * purse_scale;
 
         tracing::warn!("minimum required balance is {}", min_required_balance) ;
 
@@ -709,11 +899,12 @@ pub async fn bid_allowance_handler(
         // -------------------------
         // Determine segment buffer
         let (segment_max_players, buffer_per_player) = match total_players_brought {
-            0..=4 => (5, 5.0),
-            5..=9 => (10, 4.0),
-            10..=14 => (15, 1.0),
-            _ => (15, 0.0),
+            brought if brought < first_segment => (first_segment, 5.0),
+            brought if brought < second_segment => (second_segment, 4.0),
+            brought if brought < last_segment => (last_segment, 1.0),
+            _ => (last_segment, 0.0),
         };
+        let buffer_per_player = buffer_per_player * purse_scale;
         tracing::warn!("segment max players is {}", segment_max_players) ;
         tracing::warn!("buffer per player is {}", buffer_per_player) ;
         let mut remaining_players_in_segment =
@@ -744,7 +935,7 @@ pub async fn bid_allowance_handler(
     }
 
     // FREE MODE LOGIC
-    let total_players_required = 15 - total_players_brought as i32;
+    let total_players_required = (rules.min_squad_size as i32 - total_players_brought as i32).max(0);
     let money_required = total_players_required as f32 * 0.30;
     remaining_balance >= money_required
 }
@@ -754,20 +945,6 @@ pub async fn bid_allowance_handler(
     while generating use of RTM also , we need to make sure that the previous team has the ability to get foreign player or not.
 */
 
-pub async fn send_message_to_participant(participant_id: i32, message: String, room_id: &str, state: &AppState) {
-    let mut rooms = state.rooms.read().await;
-    for sender in rooms.get(room_id).unwrap().iter() {
-        if participant_id == sender.0 {
-            if let Ok(_) = sender.1.send(Message::text(&message)) {
-                tracing::info!("Message sent to participant successfully");
-                break;
-            }else{
-                tracing::info!("Failed to send message to participant");
-                break;
-            }
-        }
-    }
-}
 
 
 
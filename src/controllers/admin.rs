@@ -2,10 +2,14 @@ use std::sync::Arc;
 use axum::extract::{Path, State};
 use axum::{Json, Router};
 use http::StatusCode;
-use crate::models::admin_models::AuctionCompletedTasksExecutionModel;
+use subtle::ConstantTimeEq;
+use crate::models::admin_models::{AuctionCompletedTasksExecutionModel, DeadLetterClose, DeadLetterCloseModel, DeadLetterInspectModel, DeadLetterListModel, DeadLetterPage, DeadLetterReplay, DeadLetterReplayModel, RehydrateRoomModel};
 use crate::models::app_state::{AppState, Player};
-use crate::models::background_db_tasks::{CompletedRoom, DBCommandsAuctionRoom};
+use crate::models::background_db_tasks::{CompletedRoom, DBCommandsAuctionRoom, DeadLetterTask};
+use crate::models::room_models::{RehydratedRoom, RehydrationError};
 use crate::services::auction_room::RedisConnection;
+use crate::services::dead_letters::replay_dead_letter_tasks;
+use crate::services::room_rehydration::rehydrate_room;
 
 pub async fn get_player(State(app_state): State<Arc<AppState>>, Path(player_id): Path<i32>) -> Result<(StatusCode, Json<Player>), (StatusCode, String)> {
     let redis_connection = RedisConnection::new().await;
@@ -17,24 +21,106 @@ pub async fn get_player(State(app_state): State<Arc<AppState>>, Path(player_id):
 
 pub async fn auction_completed_tasks_execution(State(app_state): State<Arc<AppState>>, Json(details): Json<AuctionCompletedTasksExecutionModel>) -> Result<(StatusCode, String), (StatusCode, String)> {
     tracing::info!("getting admin password") ;
-    let password = std::env::var("ADMIN_PASSWORD").unwrap_or_else(|_| "".to_string());
-    if password != details.password {
-        return Err((StatusCode::INTERNAL_SERVER_ERROR, "Invalid Password".to_string()))
-    }
+    check_admin_password(&details.password)?;
     tracing::info!("password was correct now execute the tasks") ;
     let completed_room = CompletedRoom {
         room_id: details.room_id.clone(),
+        // same key as the tasks queued when the room completed, so running it again after them was a no-op
+        idempotency_key: format!("completed:{}", details.room_id),
+        enqueued_at: chrono::Utc::now().timestamp_millis(),
         retry_count: 0,
         last_error: "".to_string()
     } ;
-    app_state.auction_room_database_task_executor.send(
-        DBCommandsAuctionRoom::CompletedRoomSoldPlayers(completed_room.clone())
-    ).unwrap() ;
-    app_state.auction_room_database_task_executor.send(
-        DBCommandsAuctionRoom::CompletedRoomUnsoldPlayers(completed_room.clone())
-    ).unwrap() ;
-    app_state.auction_room_database_task_executor.send(
+    let commands = [
+        DBCommandsAuctionRoom::CompletedRoomSoldPlayers(completed_room.clone()),
+        DBCommandsAuctionRoom::CompletedRoomUnsoldPlayers(completed_room.clone()),
         DBCommandsAuctionRoom::CompletedRoomCompletedAt(completed_room)
-    ).unwrap() ;
+    ] ;
+    for command in commands {
+        // the tasks which were queued before were skipped by their idempotency key when this was called again
+        if let Err(err) = app_state.auction_room_database_task_executor.send(command).await {
+            tracing::error!("{}", err) ;
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, "Unable to queue the tasks, try again".to_string()))
+        }
+    }
     Ok((StatusCode::OK, "Successfully Executed".to_string()))
-}
\ No newline at end of file
+}
+
+// rebuilds the redis state of an in progress room from postgres, after redis lost it
+pub async fn rehydrate_room_state(State(app_state): State<Arc<AppState>>, Json(details): Json<RehydrateRoomModel>) -> Result<(StatusCode, Json<RehydratedRoom>), (StatusCode, String)> {
+    check_admin_password(&details.password)?;
+    match rehydrate_room(&details.room_id, &app_state).await {
+        Ok(rehydrated_room) => Ok((StatusCode::OK, Json(rehydrated_room))),
+        Err(err) => {
+            let status = match err {
+                RehydrationError::RoomNotInProgress | RehydrationError::NoParticipants | RehydrationError::AuctionFinished => StatusCode::BAD_REQUEST,
+                RehydrationError::RoomStateExists | RehydrationError::InProgress => StatusCode::CONFLICT,
+                RehydrationError::Failed(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            } ;
+            Err((status, err.message()))
+        }
+    }
+}
+
+// admin routes were disabled when ADMIN_PASSWORD was not set, else an empty password was letting everyone in
+fn check_admin_password(password: &str) -> Result<(), (StatusCode, String)> {
+    let admin_password = std::env::var("ADMIN_PASSWORD").unwrap_or_default();
+    if admin_password.is_empty() {
+        tracing::warn!("admin call was refused as ADMIN_PASSWORD was not set") ;
+        return Err((StatusCode::FORBIDDEN, "Admin routes are disabled".to_string()))
+    }
+    // compared in constant time, so the time taken does not give away how much of the password matched
+    if !bool::from(admin_password.as_bytes().ct_eq(password.as_bytes())) {
+        return Err((StatusCode::UNAUTHORIZED, "Invalid Password".to_string()))
+    }
+    Ok(())
+}
+
+const DEAD_LETTER_STATUSES: [&str; 4] = ["pending", "replayed", "resolved", "discarded"];
+
+pub async fn list_dead_letter_tasks(State(app_state): State<Arc<AppState>>, Json(details): Json<DeadLetterListModel>) -> Result<(StatusCode, Json<DeadLetterPage>), (StatusCode, String)> {
+    check_admin_password(&details.password)?;
+    let status = details.status.unwrap_or_else(|| String::from("pending"));
+    if !DEAD_LETTER_STATUSES.contains(&status.as_str()) {
+        return Err((StatusCode::BAD_REQUEST, "Invalid Status".to_string()))
+    }
+    let page = details.page.unwrap_or(1).max(1);
+    let per_page = details.per_page.unwrap_or(20).clamp(1, 100);
+    match app_state.database_connection.list_dead_letter_tasks(
+        details.task_type.as_deref(), details.room_id.as_deref(), &status, per_page, (page - 1) * per_page
+    ).await {
+        Ok((tasks, total)) => Ok((StatusCode::OK, Json(DeadLetterPage { tasks, page, per_page, total }))),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, "Error while listing the dead letter tasks".to_string()))
+    }
+}
+
+pub async fn inspect_dead_letter_task(State(app_state): State<Arc<AppState>>, Json(details): Json<DeadLetterInspectModel>) -> Result<(StatusCode, Json<DeadLetterTask>), (StatusCode, String)> {
+    check_admin_password(&details.password)?;
+    match app_state.database_connection.get_dead_letter_task(details.id).await {
+        Ok(Some(task)) => Ok((StatusCode::OK, Json(task))),
+        Ok(None) => Err((StatusCode::NOT_FOUND, "Dead letter task not found".to_string())),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, "Error while getting the dead letter task".to_string()))
+    }
+}
+
+// sends the pending tasks back to the auction room background tasks with reset retries
+pub async fn replay_dead_letters(State(app_state): State<Arc<AppState>>, Json(details): Json<DeadLetterReplayModel>) -> Result<(StatusCode, Json<DeadLetterReplay>), (StatusCode, String)> {
+    check_admin_password(&details.password)?;
+    match replay_dead_letter_tasks(&app_state, &details.ids).await {
+        Ok(replay) => Ok((StatusCode::OK, Json(replay))),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, "Error while replaying the dead letter tasks".to_string()))
+    }
+}
+
+pub async fn close_dead_letter_tasks(State(app_state): State<Arc<AppState>>, Json(details): Json<DeadLetterCloseModel>) -> Result<(StatusCode, Json<DeadLetterClose>), (StatusCode, String)> {
+    check_admin_password(&details.password)?;
+    match app_state.database_connection.update_dead_letter_tasks_status(&details.ids, "pending", details.status.as_str()).await {
+        Ok(tasks) => {
+            let updated: Vec<i64> = tasks.iter().map(|task| task.id).collect();
+            let skipped = details.ids.iter().filter(|id| !updated.contains(id)).copied().collect();
+            Ok((StatusCode::OK, Json(DeadLetterClose { updated, skipped })))
+        },
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, "Error while closing the dead letter tasks".to_string()))
+    }
+}
+
@@ -105,6 +105,8 @@ pub async fn authentication_handler(
         UserExternalDetails {
             user_id: id,
             ip_address: ip_address.to_string(),
+            retry_count: 0,
+            last_error: String::from("")
         }
     )).expect("Unable to send the message to the db task executor");
 
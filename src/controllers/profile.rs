@@ -37,7 +37,9 @@ pub async fn update_favorite_team(State(app_state): State<Arc<AppState>>, Extens
                 FavoriteTeamUpdated {
                     user_id: user.user_id,
                     old_favorite_team,
-                    new_favorite_team: new_team.clone()
+                    new_favorite_team: new_team.clone(),
+                    retry_count: 0,
+                    last_error: String::from("")
                 }
             )).expect("unable to send the data to the Favorite Team Updated queue") ;
 
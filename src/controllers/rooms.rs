@@ -5,15 +5,18 @@ use axum::http::StatusCode;
 use axum::response::IntoResponse;
 use serde_json::json;
 use crate::models::app_state::AppState;
+use crate::models::auction_models::RoomRules;
 use crate::models::authentication_models::Claims;
 use crate::models::room_models::{Participant, ParticipantResponse, ParticipantsWithTeam, Rooms};
 use crate::models::player_models::Teams;
 
-pub async fn create_room(State(app_state): State<Arc<AppState>>, Extension(user): Extension<Claims>, Path((team_name, is_strict_mode)) : Path<(String,bool)>) -> impl IntoResponse  {
+pub async fn create_room(State(app_state): State<Arc<AppState>>, Extension(user): Extension<Claims>, Path((team_name, is_strict_mode)) : Path<(String,bool)>, rules: Option<Json<RoomRules>>) -> impl IntoResponse  {
     /*
         we are going to create a new room and then returning participant_id, and then in front-end, it will immediately
         create a websocket connection with the server, and the server will send all the details to the room if any
         new team has joined everything.
+
+        the rules of the room are optional json body, when no body was sent the room plays with the default rules.
     */
     let team_name_check = Teams::check_team(&team_name);
     if !team_name_check {
@@ -22,8 +25,16 @@ pub async fn create_room(State(app_state): State<Arc<AppState>>, Extension(user)
             Json(json!({ "message": "Invalid Team Name" })),
         ) ;
     }
+    let rules = rules.map(|Json(rules)| rules).unwrap_or_default();
+    if let Err(message) = rules.validate() {
+        tracing::warn!("invalid room rules {:?} : {}", rules, message);
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "message": message })),
+        ) ;
+    }
     // first creating a room
-    match app_state.database_connection.create_room(user.user_id, is_strict_mode).await {
+    match app_state.database_connection.create_room(user.user_id, is_strict_mode, &rules).await {
         Ok(room_id) => {
             let participant_id = app_state.database_connection.add_participant(user.user_id, room_id.clone(), team_name.clone()).await.expect("Unable to add participant to the room");
             tracing::info!("created participant_id {} for the room_id {} and team_name {} ", participant_id, room_id, team_name);
@@ -33,6 +44,7 @@ pub async fn create_room(State(app_state): State<Arc<AppState>>, Extension(user)
                     "room_id": room_id,
                     "team_name": team_name,
                     "participant_id": participant_id,
+                    "rules": rules,
                     "message": "Room Created Successfully"
                 })),
             )
@@ -11,16 +11,19 @@ use dotenv::dotenv;
 
 use crate::auction::ws_handler;
 
-use crate::models::app_state::AppState;
+use crate::models::app_state::{AppState, JournaledQueue, TaskQueue};
+use crate::models::retry_policy::RetryPolicies;
 use crate::routes::players_routes::players_routes;
 use crate::routes::rooms_routes::rooms_routes;
 use crate::services::auction::DatabaseAccess;
-use crate::services::auction_room::listen_for_expiry_events;
+use crate::services::auction_room::{listen_for_room_events, recover_in_flight_auctions, run_timer_scheduler};
 use crate::services::other::load_players_to_redis;
+use crate::services::db_journal::{replay_journal, run_journal_keeper};
+use crate::services::shutdown::{drain_background_tasks, prepare_shutdown, shutdown_signal};
 use tower_http::cors::{CorsLayer};
 use crate::controllers::others::feed_back;
 use crate::controllers::profile::update_favorite_team;
-use crate::models::background_db_tasks::{DBCommandsAuction, DBCommandsAuctionRoom};
+use crate::models::background_db_tasks::{DBCommandsAuction, JournaledTask};
 use crate::routes::admin_routes::admin_routes;
 use crate::services::background_db_tasks_runner::{background_task_executor_outside_auction_db_calls, background_tasks_executor, listening_to_retries, save_to_DLQ};
 use crate::observability::http_tracing::http_trace_layer;
@@ -53,35 +56,78 @@ async fn main() {
     }else{
         tcp_listener = tokio::net::TcpListener::bind(format!("[::]:{}", port)).await.unwrap();
     }
-    let app = routes().await;
-    axum::serve(tcp_listener, app.into_make_service_with_connect_info::<SocketAddr>()).await.unwrap();
+    let (app, state, retries) = routes().await;
+    let shutdown_state = state.clone();
+    axum::serve(tcp_listener, app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(async move {
+            shutdown_signal().await;
+            prepare_shutdown(&shutdown_state).await;
+        })
+        .await.unwrap();
+    // the websockets were closed, so no more tasks will be sent to the background channels
+    drain_background_tasks(&state, retries).await;
+    tracing::info!("server stopped");
 
 }
 
 
-async fn routes() -> Router {
-    let (tx, mut rx) = mpsc::unbounded_channel::<DBCommandsAuctionRoom>();
+async fn routes() -> (Router, Arc<AppState>, tokio::task::JoinHandle<()>) {
+    let (tx, mut rx) = mpsc::unbounded_channel::<JournaledTask>();
     let (tx_outside_auction_d, mut rx_outside_auction_d) = mpsc::unbounded_channel::<DBCommandsAuction>();
-    let (tx_dql, mut rx_dql) = mpsc::unbounded_channel::<DBCommandsAuctionRoom>();
+    let (tx_dql, mut rx_dql) = mpsc::unbounded_channel::<JournaledTask>();
+    // a stable NODE_ID lets the server replay its own journal on restart
+    let node_id = std::env::var("NODE_ID").unwrap_or_else(|_| uuid::Uuid::new_v4().to_string());
+    let redis_connection = Arc::new(services::auction_room::RedisConnection::new().await);
     let state = Arc::new(
         AppState {
             rooms: Arc::new(RwLock::new(std::collections::HashMap::new())),
             database_connection: Arc::from(DatabaseAccess::new().await),
-            auction_room_database_task_executor: tx,
-            database_task_executor: tx_outside_auction_d,
-            redis_connection: Arc::new(services::auction_room::RedisConnection::new().await),
-            dlq_task_executor: tx_dql
+            auction_room_database_task_executor: JournaledQueue::new(tx, redis_connection.clone(), node_id.clone()),
+            database_task_executor: TaskQueue::new(tx_outside_auction_d),
+            redis_connection,
+            dlq_task_executor: TaskQueue::new(tx_dql),
+            node_id,
+            shutdown: Arc::new(tokio::sync::watch::channel(false).0),
+            retry_policy: Arc::new(RetryPolicies::from_env()),
         }
     ) ;
+    // the tasks left in the journal were queued before the new ones, the executor picks them once it was spawned
+    if let Err(e) = replay_journal(&state).await {
+        tracing::error!("Replay of the journal failed: {:?}", e);
+    }
+    tokio::spawn(run_journal_keeper(state.clone()));
+    let state_ = state.clone();
+    tokio::spawn(async move {
+        // rooms whose timer was lost before this start were resolved, the due timers were left for the scheduler
+        if let Err(e) = recover_in_flight_auctions(&state_).await {
+            tracing::error!("Recovery of the rooms in progress failed: {:?}", e);
+        }
+    });
+    let state_ = state.clone();
+    tokio::spawn(async move {
+        let state = state_;
+        loop {
+            // due timers were kept in the schedule while the scheduler was restarting, so none of them were lost
+            if let Err(e) = run_timer_scheduler(&state).await {
+                tracing::error!("Timer scheduler failed: {:?}", e);
+            }
+            if state.is_shutting_down() {
+                break;
+            }
+            tracing::warn!("🔁 Restarting timer scheduler in 2 seconds...");
+            tokio::time::sleep(Duration::from_secs(2)).await;
+        }
+    });
+
     let redis_url = std::env::var("REDIS_URL").unwrap();
     let state_ = state.clone();
     tokio::spawn(async move {
         let state = state_;
         loop {
-            if let Err(e) = listen_for_expiry_events(&format!("redis://{}:6379/", redis_url), &state).await {
-                tracing::error!("Redis expiry listener failed: {:?}", e);
+            if let Err(e) = listen_for_room_events(&format!("redis://{}:6379/", redis_url), &state).await {
+                tracing::error!("Redis room events listener failed: {:?}", e);
             }
-            tracing::warn!("🔁 Restarting listener in 2 seconds...");
+            tracing::warn!("🔁 Restarting room events listener in 2 seconds...");
             tokio::time::sleep(Duration::from_secs(2)).await;
         }
     });
@@ -103,7 +149,7 @@ async fn routes() -> Router {
     tokio::spawn(async move {
        background_task_executor_outside_auction_db_calls(state_, rx_outside_auction_d).await ; 
     });
-    listening_to_retries(state.clone()).await ;
+    let retries = listening_to_retries(state.clone()).await ;
     // Configure CORS
     let cors = CorsLayer::new()
         .allow_origin([
@@ -151,9 +197,9 @@ async fn routes() -> Router {
         }))
         // .route("/retry-tasks_test", post(retry_tasks_test)) used for testing Dead Letter Queue Logic
         .layer(http_trace_layer())
-        .with_state(state);
+        .with_state(state.clone());
 
-    app
+    (app, state, retries)
 
 }
 
@@ -1,6 +1,7 @@
 use axum::{
     extract::Request,
-    http::StatusCode,
+    http::{HeaderMap, StatusCode},
+    http::header::{AUTHORIZATION, SEC_WEBSOCKET_PROTOCOL},
     middleware::Next,
     response::Response,
 };
@@ -25,30 +26,68 @@ pub async fn auth_check(mut req: Request, next: Next) -> Response {
     };
     tracing::info!("Token is {}", token);
     // Decode and verify JWT
+    let Some(decoded) = decode_token(token) else {
+        return unauthorized("Token has expired or is invalid");
+    };
+
+    // Attach claims to request extensions
+    req.extensions_mut().insert(decoded);
+
+    // Continue with the request
+    next.run(req).await
+}
+
+pub fn decode_token(token: &str) -> Option<Claims> {
     let secret_key = std::env::var("JWT_SECRET").unwrap();
     let key = DecodingKey::from_secret(secret_key.as_ref());
     let validation = Validation::new(Algorithm::HS256);
 
-    let decoded = match decode::<Claims>(token, &key, &validation) {
-        Ok(data) => data.claims,
+    match decode::<Claims>(token, &key, &validation) {
+        Ok(data) => Some(data.claims),
         Err(_) => {
             tracing::error!("Token expired or invalid secret key");
-            return unauthorized("Token has expired or is invalid");
+            None
         },
-    };
-
-    // Attach claims to request extensions
-    req.extensions_mut().insert(decoded);
+    }
+}
 
-    // Continue with the request
-    next.run(req).await
+/*
+    browsers cannot set the Authorization header on a websocket, so the token can also come as a subprotocol
+    (new WebSocket(url, ["bearer", token])) or as the token query param. header was checked first.
+*/
+pub fn websocket_token(headers: &HeaderMap, query_token: Option<&str>) -> Option<String> {
+    if let Some(token) = headers.get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer ")) {
+        return Some(token.to_string());
+    }
+    if let Some(protocols) = headers.get(SEC_WEBSOCKET_PROTOCOL).and_then(|value| value.to_str().ok()) {
+        let protocols = protocols.split(',').map(|protocol| protocol.trim()).collect::<Vec<&str>>() ;
+        let token = protocols.iter()
+            .position(|protocol| protocol.eq_ignore_ascii_case(WEBSOCKET_AUTH_PROTOCOL))
+            .and_then(|index| protocols.get(index + 1)) ;
+        if let Some(token) = token {
+            return Some(token.to_string());
+        }
+    }
+    query_token.map(|token| token.to_string())
 }
 
+pub const WEBSOCKET_AUTH_PROTOCOL: &str = "bearer";
+
 // Helper function for consistent unauthorized responses
-fn unauthorized(msg: &str) -> Response {
+pub fn unauthorized(msg: &str) -> Response {
+    error_response(StatusCode::UNAUTHORIZED, msg)
+}
+
+pub fn forbidden(msg: &str) -> Response {
+    error_response(StatusCode::FORBIDDEN, msg)
+}
+
+fn error_response(status: StatusCode, msg: &str) -> Response {
     let body = json!({ "message": msg });
     Response::builder()
-        .status(StatusCode::UNAUTHORIZED)
+        .status(status)
         .header("Content-Type", "application/json")
         .body(serde_json::to_string(&body).unwrap().into())
         .unwrap()
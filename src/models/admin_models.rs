@@ -1,7 +1,85 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use crate::models::background_db_tasks::DeadLetterTask;
 
 #[derive(Deserialize, Debug)]
 pub struct AuctionCompletedTasksExecutionModel {
     pub room_id: String,
     pub password: String,
-}
\ No newline at end of file
+}
+#[derive(Deserialize, Debug)]
+pub struct RehydrateRoomModel {
+    pub room_id: String,
+    pub password: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DeadLetterListModel {
+    pub password: String,
+    pub task_type: Option<String>,
+    pub room_id: Option<String>,
+    pub status: Option<String>, // pending by default
+    pub page: Option<i64>, // starts from 1
+    pub per_page: Option<i64>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DeadLetterInspectModel {
+    pub id: i64,
+    pub password: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DeadLetterReplayModel {
+    pub ids: Vec<i64>,
+    pub password: String,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum DeadLetterResolution {
+    Resolved, // the admin fixed the data by hand
+    Discarded,
+}
+
+impl DeadLetterResolution {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeadLetterResolution::Resolved => "resolved",
+            DeadLetterResolution::Discarded => "discarded",
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DeadLetterCloseModel {
+    pub ids: Vec<i64>,
+    pub status: DeadLetterResolution,
+    pub password: String,
+}
+
+#[derive(Serialize)]
+pub struct DeadLetterPage {
+    pub tasks: Vec<DeadLetterTask>,
+    pub page: i64,
+    pub per_page: i64,
+    pub total: i64,
+}
+
+#[derive(Serialize)]
+pub struct DeadLetterReplayFailure {
+    pub id: i64,
+    pub error: String,
+}
+
+#[derive(Serialize, Default)]
+pub struct DeadLetterReplay {
+    pub replayed: Vec<i64>,
+    pub skipped: Vec<i64>, // not found or not pending
+    pub failed: Vec<DeadLetterReplayFailure>, // left pending
+}
+
+#[derive(Serialize)]
+pub struct DeadLetterClose {
+    pub updated: Vec<i64>,
+    pub skipped: Vec<i64>,
+}
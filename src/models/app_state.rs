@@ -1,22 +1,176 @@
 use std::collections::HashMap;
 use std::sync::{Arc};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::sync::mpsc::error::SendError;
 use tokio::sync::RwLock;
-use axum::extract::ws::Message;
 use redis_derive::{FromRedisValue, ToRedisArgs};
 use serde::{Deserialize, Serialize};
 use tokio::sync::broadcast;
-use crate::models::background_db_tasks::{DBCommandsAuction, DBCommandsAuctionRoom};
+use crate::models::background_db_tasks::{DBCommandsAuction, DBCommandsAuctionRoom, JournaledTask};
+use crate::models::retry_policy::RetryPolicies;
+use crate::models::room_models::RoomHandle;
 use crate::services::auction::DatabaseAccess;
 use crate::services::auction_room::RedisConnection;
 
 #[derive(Debug,Clone)]
 pub struct AppState {
-    pub rooms: Arc<RwLock<HashMap<String, Vec<(i32, tokio::sync::mpsc::UnboundedSender<Message>)>>>>, // i32 is participant id
+    pub rooms: Arc<RwLock<HashMap<String, RoomHandle>>>, // room actors running on this server
     pub database_connection: Arc<DatabaseAccess>,
-    pub auction_room_database_task_executor: tokio::sync::mpsc::UnboundedSender<DBCommandsAuctionRoom>,
-    pub database_task_executor: tokio::sync::mpsc::UnboundedSender<DBCommandsAuction>,
+    pub auction_room_database_task_executor: JournaledQueue,
+    pub database_task_executor: TaskQueue<DBCommandsAuction>,
     pub redis_connection: Arc<RedisConnection>,
-    pub dlq_task_executor: tokio::sync::mpsc::UnboundedSender<DBCommandsAuctionRoom>,
+    pub dlq_task_executor: TaskQueue<JournaledTask>, // the journal entry was removed after the task was saved
+    pub node_id: String, // identifies this server in the presence of the rooms
+    pub shutdown: Arc<tokio::sync::watch::Sender<bool>>, // true once the server started shutting down
+    pub retry_policy: Arc<RetryPolicies>,
+}
+
+impl AppState {
+    pub fn is_shutting_down(&self) -> bool {
+        *self.shutdown.borrow()
+    }
+}
+
+/*
+    sender of a background tasks channel, along with the count of the tasks which were sent but not executed yet.
+    the executor marks every task as done after executing it, such that the shutdown can wait for the pending tasks.
+*/
+pub struct TaskQueue<T> {
+    sender: tokio::sync::mpsc::UnboundedSender<T>,
+    pending: Arc<AtomicUsize>,
+}
+
+impl<T> std::fmt::Debug for TaskQueue<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TaskQueue").field("pending", &self.pending()).finish()
+    }
+}
+
+impl<T> Clone for TaskQueue<T> {
+    fn clone(&self) -> Self {
+        Self { sender: self.sender.clone(), pending: self.pending.clone() }
+    }
+}
+
+impl<T> TaskQueue<T> {
+    pub fn new(sender: tokio::sync::mpsc::UnboundedSender<T>) -> Self {
+        Self { sender, pending: Arc::new(AtomicUsize::new(0)) }
+    }
+
+    pub fn send(&self, task: T) -> Result<(), SendError<T>> {
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        self.sender.send(task).inspect_err(|_| {
+            self.pending.fetch_sub(1, Ordering::SeqCst);
+        })
+    }
+
+    pub fn done(&self) {
+        self.pending.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    pub fn pending(&self) -> usize {
+        self.pending.load(Ordering::SeqCst)
+    }
+
+    // false if the tasks were still pending after the timeout
+    pub async fn drain(&self, timeout: std::time::Duration) -> bool {
+        let deadline = tokio::time::Instant::now() + timeout;
+        while self.pending() > 0 {
+            if tokio::time::Instant::now() >= deadline {
+                return false;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        true
+    }
+}
+
+const JOURNAL_APPEND_ATTEMPTS: u32 = 3;
+const JOURNAL_APPEND_BACKOFF: std::time::Duration = std::time::Duration::from_millis(50);
+
+// the task which was not queued, along with the reason
+pub enum JournalSendError {
+    NotJournaled(DBCommandsAuctionRoom), // the journal was not reachable
+    Closed(DBCommandsAuctionRoom), // the executor was stopped
+}
+
+impl std::fmt::Display for JournalSendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JournalSendError::NotJournaled(command) => write!(f, "unable to journal the {} task", command.task_type()),
+            JournalSendError::Closed(command) => write!(f, "background tasks channel was closed for the {} task", command.task_type()),
+        }
+    }
+}
+
+impl std::fmt::Debug for JournalSendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Display::fmt(self, f)
+    }
+}
+
+/*
+    the auction room tasks were appended to the journal of this server before they were queued, and the executor
+    removes them from the journal once they were executed or moved to the retries, see services/db_journal.rs
+*/
+#[derive(Debug, Clone)]
+pub struct JournaledQueue {
+    tasks: TaskQueue<JournaledTask>,
+    redis_connection: Arc<RedisConnection>,
+    node_id: String,
+}
+
+impl JournaledQueue {
+    pub fn new(sender: tokio::sync::mpsc::UnboundedSender<JournaledTask>, redis_connection: Arc<RedisConnection>, node_id: String) -> Self {
+        Self { tasks: TaskQueue::new(sender), redis_connection, node_id }
+    }
+
+    /*
+        a task which was not journaled was not queued either, the caller gets it back and stops, such that a sale
+        which was not durable was never broadcasted. the append was tried a few times before giving up.
+    */
+    pub async fn send(&self, command: DBCommandsAuctionRoom) -> Result<(), JournalSendError> {
+        let mut attempt = 1;
+        let entry_id = loop {
+            match self.redis_connection.append_to_journal(&self.node_id, &command).await {
+                Ok(entry_id) => break entry_id,
+                Err(err) => {
+                    tracing::error!("unable to journal the auction room task, attempt {} {}", attempt, err);
+                    metrics::counter!("db_journal_write_failures_total", "task_type" => command.task_type()).increment(1);
+                    if attempt == JOURNAL_APPEND_ATTEMPTS {
+                        return Err(JournalSendError::NotJournaled(command));
+                    }
+                    tokio::time::sleep(JOURNAL_APPEND_BACKOFF * attempt).await;
+                    attempt += 1;
+                }
+            }
+        };
+        self.tasks.send(JournaledTask { entry_id: Some(entry_id), command }).map_err(|SendError(task)| JournalSendError::Closed(task.command))
+    }
+
+    // queues a task which was already in the journal of this server
+    pub fn requeue(&self, entry_id: String, command: DBCommandsAuctionRoom) -> Result<(), SendError<DBCommandsAuctionRoom>> {
+        self.tasks.send(JournaledTask { entry_id: Some(entry_id), command }).map_err(|SendError(task)| SendError(task.command))
+    }
+
+    pub async fn done(&self, entry_id: Option<String>) {
+        if let Some(entry_id) = entry_id {
+            self.forget(&entry_id).await;
+        }
+        self.tasks.done();
+    }
+
+    // removes the entry alone, used once a dead lettered task was saved
+    pub async fn forget(&self, entry_id: &str) {
+        // an entry which was not removed was executed again on the next start
+        if let Err(err) = self.redis_connection.remove_from_journal(&self.node_id, entry_id).await {
+            tracing::error!("unable to remove the entry {} from the journal {}", entry_id, err);
+        }
+    }
+
+    pub fn tasks(&self) -> &TaskQueue<JournaledTask> {
+        &self.tasks
+    }
 }
 
 #[derive(Debug,Clone, FromRedisValue, ToRedisArgs, Serialize, Deserialize, sqlx::FromRow)]
@@ -16,13 +16,13 @@ pub struct AuctionParticipant {
 } // room_id:participant_id:meta is the key to get the participant from redis
 
 impl AuctionParticipant {
-    pub fn new(id: i32, team_name: String, remaining_rtms: i16) -> Self {
+    pub fn new(id: i32, team_name: String, rules: &RoomRules) -> Self {
         Self {
             id,
             team_name,
-            balance: 100.0,
+            balance: rules.purse,
             total_players_brought: 0,
-            remaining_rtms,
+            remaining_rtms: rules.rtms_per_team,
             is_unmuted: true,
             foreign_players_brought: 0
         }
@@ -60,6 +60,48 @@ impl Bid {
     }
 }
 
+// amounts were compared in hundredths in the redis scripts, as the f32 amount read back from redis may not be equal to the one written
+pub fn amount_in_hundredths(amount: f32) -> i64 {
+    (amount as f64 * 100.0).round() as i64
+}
+
+
+// reasons returned by the accept bid script, when a bid was not taken
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BidRejection {
+    BiddingClosed,      // timer key was not there, the player was being sold or RTM was taking place
+    Skipped,            // participant skipped the player
+    NoCurrentBid,
+    PlayerChanged,      // the next player came up after the bid was read
+    Outbid,             // some other bid was taken after the bid was read
+    AlreadyHighestBidder,
+}
+
+impl BidRejection {
+    pub fn from_reason(reason: &str) -> Option<BidRejection> {
+        match reason {
+            "bidding-closed" => Some(BidRejection::BiddingClosed),
+            "skipped" => Some(BidRejection::Skipped),
+            "no-current-bid" => Some(BidRejection::NoCurrentBid),
+            "player-changed" => Some(BidRejection::PlayerChanged),
+            "outbid" => Some(BidRejection::Outbid),
+            "already-highest-bidder" => Some(BidRejection::AlreadyHighestBidder),
+            _ => None
+        }
+    }
+
+    pub fn message(&self) -> &'static str {
+        match self {
+            BidRejection::BiddingClosed => "Bid is Invalid, Bidding was Closed",
+            BidRejection::Skipped => "Bid is Invalid, you skipped the player",
+            BidRejection::NoCurrentBid => "Bid is Invalid, No Player was in the Auction",
+            BidRejection::PlayerChanged => "Bid is Invalid, Player was changed",
+            BidRejection::Outbid => "Bid is Invalid, Some one else bid before you",
+            BidRejection::AlreadyHighestBidder => "You are already the highest bidder",
+        }
+    }
+}
+
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RoomMeta {
@@ -68,6 +110,138 @@ pub struct RoomMeta {
 } // meta data of the room -> room_id:meta is the key to get the meta data from redis
 
 
+/// Rules of a room, chosen by the creator at create-room time. They are stored in the `rules` column of
+/// the rooms table and copied into redis (room_id:rules) when the room is opened for the auction.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RoomRules {
+    pub purse: f32, // starting balance of every team in crores
+    pub max_squad_size: u8, // a team cannot buy more than these many players
+    pub min_squad_size: u8, // every team must be able to complete these many players
+    pub max_foreign_players: u8,
+    pub rtms_per_team: i16,
+    pub bid_increments: Vec<BidIncrementSlab>, // ladder of increments, ordered by the upper limit of each slab
+}
+
+/// One step of the increment ladder, while the current bid is below `below` the bid increases by `increment`.
+/// The last slab of the ladder should not have an upper limit.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BidIncrementSlab {
+    pub below: Option<f32>,
+    pub increment: f32,
+}
+
+impl Default for RoomRules {
+    fn default() -> Self {
+        Self {
+            purse: 100.0,
+            max_squad_size: 25,
+            min_squad_size: 15,
+            max_foreign_players: 8,
+            rtms_per_team: 3,
+            bid_increments: vec![
+                BidIncrementSlab { below: Some(1.0), increment: 0.05 },
+                BidIncrementSlab { below: Some(10.0), increment: 0.10 },
+                BidIncrementSlab { below: None, increment: 0.25 },
+            ],
+        }
+    }
+}
+
+impl RoomRules {
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.purse.is_finite() || self.purse <= 0.0 {
+            return Err(String::from("purse should be greater than 0"));
+        }
+        if self.max_squad_size == 0 {
+            return Err(String::from("max_squad_size should be greater than 0"));
+        }
+        if self.min_squad_size > self.max_squad_size {
+            return Err(String::from("min_squad_size cannot be greater than max_squad_size"));
+        }
+        if self.max_foreign_players > self.max_squad_size {
+            return Err(String::from("max_foreign_players cannot be greater than max_squad_size"));
+        }
+        if self.rtms_per_team < 0 {
+            return Err(String::from("rtms_per_team cannot be negative"));
+        }
+        // every team should be able to complete the minimum squad with base price (0.30cr) players
+        if self.purse < self.min_squad_size as f32 * 0.30 {
+            return Err(String::from("purse is not enough to complete the min_squad_size"));
+        }
+        self.validate_bid_increments()
+    }
+
+    fn validate_bid_increments(&self) -> Result<(), String> {
+        let Some(last_slab) = self.bid_increments.last() else {
+            return Err(String::from("bid_increments should have at least one slab"));
+        };
+        if last_slab.below.is_some() {
+            return Err(String::from("last slab of bid_increments should not have an upper limit"));
+        }
+        let mut previous_limit = 0.0;
+        for (index, slab) in self.bid_increments.iter().enumerate() {
+            if !slab.increment.is_finite() || slab.increment <= 0.0 {
+                return Err(String::from("every increment in bid_increments should be greater than 0"));
+            }
+            if index == self.bid_increments.len() - 1 {
+                break;
+            }
+            match slab.below {
+                Some(below) if below.is_finite() && below > previous_limit => previous_limit = below,
+                Some(_) => return Err(String::from("limits of bid_increments should be in increasing order")),
+                None => return Err(String::from("only the last slab of bid_increments can be without an upper limit")),
+            }
+        }
+        Ok(())
+    }
+
+    /// Upper limits (exclusive) of the three strict mode segments, the thirds of the minimum squad rounded up.
+    pub fn strict_segments(&self) -> (u8, u8, u8) {
+        let min_squad_size = self.min_squad_size as u16;
+        (
+            min_squad_size.div_ceil(3) as u8,
+            (min_squad_size * 2).div_ceil(3) as u8,
+            self.min_squad_size,
+        )
+    }
+
+    /// increment that has to be added to the current bid amount
+    pub fn bid_increment(&self, current_bid_amount: f32) -> f32 {
+        self.bid_increments
+            .iter()
+            .find(|slab| slab.below.is_none_or(|below| current_bid_amount < below))
+            .or(self.bid_increments.last())
+            .map(|slab| slab.increment)
+            .unwrap_or_default()
+    }
+
+    /// the amount of the next bid, the first bid of a player is always the base price
+    pub fn next_bid_amount(&self, current_bid_amount: f32, base_price: f32) -> f32 {
+        if current_bid_amount == 0.0 {
+            return base_price;
+        }
+        ((current_bid_amount + self.bid_increment(current_bid_amount)) * 100.0).round() / 100.0
+    }
+
+    /// true if the amount was one of the steps of the ladder after the current bid, a jump bid can skip steps
+    /// but cannot land in between them
+    pub fn is_on_ladder(&self, current_bid_amount: f32, base_price: f32, amount: f32) -> bool {
+        let amount = amount_in_hundredths(amount);
+        let mut step = self.next_bid_amount(current_bid_amount, base_price);
+        while amount_in_hundredths(step) < amount {
+            let next_step = self.next_bid_amount(step, base_price);
+            // an increment lost in the rounding would never reach the amount
+            if amount_in_hundredths(next_step) <= amount_in_hundredths(step) {
+                return false;
+            }
+            step = next_step;
+        }
+        amount_in_hundredths(step) == amount
+    }
+}
+
+
 
 
 #[derive(Debug,Clone, Serialize, Deserialize)]
@@ -102,4 +276,169 @@ pub struct ParticipantAudio {
 pub struct ChatMessage {
     pub team_name: String,
     pub message: String
-}
\ No newline at end of file
+}
+
+// absolute end of the running timer, such that clients can show the countdown without drifting
+#[derive(Debug,Clone, Serialize, Deserialize)]
+pub struct TimerDeadline {
+    pub room_id: String,
+    pub phase: String, // bidding or rtm
+    pub deadline: i64, // server epoch millis
+    pub server_time: i64, // server epoch millis when the timer was set
+}
+
+// a timer as it was set by the redis scripts, epoch millis of the redis clock which decides the expiry
+#[derive(Debug,Clone, Copy)]
+pub struct TimerWindow {
+    pub server_time: i64,
+    pub deadline: i64,
+}
+
+// due timer claimed by a server, previous_claim was the claim which was not completed within its lease
+#[derive(Debug,Clone)]
+pub struct TimerClaim {
+    pub timer_key: String,
+    pub claim: String, // node id of the claiming server along with the claimed time
+    pub previous_claim: Option<String>,
+}
+
+// reply for time-sync, client_time was the time sent by the client, used for finding the clock skew
+#[derive(Debug,Clone, Serialize, Deserialize)]
+pub struct TimeSync {
+    pub client_time: Option<i64>,
+    pub server_time: i64,
+}
+
+// whole state of the room, sent on every join and on demand, such that a reconnecting client can restore the auction
+#[derive(Debug,Clone, Serialize, Deserialize)]
+pub struct RoomSnapshot {
+    pub room_creator_id: i32,
+    pub pause: bool,
+    pub strict_mode: bool,
+    pub current_player: Option<Player>,
+    pub current_bid: Option<Bid>,
+    pub highest_bidder: Option<String>, // team name of the highest bidder
+    pub bid_time_remaining: Option<i64>, // seconds, none when bidding was not going on
+    pub rtm_in_progress: bool,
+    pub rtm_time_remaining: Option<i64>, // seconds, none when no rtm was going on
+    pub skipped: Vec<i32>, // participants who skipped the current player
+    pub skipped_pool: Vec<i32>, // participants who want to skip the current pool
+    pub participants: Vec<AuctionParticipant>,
+    pub bots: Vec<i32>,
+    pub autopilot: Vec<i32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slab(below: Option<f32>, increment: f32) -> BidIncrementSlab {
+        BidIncrementSlab { below, increment }
+    }
+
+    #[test]
+    fn first_bid_is_the_base_price() {
+        let rules = RoomRules::default();
+        assert_eq!(rules.next_bid_amount(0.0, 0.3), 0.3);
+        assert_eq!(rules.next_bid_amount(0.0, 2.0), 2.0);
+    }
+
+    #[test]
+    fn next_bid_follows_the_ladder() {
+        let rules = RoomRules::default();
+        assert_eq!(rules.next_bid_amount(0.3, 0.3), 0.35);
+        assert_eq!(rules.next_bid_amount(0.95, 0.3), 1.0);
+        // exactly at the limit of a slab, the increment of the next slab was taken
+        assert_eq!(rules.next_bid_amount(1.0, 0.3), 1.1);
+        assert_eq!(rules.next_bid_amount(9.9, 0.3), 10.0);
+        assert_eq!(rules.next_bid_amount(10.0, 0.3), 10.25);
+    }
+
+    #[test]
+    fn last_slab_has_no_upper_limit() {
+        let rules = RoomRules::default();
+        assert_eq!(rules.bid_increment(25.0), 0.25);
+        assert_eq!(rules.bid_increment(1000.0), 0.25);
+        assert_eq!(rules.next_bid_amount(99.75, 0.3), 100.0);
+    }
+
+    #[test]
+    fn custom_ladder_was_used() {
+        let rules = RoomRules {
+            bid_increments: vec![slab(Some(2.0), 0.2), slab(None, 0.5)],
+            ..RoomRules::default()
+        };
+        assert_eq!(rules.next_bid_amount(1.8, 0.2), 2.0);
+        assert_eq!(rules.next_bid_amount(2.0, 0.2), 2.5);
+    }
+
+    #[test]
+    fn jump_bids_were_on_the_ladder_steps() {
+        let rules = RoomRules {
+            bid_increments: vec![slab(Some(2.0), 0.2), slab(None, 0.5)],
+            ..RoomRules::default()
+        };
+        assert!(rules.is_on_ladder(0.0, 0.5, 0.5));
+        assert!(rules.is_on_ladder(0.5, 0.5, 0.7));
+        assert!(rules.is_on_ladder(0.5, 0.5, 1.5));
+        // crossing a slab, the steps continue with the increment of the next slab
+        assert!(rules.is_on_ladder(1.9, 0.5, 2.1));
+        assert!(rules.is_on_ladder(1.9, 0.5, 3.1));
+        assert!(!rules.is_on_ladder(0.5, 0.5, 1.07));
+        assert!(!rules.is_on_ladder(0.5, 0.5, 0.6));
+        assert!(!rules.is_on_ladder(1.9, 0.5, 2.5));
+    }
+
+    #[test]
+    fn default_rules_are_valid() {
+        assert_eq!(RoomRules::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn invalid_rules_were_rejected() {
+        let invalid = [
+            RoomRules { purse: 0.0, ..RoomRules::default() },
+            RoomRules { purse: f32::NAN, ..RoomRules::default() },
+            RoomRules { max_squad_size: 0, min_squad_size: 0, max_foreign_players: 0, ..RoomRules::default() },
+            RoomRules { min_squad_size: 26, ..RoomRules::default() },
+            RoomRules { max_foreign_players: 26, ..RoomRules::default() },
+            RoomRules { rtms_per_team: -1, ..RoomRules::default() },
+            // 15 players of 0.30cr were not affordable
+            RoomRules { purse: 4.0, ..RoomRules::default() },
+        ];
+        for rules in invalid {
+            assert!(rules.validate().is_err(), "rules {:?} should be invalid", rules);
+        }
+    }
+
+    #[test]
+    fn invalid_ladders_were_rejected() {
+        let ladders = [
+            (vec![], "bid_increments should have at least one slab"),
+            (vec![slab(Some(1.0), 0.05)], "last slab of bid_increments should not have an upper limit"),
+            (vec![slab(Some(1.0), 0.0), slab(None, 0.25)], "every increment in bid_increments should be greater than 0"),
+            (vec![slab(Some(1.0), 0.05), slab(None, -0.25)], "every increment in bid_increments should be greater than 0"),
+            (vec![slab(Some(10.0), 0.05), slab(Some(1.0), 0.1), slab(None, 0.25)], "limits of bid_increments should be in increasing order"),
+            (vec![slab(Some(1.0), 0.05), slab(Some(1.0), 0.1), slab(None, 0.25)], "limits of bid_increments should be in increasing order"),
+            (vec![slab(Some(0.0), 0.05), slab(None, 0.25)], "limits of bid_increments should be in increasing order"),
+            (vec![slab(None, 0.05), slab(None, 0.25)], "only the last slab of bid_increments can be without an upper limit"),
+        ];
+        for (bid_increments, message) in ladders {
+            let rules = RoomRules { bid_increments, ..RoomRules::default() };
+            assert_eq!(rules.validate(), Err(String::from(message)));
+        }
+    }
+
+    #[test]
+    fn strict_segments_were_the_thirds_of_the_min_squad() {
+        assert_eq!(RoomRules::default().strict_segments(), (5, 10, 15));
+        assert_eq!(RoomRules { min_squad_size: 11, ..RoomRules::default() }.strict_segments(), (4, 8, 11));
+    }
+
+    #[test]
+    fn amounts_were_compared_in_hundredths() {
+        assert_eq!(amount_in_hundredths(1.05), 105);
+        assert_eq!(amount_in_hundredths(0.1 + 0.2), 30);
+        assert_eq!(amount_in_hundredths(99.75), 9975);
+    }
+}
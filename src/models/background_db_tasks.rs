@@ -5,6 +5,10 @@ pub trait AuctionRoomRetryTasks {}
 #[derive(Serialize, Deserialize, Clone, sqlx::FromRow)]
 pub struct ParticipantId {
     pub id: i32,
+    #[serde(default)] // tasks enqueued by the older servers were not having a key
+    pub idempotency_key: String,
+    #[serde(default)]
+    pub enqueued_at: i64, // milliseconds, when the task was first enqueued
     pub retry_count: u8,
     pub last_error: String
 }
@@ -15,6 +19,8 @@ impl AuctionRoomRetryTasks for UnSoldPlayer {}
 impl AuctionRoomRetryTasks for BalanceUpdate {}
 impl AuctionRoomRetryTasks for RoomStatus {}
 impl AuctionRoomRetryTasks for CompletedRoom {}
+impl AuctionRoomRetryTasks for SaleCompleted {}
+impl AuctionRoomRetryTasks for SkipVoted {}
 
 #[derive(Serialize, Deserialize,sqlx::FromRow, Clone)]
 pub struct SoldPlayer {
@@ -22,6 +28,10 @@ pub struct SoldPlayer {
     pub player_id: i32,
     pub participant_id: i32,
     pub bid_amount: f32,
+    #[serde(default)]
+    pub idempotency_key: String,
+    #[serde(default)]
+    pub enqueued_at: i64,
     pub retry_count: u8,
     pub last_error: String
 }
@@ -30,6 +40,10 @@ pub struct SoldPlayer {
 pub struct UnSoldPlayer {
     pub player_id: i32,
     pub room_id: String,
+    #[serde(default)]
+    pub idempotency_key: String,
+    #[serde(default)]
+    pub enqueued_at: i64,
     pub retry_count: u8,
     pub last_error: String
 }
@@ -38,6 +52,10 @@ pub struct UnSoldPlayer {
 pub struct BalanceUpdate {
     pub participant_id: i32,
     pub remaining_balance: f32,
+    #[serde(default)]
+    pub idempotency_key: String,
+    #[serde(default)]
+    pub enqueued_at: i64,
     pub retry_count: u8,
     pub last_error: String
 }
@@ -46,13 +64,68 @@ pub struct BalanceUpdate {
 pub struct RoomStatus {
     pub room_id: String,
     pub status: String,
+    #[serde(default)]
+    pub idempotency_key: String,
+    #[serde(default)]
+    pub enqueued_at: i64,
     pub retry_count: u8,
     pub last_error: String
 }
 
+// a sold player along with the balance and the rtm of the participant, written and retried as a whole
+#[derive(Serialize, Deserialize,sqlx::FromRow, Clone)]
+pub struct SaleCompleted {
+    pub room_id: String,
+    pub player_id: i32,
+    pub participant_id: i32,
+    pub bid_amount: f32,
+    pub remaining_balance: f32, // balance in redis after the sale, postgres subtracts the bid_amount instead
+    pub decrement_rtm: bool, // the player was brought using an rtm
+    #[serde(default)]
+    pub idempotency_key: String,
+    #[serde(default)]
+    pub enqueued_at: i64,
+    pub retry_count: u8,
+    pub last_error: String
+}
+
+// a participant skipped the player or the pool, kept in postgres only to rebuild the skip state of the room
+#[derive(Serialize, Deserialize,sqlx::FromRow, Clone)]
+pub struct SkipVoted {
+    pub room_id: String,
+    pub participant_id: i32,
+    pub kind: String, // player or pool
+    pub target: i32, // player id or pool no
+    #[serde(default)]
+    pub idempotency_key: String,
+    #[serde(default)]
+    pub enqueued_at: i64,
+    pub retry_count: u8,
+    pub last_error: String
+}
+
+impl SkipVoted {
+    pub fn new(room_id: &str, participant_id: i32, kind: &str, target: i32) -> Self {
+        Self {
+            room_id: room_id.to_string(),
+            participant_id,
+            kind: kind.to_string(),
+            target,
+            idempotency_key: format!("skip:{}:{}:{}:{}", room_id, participant_id, kind, target),
+            enqueued_at: chrono::Utc::now().timestamp_millis(),
+            retry_count: 0,
+            last_error: String::from("")
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize,sqlx::FromRow, Clone)]
 pub struct CompletedRoom {
     pub room_id: String,
+    #[serde(default)]
+    pub idempotency_key: String,
+    #[serde(default)]
+    pub enqueued_at: i64,
     pub retry_count: u8,
     pub last_error: String
 }
@@ -66,25 +139,234 @@ pub enum DBCommandsAuctionRoom { //inside auction room db tasks will be executed
     UpdateRoomStatus(RoomStatus),
     CompletedRoomSoldPlayers(CompletedRoom), // it will add and remove the sold players
     CompletedRoomUnsoldPlayers(CompletedRoom), // it will add and remove the unsold players
-    CompletedRoomCompletedAt(CompletedRoom)
+    CompletedRoomCompletedAt(CompletedRoom),
+    SaleCompleted(SaleCompleted), // PlayerSold, BalanceUpdate and UpdateRemainingRTMS were only sent by the older servers
+    SkipVoted(SkipVoted)
 }
 
+impl DBCommandsAuctionRoom {
+    // generated when the task was enqueued, and kept across the retries
+    pub fn idempotency_key(&self) -> &str {
+        match self {
+            DBCommandsAuctionRoom::UpdateRemainingRTMS(participant) => &participant.idempotency_key,
+            DBCommandsAuctionRoom::PlayerSold(player_sold) => &player_sold.idempotency_key,
+            DBCommandsAuctionRoom::PlayerUnSold(player_un_sold) => &player_un_sold.idempotency_key,
+            DBCommandsAuctionRoom::BalanceUpdate(balance_update) => &balance_update.idempotency_key,
+            DBCommandsAuctionRoom::UpdateRoomStatus(room_status) => &room_status.idempotency_key,
+            DBCommandsAuctionRoom::CompletedRoomSoldPlayers(completed_room)
+            | DBCommandsAuctionRoom::CompletedRoomUnsoldPlayers(completed_room)
+            | DBCommandsAuctionRoom::CompletedRoomCompletedAt(completed_room) => &completed_room.idempotency_key,
+            DBCommandsAuctionRoom::SaleCompleted(sale) => &sale.idempotency_key,
+            DBCommandsAuctionRoom::SkipVoted(skip) => &skip.idempotency_key,
+        }
+    }
+
+    pub fn retry_count(&self) -> u8 {
+        match self {
+            DBCommandsAuctionRoom::UpdateRemainingRTMS(participant) => participant.retry_count,
+            DBCommandsAuctionRoom::PlayerSold(player_sold) => player_sold.retry_count,
+            DBCommandsAuctionRoom::PlayerUnSold(player_un_sold) => player_un_sold.retry_count,
+            DBCommandsAuctionRoom::BalanceUpdate(balance_update) => balance_update.retry_count,
+            DBCommandsAuctionRoom::UpdateRoomStatus(room_status) => room_status.retry_count,
+            DBCommandsAuctionRoom::CompletedRoomSoldPlayers(completed_room)
+            | DBCommandsAuctionRoom::CompletedRoomUnsoldPlayers(completed_room)
+            | DBCommandsAuctionRoom::CompletedRoomCompletedAt(completed_room) => completed_room.retry_count,
+            DBCommandsAuctionRoom::SaleCompleted(sale) => sale.retry_count,
+            DBCommandsAuctionRoom::SkipVoted(skip) => skip.retry_count,
+        }
+    }
+
+    // zero for the tasks enqueued by the older servers
+    pub fn enqueued_at(&self) -> i64 {
+        match self {
+            DBCommandsAuctionRoom::UpdateRemainingRTMS(participant) => participant.enqueued_at,
+            DBCommandsAuctionRoom::PlayerSold(player_sold) => player_sold.enqueued_at,
+            DBCommandsAuctionRoom::PlayerUnSold(player_un_sold) => player_un_sold.enqueued_at,
+            DBCommandsAuctionRoom::BalanceUpdate(balance_update) => balance_update.enqueued_at,
+            DBCommandsAuctionRoom::UpdateRoomStatus(room_status) => room_status.enqueued_at,
+            DBCommandsAuctionRoom::CompletedRoomSoldPlayers(completed_room)
+            | DBCommandsAuctionRoom::CompletedRoomUnsoldPlayers(completed_room)
+            | DBCommandsAuctionRoom::CompletedRoomCompletedAt(completed_room) => completed_room.enqueued_at,
+            DBCommandsAuctionRoom::SaleCompleted(sale) => sale.enqueued_at,
+            DBCommandsAuctionRoom::SkipVoted(skip) => skip.enqueued_at,
+        }
+    }
+
+    // the completed room tasks share the key of the room completion, so the applied keys were kept per task type
+    pub fn task_type(&self) -> &'static str {
+        match self {
+            DBCommandsAuctionRoom::UpdateRemainingRTMS(_) => "UpdatingRTMs",
+            DBCommandsAuctionRoom::PlayerSold(_) => "SellingPlayer",
+            DBCommandsAuctionRoom::PlayerUnSold(_) => "UnsoldPlayer",
+            DBCommandsAuctionRoom::BalanceUpdate(_) => "UpdatingBalance",
+            DBCommandsAuctionRoom::UpdateRoomStatus(_) => "UpdatingRoomStatus",
+            DBCommandsAuctionRoom::CompletedRoomSoldPlayers(_) => "CompletedRoomSoldPlayers",
+            DBCommandsAuctionRoom::CompletedRoomUnsoldPlayers(_) => "CompletedRoomUnsoldPlayers",
+            DBCommandsAuctionRoom::CompletedRoomCompletedAt(_) => "CompletedRoomCompletedAt",
+            DBCommandsAuctionRoom::SaleCompleted(_) => "SaleCompleted",
+            DBCommandsAuctionRoom::SkipVoted(_) => "SkipVoted",
+        }
+    }
+
+    // the tasks sent by the older servers were not having the room, they were matched with the participants of the room
+    pub fn belongs_to_room(&self, room_id: &str, participant_ids: &[i32]) -> bool {
+        match self {
+            DBCommandsAuctionRoom::UpdateRemainingRTMS(participant) => participant_ids.contains(&participant.id),
+            DBCommandsAuctionRoom::BalanceUpdate(balance_update) => participant_ids.contains(&balance_update.participant_id),
+            DBCommandsAuctionRoom::PlayerSold(player_sold) => player_sold.room_id == room_id,
+            DBCommandsAuctionRoom::PlayerUnSold(player_un_sold) => player_un_sold.room_id == room_id,
+            DBCommandsAuctionRoom::UpdateRoomStatus(room_status) => room_status.room_id == room_id,
+            DBCommandsAuctionRoom::CompletedRoomSoldPlayers(completed_room)
+            | DBCommandsAuctionRoom::CompletedRoomUnsoldPlayers(completed_room)
+            | DBCommandsAuctionRoom::CompletedRoomCompletedAt(completed_room) => completed_room.room_id == room_id,
+            DBCommandsAuctionRoom::SaleCompleted(sale) => sale.room_id == room_id,
+            DBCommandsAuctionRoom::SkipVoted(skip) => skip.room_id == room_id,
+        }
+    }
+
+    // rebuilds the task which was saved to the dead_letter_tasks, the task type was the one saved by save_to_DLQ
+    pub fn from_dead_letter(task_type: &str, payload: serde_json::Value) -> Result<Self, String> {
+        let command = match task_type {
+            "UpdatingRTMs" => serde_json::from_value(payload).map(DBCommandsAuctionRoom::UpdateRemainingRTMS),
+            "SellingPlayer" => serde_json::from_value(payload).map(DBCommandsAuctionRoom::PlayerSold),
+            "UnsoldPlayer" => serde_json::from_value(payload).map(DBCommandsAuctionRoom::PlayerUnSold),
+            "UpdatingBalance" => serde_json::from_value(payload).map(DBCommandsAuctionRoom::BalanceUpdate),
+            "UpdatingRoomStatus" => serde_json::from_value(payload).map(DBCommandsAuctionRoom::UpdateRoomStatus),
+            "CompletedRoomSoldPlayers" => serde_json::from_value(payload).map(DBCommandsAuctionRoom::CompletedRoomSoldPlayers),
+            "CompletedRoomUnsoldPlayers" => serde_json::from_value(payload).map(DBCommandsAuctionRoom::CompletedRoomUnsoldPlayers),
+            "CompletedRoomCompletedAt" => serde_json::from_value(payload).map(DBCommandsAuctionRoom::CompletedRoomCompletedAt),
+            "SaleCompleted" => serde_json::from_value(payload).map(DBCommandsAuctionRoom::SaleCompleted),
+            "SkipVoted" => serde_json::from_value(payload).map(DBCommandsAuctionRoom::SkipVoted),
+            _ => return Err(format!("unknown task type {}", task_type)),
+        };
+        command.map_err(|err| format!("invalid payload for {} {}", task_type, err))
+    }
+
+    pub fn failed(&mut self, error: String) {
+        let (retry_count, last_error) = self.retry_state();
+        *retry_count += 1;
+        *last_error = error;
+    }
+
+    // a replayed task gets all of its retries again
+    pub fn reset_retries(&mut self) {
+        let (retry_count, last_error) = self.retry_state();
+        *retry_count = 0;
+        last_error.clear();
+    }
 
+    fn retry_state(&mut self) -> (&mut u8, &mut String) {
+        match self {
+            DBCommandsAuctionRoom::UpdateRemainingRTMS(participant) => (&mut participant.retry_count, &mut participant.last_error),
+            DBCommandsAuctionRoom::PlayerSold(player_sold) => (&mut player_sold.retry_count, &mut player_sold.last_error),
+            DBCommandsAuctionRoom::PlayerUnSold(player_un_sold) => (&mut player_un_sold.retry_count, &mut player_un_sold.last_error),
+            DBCommandsAuctionRoom::BalanceUpdate(balance_update) => (&mut balance_update.retry_count, &mut balance_update.last_error),
+            DBCommandsAuctionRoom::UpdateRoomStatus(room_status) => (&mut room_status.retry_count, &mut room_status.last_error),
+            DBCommandsAuctionRoom::CompletedRoomSoldPlayers(completed_room)
+            | DBCommandsAuctionRoom::CompletedRoomUnsoldPlayers(completed_room)
+            | DBCommandsAuctionRoom::CompletedRoomCompletedAt(completed_room) => (&mut completed_room.retry_count, &mut completed_room.last_error),
+            DBCommandsAuctionRoom::SaleCompleted(sale) => (&mut sale.retry_count, &mut sale.last_error),
+            DBCommandsAuctionRoom::SkipVoted(skip) => (&mut skip.retry_count, &mut skip.last_error),
+        }
+    }
+}
+
+// a task whose retries were exhausted, status was pending until the admin replays or closes it
+#[derive(Serialize, sqlx::FromRow)]
+pub struct DeadLetterTask {
+    pub id: i64,
+    pub task_type: String,
+    pub payload: serde_json::Value,
+    pub retry_count: i16,
+    pub last_error: Option<String>,
+    pub status: String,
+    pub created_at: Option<chrono::NaiveDateTime>,
+    pub resolved_at: Option<chrono::NaiveDateTime>,
+}
+
+// a task of the auction room channel along with its entry in the journal, none if the task was not journaled
+pub struct JournaledTask {
+    pub entry_id: Option<String>,
+    pub command: DBCommandsAuctionRoom
+}
+
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct UserExternalDetails {
     pub user_id: i32,
-    pub ip_address: String
+    pub ip_address: String,
+    #[serde(default)]
+    pub retry_count: u8,
+    #[serde(default)]
+    pub last_error: String
 }
 
+#[derive(Serialize, Deserialize, Clone)]
 pub struct FavoriteTeamUpdated {
     pub user_id: i32,
     pub old_favorite_team: String,
-    pub new_favorite_team: String
+    pub new_favorite_team: String,
+    #[serde(default)]
+    pub retry_count: u8,
+    #[serde(default)]
+    pub last_error: String
 }
+
+impl AuctionRoomRetryTasks for UserExternalDetails {}
+impl AuctionRoomRetryTasks for FavoriteTeamUpdated {}
+
+// tasks outside the auction room, retried from their own zset and saved to the same dead_letter_tasks
+#[derive(Serialize, Deserialize, Clone)]
 pub enum DBCommandsAuction {
     AddUserExternalDetails(UserExternalDetails),
     FavoriteTeamUpdated(FavoriteTeamUpdated)
 }
 
+impl DBCommandsAuction {
+    pub fn task_type(&self) -> &'static str {
+        match self {
+            DBCommandsAuction::AddUserExternalDetails(_) => "AddUserExternalDetails",
+            DBCommandsAuction::FavoriteTeamUpdated(_) => "FavoriteTeamUpdated",
+        }
+    }
+
+    pub fn retry_count(&self) -> u8 {
+        match self {
+            DBCommandsAuction::AddUserExternalDetails(user_details) => user_details.retry_count,
+            DBCommandsAuction::FavoriteTeamUpdated(fav_team) => fav_team.retry_count,
+        }
+    }
+
+    // none if the task type was not a task outside the auction room
+    pub fn from_dead_letter(task_type: &str, payload: serde_json::Value) -> Option<Result<Self, String>> {
+        let command = match task_type {
+            "AddUserExternalDetails" => serde_json::from_value(payload).map(DBCommandsAuction::AddUserExternalDetails),
+            "FavoriteTeamUpdated" => serde_json::from_value(payload).map(DBCommandsAuction::FavoriteTeamUpdated),
+            _ => return None,
+        };
+        Some(command.map_err(|err| format!("invalid payload for {} {}", task_type, err)))
+    }
+
+    pub fn failed(&mut self, error: String) {
+        let (retry_count, last_error) = self.retry_state();
+        *retry_count += 1;
+        *last_error = error;
+    }
+
+    pub fn reset_retries(&mut self) {
+        let (retry_count, last_error) = self.retry_state();
+        *retry_count = 0;
+        last_error.clear();
+    }
+
+    fn retry_state(&mut self) -> (&mut u8, &mut String) {
+        match self {
+            DBCommandsAuction::AddUserExternalDetails(user_details) => (&mut user_details.retry_count, &mut user_details.last_error),
+            DBCommandsAuction::FavoriteTeamUpdated(fav_team) => (&mut fav_team.retry_count, &mut fav_team.last_error),
+        }
+    }
+}
+
 use redis_derive::FromRedisValue;
 use serde::{Deserialize, Serialize};
 
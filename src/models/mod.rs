@@ -6,4 +6,6 @@ pub mod authentication_models;
 pub mod webRTC_models;
 pub mod background_db_tasks;
 pub mod others;
-pub mod admin_models;
\ No newline at end of file
+pub mod admin_models;
+pub mod protocol_models;
+pub mod retry_policy;
@@ -0,0 +1,380 @@
+use axum::extract::ws::Message;
+use serde::{Deserialize, Serialize};
+use crate::models::app_state::Player;
+use crate::models::auction_models::{AuctionParticipant, BidOutput, ChatMessage, ParticipantAudio, RoomRules, RoomSnapshot, SoldPlayer, TimeSync, TimerDeadline};
+use crate::models::room_models::Participant;
+use crate::models::webRTC_models::SignalingMessage;
+
+/*
+    Protocol versions of the websocket:
+    1 -> the old protocol, plain string commands (bid, skip, rtm-5.00, chat-hi ...) and replies were a mix of
+         plain text and json.
+    2 -> every message in both the directions was a json with a type field, ClientMessage and ServerMessage.
+    the version was negotiated on connect with the protocol query param (/ws/{room_id}/{participant_id}?protocol=2),
+    without it the connection uses version 1. string commands were still accepted in both the versions till the
+    front-end was migrated.
+*/
+pub const LEGACY_PROTOCOL_VERSION: u8 = 1;
+pub const PROTOCOL_VERSION: u8 = 2;
+
+#[derive(Deserialize, Debug)]
+pub struct WebSocketParams {
+    pub protocol: Option<u8>,
+    pub token: Option<String>, // jwt, for the clients which cannot set the Authorization header
+}
+
+pub fn negotiate_protocol_version(requested: Option<u8>) -> u8 {
+    requested.unwrap_or(LEGACY_PROTOCOL_VERSION).clamp(LEGACY_PROTOCOL_VERSION, PROTOCOL_VERSION)
+}
+
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum ClientMessage {
+    Ping,
+    Mute,
+    Unmute,
+    Start,
+    AddBot,
+    Autopilot { enabled: bool },
+    Bid { amount: Option<f32> }, // amount is only for the jump bids
+    Proxy { player_id: i32, max_amount: f32 },
+    ProxyCancel { player_id: i32 },
+    Skip { reason: Option<String> },
+    SkipCurrentPool,
+    GetIsSkippedPool,
+    Rtm { amount: f32 }, // amount increased on top of the current bid
+    RtmAccept,
+    RtmCancel,
+    InstantRtmCancel,
+    End,
+    Pause,
+    Chat { message: String },
+    TimeSync { client_time: Option<i64> },
+    GetSnapshot,
+    // offer, answer and ice-candidate were having their own format, so they were parsed as SignalingMessage
+    #[serde(skip)]
+    Signaling(SignalingMessage),
+}
+
+impl ClientMessage {
+    pub fn parse(text: &str) -> Result<ClientMessage, String> {
+        if text.starts_with('{') {
+            if let Ok(signaling_message) = serde_json::from_str::<SignalingMessage>(text) {
+                return Ok(ClientMessage::Signaling(signaling_message));
+            }
+            return serde_json::from_str::<ClientMessage>(text).map_err(|err| format!("Invalid Message, {}", err));
+        }
+        ClientMessage::from_legacy(text)
+    }
+
+    // compatibility for the string commands of the protocol version 1
+    fn from_legacy(text: &str) -> Result<ClientMessage, String> {
+        let message = match text {
+            "ping" => ClientMessage::Ping,
+            "mute" => ClientMessage::Mute,
+            "unmute" => ClientMessage::Unmute,
+            "start" => ClientMessage::Start,
+            "add-bot" => ClientMessage::AddBot,
+            "autopilot-on" => ClientMessage::Autopilot { enabled: true },
+            "autopilot-off" => ClientMessage::Autopilot { enabled: false },
+            "bid" => ClientMessage::Bid { amount: None },
+            "skip" => ClientMessage::Skip { reason: None },
+            "skip-current-pool" => ClientMessage::SkipCurrentPool,
+            "get-is-skipped-pool" => ClientMessage::GetIsSkippedPool,
+            "rtm-accept" => ClientMessage::RtmAccept,
+            "rtm-cancel" => ClientMessage::RtmCancel,
+            "instant-rtm-cancel" => ClientMessage::InstantRtmCancel,
+            "end" => ClientMessage::End,
+            "pause" => ClientMessage::Pause,
+            "time-sync" => ClientMessage::TimeSync { client_time: None },
+            "get-snapshot" => ClientMessage::GetSnapshot,
+            _ => {
+                if let Some(amount) = text.strip_prefix("bid-") {
+                    // bid-5.50, jump bid
+                    ClientMessage::Bid { amount: Some(parse_number(amount, "Invalid Bid Amount")?) }
+                } else if let Some(player_id) = text.strip_prefix("proxy-cancel-") {
+                    ClientMessage::ProxyCancel { player_id: parse_number(player_id, "Invalid Proxy Bid")? }
+                } else if let Some(proxy) = text.strip_prefix("proxy-") {
+                    // proxy-player_id-max_amount
+                    let Some((player_id, max_amount)) = proxy.split_once('-') else {
+                        return Err(String::from("Invalid Proxy Bid"));
+                    };
+                    ClientMessage::Proxy {
+                        player_id: parse_number(player_id, "Invalid Proxy Bid")?,
+                        max_amount: parse_number(max_amount, "Invalid Proxy Bid")?
+                    }
+                } else if let Some(amount) = text.strip_prefix("rtm-") {
+                    // rtm-5.00, increasing 5.00cr from the current price
+                    ClientMessage::Rtm { amount: parse_number(amount, "Invalid RTM Amount")? }
+                } else if let Some(reason) = text.strip_prefix("skip-") {
+                    ClientMessage::Skip { reason: Some(reason.to_string()) }
+                } else if let Some(message) = text.strip_prefix("chat-") {
+                    ClientMessage::Chat { message: message.to_string() }
+                } else if let Some(client_time) = text.strip_prefix("time-sync-") {
+                    ClientMessage::TimeSync { client_time: client_time.parse::<i64>().ok() }
+                } else {
+                    return Err(format!("Unknown Message {}", text));
+                }
+            }
+        };
+        Ok(message)
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str, error: &str) -> Result<T, String> {
+    value.parse::<T>().map_err(|_| error.to_string())
+}
+
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorCode {
+    InvalidMessage,
+    NotPermitted,
+    NotEnoughParticipants,
+    BidRejected,
+    RtmRejected,
+    RoomClosed,
+    Conflict,
+    ServerError,
+}
+
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum ServerMessage {
+    Welcome { protocol_version: u8, participant_id: i32 },
+    ParticipantJoined(AuctionParticipant),
+    ParticipantLeft(Participant),
+    Participants { participants: Vec<AuctionParticipant> },
+    RoomRules(RoomRules),
+    StrictMode,
+    RoomSnapshot(Box<RoomSnapshot>),
+    PlayerUp(Player),
+    BidPlaced(BidOutput),
+    PlayerSold(SoldPlayer),
+    PlayerUnsold,
+    AuctionPaused,
+    AuctionCompleted,
+    Exit,
+    UseRtm,
+    RtmAmount { amount: f32 }, // price quoted by the rtm team, the highest bidder can accept or cancel
+    Timer(TimerDeadline),
+    TimeSync(TimeSync),
+    ParticipantAudio(ParticipantAudio),
+    Chat(ChatMessage),
+    IsSkippedPool { is_skipped: bool },
+    TeamMuted { team_name: String, is_unmuted: bool },
+    PlayerSkipped { team_name: String, reason: Option<String> },
+    PoolSkipRequested { team_name: String },
+    PauseScheduled, // the auction pauses after the current bid
+    AutopilotStarted { team_name: String },
+    AutopilotStopped { team_name: String },
+    AutopilotOptIn { enabled: bool },
+    BotAdded { team_name: String },
+    ProxyRegistered { player_id: i32, max_amount: f32 },
+    ProxyCancelled { player_id: i32 },
+    ProxyOutbid { max_amount: f32 },
+    ProxyStopped,
+    RtmCancelled,
+    RtmPriceCancelled,
+    RtmNotAffordable { amount: f32 }, // the highest bidder was not having the balance for the rtm price
+    Notice { message: String },
+    Maintenance { message: String }, // the server was shutting down, the client should reconnect
+    Error { code: ErrorCode, message: String },
+}
+
+impl ServerMessage {
+    pub fn error(code: ErrorCode, message: &str) -> ServerMessage {
+        ServerMessage::Error { code, message: message.to_string() }
+    }
+
+    // errors sent directly on the socket, before the connection was registered in the room
+    pub fn connection_error(code: ErrorCode, message: &str, protocol_version: u8) -> Message {
+        let error = ServerMessage::error(code, message) ;
+        if protocol_version == LEGACY_PROTOCOL_VERSION {
+            Message::text(message)
+        } else {
+            error.to_message()
+        }
+    }
+
+    pub fn to_message(&self) -> Message {
+        Message::from(serde_json::to_string(self).unwrap())
+    }
+
+    // the same message as it was in the protocol version 1, none if version 1 was not having that message
+    pub fn to_legacy_message(&self) -> Option<Message> {
+        let message = match self {
+            ServerMessage::Welcome { .. } => return None,
+            ServerMessage::ParticipantJoined(participant) => to_json(participant),
+            ServerMessage::ParticipantLeft(participant) => to_json(participant),
+            ServerMessage::Participants { participants } => to_json(participants),
+            ServerMessage::RoomRules(rules) => to_json(rules),
+            ServerMessage::StrictMode => Message::text("strict-mode"),
+            ServerMessage::PlayerUp(player) => to_json(player),
+            ServerMessage::BidPlaced(bid) => to_json(bid),
+            ServerMessage::PlayerSold(sold_player) => to_json(sold_player),
+            ServerMessage::PlayerUnsold => Message::text("UnSold"),
+            ServerMessage::AuctionPaused => Message::text("Auction was Paused"),
+            ServerMessage::AuctionCompleted => Message::text("Auction Completed"),
+            ServerMessage::Exit => Message::text("exit"),
+            ServerMessage::UseRtm => Message::text("Use RTM"),
+            ServerMessage::RtmAmount { amount } => Message::text(format!("rtm-amount-{}", amount)),
+            ServerMessage::ParticipantAudio(audio) => to_json(audio),
+            ServerMessage::Chat(chat) => to_json(chat),
+            ServerMessage::IsSkippedPool { is_skipped } => Message::text(format!("is_skipped:{}", is_skipped)),
+            ServerMessage::TeamMuted { team_name, is_unmuted } => Message::text(format!("{} {}", team_name, if *is_unmuted { "unmuted" } else { "muted" })),
+            ServerMessage::PlayerSkipped { team_name, reason } => match reason {
+                Some(reason) => Message::text(format!("{} was out of bid, due to {}", team_name, reason)),
+                None => Message::text(format!("{} skipped the player", team_name))
+            },
+            ServerMessage::PoolSkipRequested { team_name } => Message::text(format!("{} want's to skip the current-pool", team_name)),
+            ServerMessage::PauseScheduled => Message::text("After the Current Bid Auction will be Paused"),
+            ServerMessage::AutopilotStarted { team_name } => Message::text(format!("{} was on Autopilot", team_name)),
+            ServerMessage::AutopilotStopped { team_name } => Message::text(format!("{} was back, Autopilot Stopped", team_name)),
+            ServerMessage::AutopilotOptIn { enabled: true } => Message::text("Autopilot Enabled, it starts when you got disconnected"),
+            ServerMessage::AutopilotOptIn { enabled: false } => Message::text("Autopilot Disabled"),
+            ServerMessage::BotAdded { team_name } => Message::text(format!("{} was added as a Bot", team_name)),
+            ServerMessage::ProxyRegistered { player_id, max_amount } => Message::text(format!("Proxy Bid registered for player {} up to {}", player_id, max_amount)),
+            ServerMessage::ProxyCancelled { player_id } => Message::text(format!("Proxy Bid Cancelled for player {}", player_id)),
+            ServerMessage::ProxyOutbid { max_amount } => Message::text(format!("Your Proxy Bid of {} was outbid", max_amount)),
+            ServerMessage::ProxyStopped => Message::text("Your Proxy Bid was stopped"),
+            ServerMessage::RtmCancelled => Message::text("Cancelled the RTM"),
+            ServerMessage::RtmPriceCancelled => Message::text("Cancelled the RTM Price"),
+            ServerMessage::RtmNotAffordable { amount } => Message::text(format!("no balance to accept the bid price of {}", amount)),
+            ServerMessage::Notice { message } | ServerMessage::Maintenance { message } | ServerMessage::Error { message, .. } => Message::text(message.as_str()),
+            // these were already sent with the type in version 1
+            ServerMessage::RoomSnapshot(_) | ServerMessage::Timer(_) | ServerMessage::TimeSync(_) => self.to_message(),
+        };
+        Some(message)
+    }
+}
+
+fn to_json<T: Serialize>(value: &T) -> Message {
+    Message::from(serde_json::to_string(value).unwrap())
+}
+
+
+/*
+    messages were sent once to the room, the forwarding task of each connection converts them to the protocol
+    version of that connection. none means the message was not sent to that connection.
+*/
+pub fn message_for_version(message: Message, protocol_version: u8) -> Option<Message> {
+    let Message::Text(text) = &message else {
+        return Some(message);
+    };
+    let is_json = text.starts_with('{') || text.starts_with('[');
+    if protocol_version == LEGACY_PROTOCOL_VERSION {
+        if !is_json {
+            return Some(message);
+        }
+        match serde_json::from_str::<ServerMessage>(text.as_str()) {
+            Ok(server_message) => server_message.to_legacy_message(),
+            Err(_) => Some(message), // signaling messages
+        }
+    } else if is_json {
+        Some(message)
+    } else {
+        // informational texts like "X skipped the player"
+        Some(ServerMessage::Notice { message: text.to_string() }.to_message())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ClientMessage was not comparable, the parsed messages were compared through their debug output
+    fn parsed(text: &str) -> String {
+        format!("{:?}", ClientMessage::parse(text).unwrap_or_else(|err| panic!("{} was not parsed, {}", text, err)))
+    }
+
+    #[test]
+    fn legacy_commands_were_same_as_json_messages() {
+        let commands = [
+            ("ping", r#"{"type": "ping"}"#),
+            ("mute", r#"{"type": "mute"}"#),
+            ("unmute", r#"{"type": "unmute"}"#),
+            ("start", r#"{"type": "start"}"#),
+            ("add-bot", r#"{"type": "add-bot"}"#),
+            ("autopilot-on", r#"{"type": "autopilot", "enabled": true}"#),
+            ("autopilot-off", r#"{"type": "autopilot", "enabled": false}"#),
+            ("bid", r#"{"type": "bid"}"#),
+            ("bid-5.50", r#"{"type": "bid", "amount": 5.5}"#),
+            ("proxy-25-7.50", r#"{"type": "proxy", "player_id": 25, "max_amount": 7.5}"#),
+            ("proxy-cancel-25", r#"{"type": "proxy-cancel", "player_id": 25}"#),
+            ("skip", r#"{"type": "skip"}"#),
+            ("skip-budget", r#"{"type": "skip", "reason": "budget"}"#),
+            ("skip-current-pool", r#"{"type": "skip-current-pool"}"#),
+            ("get-is-skipped-pool", r#"{"type": "get-is-skipped-pool"}"#),
+            ("rtm-5.00", r#"{"type": "rtm", "amount": 5.0}"#),
+            ("rtm-accept", r#"{"type": "rtm-accept"}"#),
+            ("rtm-cancel", r#"{"type": "rtm-cancel"}"#),
+            ("instant-rtm-cancel", r#"{"type": "instant-rtm-cancel"}"#),
+            ("end", r#"{"type": "end"}"#),
+            ("pause", r#"{"type": "pause"}"#),
+            ("chat-hi", r#"{"type": "chat", "message": "hi"}"#),
+            ("chat-", r#"{"type": "chat", "message": ""}"#),
+            ("time-sync", r#"{"type": "time-sync"}"#),
+            ("time-sync-1767081600000", r#"{"type": "time-sync", "client_time": 1767081600000}"#),
+            ("get-snapshot", r#"{"type": "get-snapshot"}"#),
+        ];
+        for (legacy, json) in commands {
+            assert_eq!(parsed(legacy), parsed(json), "{} and {} were parsed differently", legacy, json);
+        }
+    }
+
+    #[test]
+    fn legacy_commands_were_parsed() {
+        assert!(matches!(ClientMessage::parse("bid-5.50"), Ok(ClientMessage::Bid { amount: Some(amount) }) if amount == 5.5));
+        assert!(matches!(ClientMessage::parse("proxy-25-7.50"), Ok(ClientMessage::Proxy { player_id: 25, max_amount }) if max_amount == 7.5));
+        assert!(matches!(ClientMessage::parse("skip-current-pool"), Ok(ClientMessage::SkipCurrentPool)));
+        assert!(matches!(ClientMessage::parse("rtm-accept"), Ok(ClientMessage::RtmAccept)));
+        assert!(matches!(ClientMessage::parse("chat-well-played"), Ok(ClientMessage::Chat { message }) if message == "well-played"));
+        // an invalid client time was taken as no client time
+        assert!(matches!(ClientMessage::parse("time-sync-abc"), Ok(ClientMessage::TimeSync { client_time: None })));
+    }
+
+    #[test]
+    fn invalid_legacy_commands_were_rejected() {
+        let commands = [
+            ("bid-abc", "Invalid Bid Amount"),
+            ("bid-", "Invalid Bid Amount"),
+            ("rtm-five", "Invalid RTM Amount"),
+            ("proxy-25", "Invalid Proxy Bid"),
+            ("proxy-abc-7.50", "Invalid Proxy Bid"),
+            ("proxy-25-abc", "Invalid Proxy Bid"),
+            ("proxy-cancel-abc", "Invalid Proxy Bid"),
+            ("hello", "Unknown Message hello"),
+            ("", "Unknown Message "),
+            ("PING", "Unknown Message PING"),
+        ];
+        for (command, error) in commands {
+            assert_eq!(ClientMessage::parse(command).err().as_deref(), Some(error), "{} was not rejected", command);
+        }
+    }
+
+    #[test]
+    fn unknown_or_malformed_json_was_rejected() {
+        for text in [
+            r#"{"type": "dance"}"#,
+            r#"{"type": "bid", "amount": "five"}"#,
+            r#"{"type": "proxy", "player_id": 25}"#,
+            r#"{"amount": 5.5}"#,
+            r#"{"type": "ping""#,
+            "{",
+        ] {
+            let result = ClientMessage::parse(text);
+            assert!(matches!(&result, Err(err) if err.starts_with("Invalid Message")), "{} was parsed as {:?}", text, result);
+        }
+    }
+
+    #[test]
+    fn signaling_messages_were_parsed_first() {
+        let offer = r#"{"type": "offer", "from": 1, "to": 2, "payload": {"type": "offer", "sdp": "v=0"}}"#;
+        assert!(matches!(ClientMessage::parse(offer), Ok(ClientMessage::Signaling(SignalingMessage::Offer { from: 1, to: 2, .. }))));
+    }
+}
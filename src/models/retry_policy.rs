@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use rand::Rng;
+
+/*
+    retry policy of the auction room background tasks, loaded from the env:
+    -> RETRY_MAX_ATTEMPTS (4, the first attempt and 3 retries), RETRY_BASE_DELAY_SECS (30), RETRY_MULTIPLIER (2),
+       RETRY_JITTER (0.1, the delay was randomized within ±10% of itself), RETRY_MAX_DELAY_SECS (600)
+    -> RETRY_OVERRIDES overrides them per task type, for example
+       "SaleCompleted=max_attempts:5,base_delay_secs:5;UpdatingRoomStatus=max_attempts:10"
+    -> RETRY_POLL_MAX_SECS (5), the retries listener sleeps until the next due retry but not longer than this
+*/
+// bigger delays were not representable as a Duration
+const MAX_VALUE: f64 = 1e9;
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_attempts: u8,
+    pub base_delay: Duration,
+    pub multiplier: f64,
+    pub jitter: f64,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            base_delay: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: 0.1,
+            max_delay: Duration::from_secs(600),
+        }
+    }
+}
+
+impl RetryPolicy {
+    // the task was moved to the DLQ once it failed this many times
+    pub fn is_exhausted(&self, failures: u8) -> bool {
+        failures >= self.max_attempts
+    }
+
+    // delay before the given retry, the first retry was 1
+    pub fn delay(&self, retry: u8) -> Duration {
+        let delay = self.base_delay.as_secs_f64() * self.multiplier.powi(i32::from(retry.saturating_sub(1)));
+        let delay = delay.min(self.max_delay.as_secs_f64());
+        let jitter = if self.jitter > 0.0 { rand::rng().random_range(-self.jitter..=self.jitter) } else { 0.0 };
+        Duration::from_secs_f64(delay * (1.0 + jitter))
+    }
+
+    fn set(&mut self, field: &str, value: &str) -> Result<(), String> {
+        let number = value.parse::<f64>().ok()
+            .filter(|number| (0.0..MAX_VALUE).contains(number))
+            .ok_or_else(|| format!("invalid value {} for {}", value, field))?;
+        match field {
+            "max_attempts" => self.max_attempts = number.clamp(1.0, f64::from(u8::MAX)) as u8,
+            "base_delay_secs" => self.base_delay = Duration::from_secs_f64(number),
+            "multiplier" => self.multiplier = number.max(1.0),
+            "jitter" => self.jitter = number.min(1.0),
+            "max_delay_secs" => self.max_delay = Duration::from_secs_f64(number),
+            _ => return Err(format!("unknown retry policy field {}", field)),
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicies {
+    default: RetryPolicy,
+    overrides: HashMap<String, RetryPolicy>, // task type -> policy
+    pub poll_max: Duration,
+}
+
+impl RetryPolicies {
+    // invalid values were logged and the defaults were used instead
+    pub fn from_env() -> Self {
+        let mut default = RetryPolicy::default();
+        for (variable, field) in [
+            ("RETRY_MAX_ATTEMPTS", "max_attempts"),
+            ("RETRY_BASE_DELAY_SECS", "base_delay_secs"),
+            ("RETRY_MULTIPLIER", "multiplier"),
+            ("RETRY_JITTER", "jitter"),
+            ("RETRY_MAX_DELAY_SECS", "max_delay_secs"),
+        ] {
+            if let Ok(value) = std::env::var(variable)
+                && let Err(err) = default.set(field, value.trim()) {
+                tracing::warn!("{} was ignored, {}", variable, err);
+            }
+        }
+
+        let overrides = parse_overrides(&std::env::var("RETRY_OVERRIDES").unwrap_or_default(), &default);
+
+        let poll_max = std::env::var("RETRY_POLL_MAX_SECS").ok()
+            .and_then(|seconds| seconds.parse::<u64>().ok())
+            .unwrap_or(5);
+        tracing::info!("retry policy {:?} with overrides for {:?}", default, overrides.keys().collect::<Vec<_>>());
+        Self { default, overrides, poll_max: Duration::from_secs(poll_max.max(1)) }
+    }
+
+    pub fn for_task(&self, task_type: &str) -> &RetryPolicy {
+        self.overrides.get(task_type).unwrap_or(&self.default)
+    }
+}
+
+// fields which were not given in the override were taken from the default policy
+fn parse_overrides(task_overrides: &str, default: &RetryPolicy) -> HashMap<String, RetryPolicy> {
+    let mut overrides = HashMap::new();
+    for task_override in task_overrides.split(';').filter(|task_override| !task_override.trim().is_empty()) {
+        let Some((task_type, fields)) = task_override.split_once('=') else {
+            tracing::warn!("retry override {} was ignored, expected task_type=field:value,...", task_override);
+            continue;
+        };
+        let mut policy = default.clone();
+        for field in fields.split(',') {
+            let result = match field.split_once(':') {
+                Some((field, value)) => policy.set(field.trim(), value.trim()),
+                None => Err(format!("expected field:value but got {}", field)),
+            };
+            if let Err(err) = result {
+                tracing::warn!("retry override of {} was partly ignored, {}", task_type, err);
+            }
+        }
+        overrides.insert(task_type.trim().to_string(), policy);
+    }
+    overrides
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn without_jitter() -> RetryPolicy {
+        RetryPolicy { jitter: 0.0, ..RetryPolicy::default() }
+    }
+
+    #[test]
+    fn delay_grows_exponentially() {
+        let policy = without_jitter();
+        assert_eq!(policy.delay(1), Duration::from_secs(30));
+        assert_eq!(policy.delay(2), Duration::from_secs(60));
+        assert_eq!(policy.delay(3), Duration::from_secs(120));
+        assert_eq!(policy.delay(4), Duration::from_secs(240));
+        // a retry count of 0 was treated as the first retry
+        assert_eq!(policy.delay(0), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn delay_is_capped_at_max_delay() {
+        let policy = without_jitter();
+        assert_eq!(policy.delay(6), Duration::from_secs(600));
+        assert_eq!(policy.delay(u8::MAX), Duration::from_secs(600));
+    }
+
+    #[test]
+    fn jitter_stays_within_bounds() {
+        let policy = RetryPolicy { jitter: 0.5, ..RetryPolicy::default() };
+        for _ in 0..1000 {
+            let delay = policy.delay(2).as_secs_f64();
+            assert!((30.0..=90.0).contains(&delay), "delay {} was out of the jitter bounds", delay);
+        }
+        let capped = RetryPolicy { jitter: 0.1, ..RetryPolicy::default() };
+        for _ in 0..1000 {
+            let delay = capped.delay(20).as_secs_f64();
+            assert!((540.0..=660.0).contains(&delay), "delay {} was out of the jitter bounds", delay);
+        }
+    }
+
+    #[test]
+    fn exhausted_after_max_attempts() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.max_attempts, 4);
+        assert!(!policy.is_exhausted(0));
+        assert!(!policy.is_exhausted(3));
+        assert!(policy.is_exhausted(4));
+        assert!(policy.is_exhausted(5));
+    }
+
+    #[test]
+    fn overrides_were_parsed_per_task_type() {
+        let overrides = parse_overrides("SaleCompleted=max_attempts:5,base_delay_secs:5; UpdatingRoomStatus = max_attempts : 10 ;", &RetryPolicy::default());
+        assert_eq!(overrides.len(), 2);
+        let sale_completed = &overrides["SaleCompleted"];
+        assert_eq!(sale_completed.max_attempts, 5);
+        assert_eq!(sale_completed.base_delay, Duration::from_secs(5));
+        assert_eq!(sale_completed.max_delay, Duration::from_secs(600));
+        let room_status = &overrides["UpdatingRoomStatus"];
+        assert_eq!(room_status.max_attempts, 10);
+        assert_eq!(room_status.base_delay, Duration::from_secs(30));
+    }
+
+    #[test]
+    fn malformed_overrides_were_ignored() {
+        let overrides = parse_overrides(
+            "SaleCompleted;UpdatingBalance=max_attempts:abc,jitter;UpdatingRTMs=retries:2,multiplier:-1,base_delay_secs:1e10,max_delay_secs:60",
+            &RetryPolicy::default(),
+        );
+        // an override without = was dropped
+        assert!(!overrides.contains_key("SaleCompleted"));
+        // invalid fields were dropped, the rest of the override was still taken
+        let balance = &overrides["UpdatingBalance"];
+        assert_eq!(balance.max_attempts, 4);
+        assert_eq!(balance.jitter, 0.1);
+        let rtms = &overrides["UpdatingRTMs"];
+        assert_eq!(rtms.multiplier, 2.0);
+        assert_eq!(rtms.base_delay, Duration::from_secs(30));
+        assert_eq!(rtms.max_delay, Duration::from_secs(60));
+    }
+
+    #[test]
+    fn values_were_clamped() {
+        let overrides = parse_overrides("SaleCompleted=max_attempts:0,multiplier:0.5,jitter:3;UnsoldPlayer=max_attempts:1000", &RetryPolicy::default());
+        let sale_completed = &overrides["SaleCompleted"];
+        assert_eq!(sale_completed.max_attempts, 1);
+        assert_eq!(sale_completed.multiplier, 1.0);
+        assert_eq!(sale_completed.jitter, 1.0);
+        assert_eq!(overrides["UnsoldPlayer"].max_attempts, u8::MAX);
+    }
+}
@@ -1,6 +1,11 @@
+use std::sync::Arc;
+use std::sync::atomic::AtomicUsize;
 use serde::{Deserialize, Serialize};
+use axum::extract::ws::{CloseFrame, Message};
+use tokio::sync::{mpsc, oneshot};
+use crate::models::protocol_models::ClientMessage;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Participant {
     pub participant_id: i32,
     pub team_name: String,
@@ -10,7 +15,8 @@ pub struct Participant {
 pub struct ParticipantResponse {
     pub participant_id: i32,
     pub team_name: String,
-    pub user_id: i32,
+    pub user_id: Option<i32>,
+    pub is_bot: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -30,4 +36,131 @@ pub struct Rooms {
     pub(crate) room_id: String,
     pub(crate) created_at: DateTime<Utc>,
     pub status: String
-}
\ No newline at end of file
+}
+
+// state of a participant as recorded in postgres, used for rebuilding the room in redis
+#[derive(Debug, sqlx::FromRow)]
+pub struct ParticipantState {
+    pub id: i32,
+    pub team_selected: String,
+    pub purse_remaining: f32,
+    pub remaining_rtms: i16,
+    pub is_bot: bool,
+    pub is_creator: bool,
+    pub players_brought: i32,
+    pub foreign_players_brought: i32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RehydratedRoom {
+    pub room_id: String,
+    pub participants: usize,
+    pub next_player_id: i32,
+}
+
+#[derive(Debug)]
+pub enum RehydrationError {
+    RoomNotInProgress,
+    RoomStateExists,    // participants were still in redis, rebuilding would overwrite the live balances
+    InProgress,         // the room was being rebuilt by another join or server
+    NoParticipants,
+    AuctionFinished,    // every player was already sold or unsold
+    Failed(String),
+}
+
+impl RehydrationError {
+    pub fn message(&self) -> String {
+        match self {
+            RehydrationError::RoomNotInProgress => String::from("Room was not in progress"),
+            RehydrationError::RoomStateExists => String::from("Room state was still in redis"),
+            RehydrationError::InProgress => String::from("Room was being rebuilt, try again"),
+            RehydrationError::NoParticipants => String::from("No participants in the room"),
+            RehydrationError::AuctionFinished => String::from("All the players were auctioned, complete the room"),
+            RehydrationError::Failed(err) => format!("Unable to rebuild the room, {}", err),
+        }
+    }
+}
+
+// every live room was owned by a room actor, the handle was used to send commands and messages to it
+#[derive(Debug, Clone)]
+pub struct RoomHandle {
+    pub commands: mpsc::UnboundedSender<RoomCommand>,
+    pub fan_out: mpsc::UnboundedSender<FanOut>,
+    pub connections: Arc<AtomicUsize>, // connections held by the fan out task, read without asking the fan out task
+}
+
+// state changes of the room, applied one after the other by the room actor
+#[derive(Debug)]
+pub enum RoomCommand {
+    Client { participant_id: i32, team_name: String, room_mode: bool, message: ClientMessage },
+    TimerExpired { timer_key: String, claim: String, is_rtm: String, bid_expiry: u8 },
+    BotsTurn,
+    ParticipantLeft { participant_id: i32, team_name: String },
+    Retire, // the last connection of this server was closed
+}
+
+// connections of the room, the fan out task owns the senders of all the connections
+#[derive(Debug)]
+pub enum FanOut {
+    Connect { participant_id: i32, sender: mpsc::UnboundedSender<Message> },
+    Disconnect { participant_id: i32, sender: mpsc::UnboundedSender<Message> },
+    Broadcast(Message),
+    Send { participant_id: i32, message: Message },
+    Connected { reply: oneshot::Sender<Vec<i32>> },
+    Retired, // the handle was removed, the messages were forwarded to the next fan out task of the room
+}
+
+/*
+    messages of the room were published to the redis channel of the room, such that every server delivers them
+    to the connections it was holding. participant_id None was a broadcast to the whole room.
+*/
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoomEvent {
+    pub participant_id: Option<i32>,
+    pub payload: RoomEventPayload,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum RoomEventPayload {
+    Text(String),
+    Binary(Vec<u8>),
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+    Close, // the room was completed, every server closes its connections of the room
+}
+
+impl RoomEvent {
+    // close frames were never published, the forwarding task of the connection sends them
+    pub fn new(participant_id: Option<i32>, message: Message) -> Option<Self> {
+        let payload = match message {
+            Message::Text(text) => RoomEventPayload::Text(text.to_string()),
+            Message::Binary(bytes) => RoomEventPayload::Binary(bytes.to_vec()),
+            Message::Ping(bytes) => RoomEventPayload::Ping(bytes.to_vec()),
+            Message::Pong(bytes) => RoomEventPayload::Pong(bytes.to_vec()),
+            Message::Close(_) => return None,
+        } ;
+        Some(Self { participant_id, payload })
+    }
+
+    pub fn close_room() -> Self {
+        Self { participant_id: None, payload: RoomEventPayload::Close }
+    }
+
+    pub fn into_fan_out(self) -> FanOut {
+        let message = match self.payload {
+            RoomEventPayload::Text(text) => Message::text(text),
+            RoomEventPayload::Binary(bytes) => Message::binary(bytes),
+            RoomEventPayload::Ping(bytes) => Message::Ping(bytes.into()),
+            RoomEventPayload::Pong(bytes) => Message::Pong(bytes.into()),
+            RoomEventPayload::Close => Message::Close(Some(CloseFrame {
+                code: 1000,
+                reason: "auction completed".into(),
+            })),
+        } ;
+        match self.participant_id {
+            Some(participant_id) => FanOut::Send { participant_id, message },
+            None => FanOut::Broadcast(message),
+        }
+    }
+}
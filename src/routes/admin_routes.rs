@@ -1,11 +1,16 @@
 use std::sync::Arc;
 use axum::Router;
 use axum::routing::{get, post};
-use crate::controllers::admin::{auction_completed_tasks_execution, get_player};
+use crate::controllers::admin::{auction_completed_tasks_execution, close_dead_letter_tasks, get_player, inspect_dead_letter_task, list_dead_letter_tasks, rehydrate_room_state, replay_dead_letters};
 use crate::models::app_state::AppState;
 
 pub fn admin_routes() -> Router<Arc<AppState>>{
     Router::new()
         .route("/get-redis-player/{player_id}", get(get_player))
         .route("/auction_completed_tasks_execution", post(auction_completed_tasks_execution))
+        .route("/rehydrate-room", post(rehydrate_room_state))
+        .route("/dlq/list", post(list_dead_letter_tasks))
+        .route("/dlq/inspect", post(inspect_dead_letter_task))
+        .route("/dlq/replay", post(replay_dead_letters))
+        .route("/dlq/close", post(close_dead_letter_tasks))
 }
\ No newline at end of file
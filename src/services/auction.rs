@@ -6,10 +6,10 @@ use dotenv::dotenv;
 use redis::AsyncCommands;
 use serde::Serialize;
 use sqlx::types::Json;
-use crate::models::auction_models::SoldPlayer;
-use crate::models::background_db_tasks::{AuctionRoomRetryTasks, DBCommandsAuctionRoom};
+use crate::models::auction_models::RoomRules;
+use crate::models::background_db_tasks::{AuctionRoomRetryTasks, DBCommandsAuctionRoom, DeadLetterTask, SaleCompleted, SkipVoted};
 use crate::models::player_models::{PlayerDetails, SoldPlayerOutput, TeamDetails, UnSoldPlayerOutput};
-use crate::models::room_models::{Participant, ParticipantResponse, Rooms};
+use crate::models::room_models::{Participant, ParticipantResponse, ParticipantState, Rooms};
 
 #[derive(Debug, Clone)]
 pub struct DatabaseAccess {
@@ -64,10 +64,11 @@ impl DatabaseAccess {
 
 
 
-    pub async fn create_room(&self, user_id: i32, is_strict_mode: bool) -> Result<String, sqlx::Error> {
-        let room = sqlx::query("insert into rooms (creator_id, strict_mode) values ($1, $2) returning id")
+    pub async fn create_room(&self, user_id: i32, is_strict_mode: bool, rules: &RoomRules) -> Result<String, sqlx::Error> {
+        let room = sqlx::query("insert into rooms (creator_id, strict_mode, rules) values ($1, $2, $3::jsonb) returning id")
             .bind(user_id)
             .bind(is_strict_mode)
+            .bind(Json(rules))
             .fetch_one(&self.connection).await ;
 
         match room {
@@ -154,6 +155,21 @@ impl DatabaseAccess {
         }
 
     }
+    // websocket connections were allowed only for the user who owns the participant in that room
+    pub async fn is_participant_of_user(&self, participant_id: i32, user_id: i32, room_id: &str) -> Result<bool, sqlx::Error> {
+        let Ok(room_id) = sqlx::types::Uuid::parse_str(room_id) else {
+            tracing::warn!("invalid room id {}", room_id) ;
+            return Ok(false);
+        } ;
+        let exists: Option<i32> = sqlx::query_scalar("SELECT id FROM participants WHERE id = $1 AND user_id = $2 AND room_id = $3")
+            .bind(participant_id)
+            .bind(user_id)
+            .bind(room_id)
+            .fetch_optional(&self.connection)
+            .await?;
+        Ok(exists.is_some())
+    }
+
     pub async fn get_room_status(&self, room_id: String) -> Result<String, sqlx::Error> {
         let room_status = sqlx::query("select status::TEXT from rooms where id = $1")
             .bind(sqlx::types::Uuid::parse_str(&room_id).expect("unable to parse the UUID"))
@@ -188,8 +204,30 @@ impl DatabaseAccess {
         }
     }
 
+    pub async fn get_room_rules(&self, room_id: &str) -> Result<RoomRules, sqlx::Error> {
+        let rules = sqlx::query_scalar::<_, Option<Json<RoomRules>>>("select rules from rooms where id = $1")
+            .bind(sqlx::types::Uuid::parse_str(room_id).expect("unable to parse the UUID"))
+            .fetch_one(&self.connection).await ;
+        match rules {
+            Ok(rules) => {
+                // rooms created before the rules column will have null, so they play with the default rules
+                Ok(rules.map(|rules| rules.0).unwrap_or_default())
+            },
+            Err(err) => {
+                tracing::error!("getting error while getting room rules for room-id {}", room_id) ;
+                Err(err)
+            }
+        }
+    }
+
     pub async fn add_participant(&self, user_id: i32, room_id: String, team_name: String) -> Result<i32, sqlx::Error> {
-        let participant = sqlx::query("insert into participants  (user_id, room_id, team_selected) values ($1,$2,$3) returning id")
+        // the starting purse and rtms of the participant are taken from the rules of the room
+        let participant = sqlx::query("
+            insert into participants (user_id, room_id, team_selected, purse_remaining, remaining_rtms)
+            select $1, $2, $3, coalesce((rules->>'purse')::real, 100.0), coalesce((rules->>'rtms_per_team')::smallint, 3)
+            from rooms where id = $2
+            returning id
+        ")
             .bind(user_id)
             .bind(sqlx::types::Uuid::parse_str(&room_id).expect("unable to parse the UUID"))
             .bind(&team_name)
@@ -210,12 +248,96 @@ impl DatabaseAccess {
     }
 
 
-    pub async fn add_sold_player(&self, room_id: &str, player_id: i32, participant_id: i32, amount: f32) -> Result<(), sqlx::Error> {
+    // returns none when the team was taken by some one else, after the remaining teams were read
+    pub async fn add_bot_participant(&self, room_id: &str, team_name: &str) -> Result<Option<i32>, sqlx::Error> {
+        // bots were not having any user, purse and rtms were same as the other participants of the room
+        // the team was claimed in the same statement, two claims of the same team at the same time were stopped by the unique (room_id, team_selected)
+        let participant = sqlx::query("
+            insert into participants (user_id, room_id, team_selected, purse_remaining, remaining_rtms, is_bot)
+            select null, $1, $2, coalesce((rules->>'purse')::real, 100.0), coalesce((rules->>'rtms_per_team')::smallint, 3), true
+            from rooms where id = $1
+            and not exists (select 1 from participants where room_id = $1 and team_selected = $2)
+            on conflict (room_id, team_selected) do nothing
+            returning id
+        ")
+            .bind(sqlx::types::Uuid::parse_str(room_id).expect("unable to parse the UUID"))
+            .bind(team_name)
+            .fetch_optional(&self.connection).await ;
+
+        match participant {
+            Ok(Some(participant)) => {
+                let participant_id: i32 = participant.get("id") ;
+                tracing::info!("bot {} was added to the room {} with team {}", participant_id, room_id, team_name) ;
+                Ok(Some(participant_id))
+            },
+            Ok(None) => {
+                tracing::warn!("team {} of the room {} was already taken, bot was not added", team_name, room_id) ;
+                Ok(None)
+            },
+            Err(err) => {
+                tracing::error!("got error while inserting a bot participant to the room {} error was {}", room_id, err) ;
+                Err(err)
+            }
+        }
+    }
+
+
+    // balance and rtms of every participant along with the players bought till now, sold players were the source of the squad counts
+    pub async fn get_participants_state(&self, room_id: &str) -> Result<Vec<ParticipantState>, sqlx::Error> {
+        let participants = sqlx::query_as::<_, ParticipantState>("
+            SELECT p.id, p.team_selected, p.purse_remaining, p.remaining_rtms, p.is_bot,
+                   COALESCE(r.creator_id = p.user_id, FALSE) AS is_creator,
+                   COUNT(s.player_id)::INT AS players_brought,
+                   (COUNT(s.player_id) FILTER (WHERE pl.is_indian IS FALSE))::INT AS foreign_players_brought
+            FROM participants p
+            JOIN rooms r ON r.id = p.room_id
+            LEFT JOIN sold_players s ON s.participant_id = p.id AND s.room_id = p.room_id
+            LEFT JOIN players pl ON pl.id = s.player_id
+            WHERE p.room_id = $1
+            GROUP BY p.id, r.creator_id
+            ORDER BY p.id
+        ")
+            .bind(sqlx::types::Uuid::parse_str(room_id).expect("unable to parse the UUID"))
+            .fetch_all(&self.connection).await ;
+
+        match participants {
+            Ok(participants) => Ok(participants),
+            Err(err) => {
+                tracing::error!("got an error while getting the participants state of the room {}", room_id) ;
+                tracing::error!("error {}", err) ;
+                Err(err)
+            }
+        }
+    }
+
+    // players were put up in the order of their ids, so the last sold or unsold player tells where the auction was
+    pub async fn get_last_auctioned_player(&self, room_id: &str) -> Result<Option<i32>, sqlx::Error> {
+        let last_player = sqlx::query_scalar::<_, Option<i32>>("
+            SELECT MAX(player_id) FROM (
+                SELECT player_id FROM sold_players WHERE room_id = $1
+                UNION ALL
+                SELECT player_id FROM unsold_players WHERE room_id = $1
+            ) auctioned
+        ")
+            .bind(sqlx::types::Uuid::parse_str(room_id).expect("unable to parse the UUID"))
+            .fetch_one(&self.connection).await ;
+
+        match last_player {
+            Ok(last_player) => Ok(last_player),
+            Err(err) => {
+                tracing::error!("got an error while getting the last auctioned player of the room {}", room_id) ;
+                tracing::error!("error {}", err) ;
+                Err(err)
+            }
+        }
+    }
+
+    pub async fn add_sold_player(tx: &mut Transaction<'_, Postgres>, room_id: &str, player_id: i32, participant_id: i32, amount: f32) -> Result<(), sqlx::Error> {
         let result = sqlx::query("insert into sold_players (room_id, player_id, participant_id, amount) values ($1,$2,$3,$4)")
             .bind(sqlx::types::Uuid::parse_str(room_id).expect("unable to parse the UUID"))
             .bind(player_id)
             .bind(participant_id)
-            .bind(amount).execute(&self.connection).await ;
+            .bind(amount).execute(&mut **tx).await ;
 
         match result {
             Ok(_) => {
@@ -229,11 +351,57 @@ impl DatabaseAccess {
             }
         }
     }
-    pub async fn add_unsold_player(&self, room_id: &str, player_id: i32) -> Result<(), sqlx::Error> {
+    // the sold player, the balance and the rtm of the participant were committed together or not at all
+    pub async fn add_skip_vote(tx: &mut Transaction<'_, Postgres>, skip: &SkipVoted) -> Result<(), sqlx::Error> {
+        sqlx::query("insert into skip_votes (room_id, participant_id, kind, target) values ($1, $2, $3, $4) on conflict do nothing")
+            .bind(sqlx::types::Uuid::parse_str(&skip.room_id).expect("unable to parse the UUID"))
+            .bind(skip.participant_id)
+            .bind(&skip.kind)
+            .bind(skip.target)
+            .execute(&mut **tx).await?;
+        Ok(())
+    }
+
+    // participants who skipped the player, or the pool, given by the kind
+    pub async fn get_skip_votes(&self, room_id: &str, kind: &str, target: i32) -> Result<Vec<i32>, sqlx::Error> {
+        sqlx::query_scalar::<_, i32>("select participant_id from skip_votes where room_id = $1 and kind = $2 and target = $3")
+            .bind(sqlx::types::Uuid::parse_str(room_id).expect("unable to parse the UUID"))
+            .bind(kind)
+            .bind(target)
+            .fetch_all(&self.connection).await
+    }
+
+    pub async fn complete_sale(tx: &mut Transaction<'_, Postgres>, sale: &SaleCompleted) -> Result<(), sqlx::Error> {
+        sqlx::query("insert into sold_players (room_id, player_id, participant_id, amount) values ($1,$2,$3,$4)")
+            .bind(sqlx::types::Uuid::parse_str(&sale.room_id).expect("unable to parse the UUID"))
+            .bind(sale.player_id)
+            .bind(sale.participant_id)
+            .bind(sale.bid_amount)
+            .execute(&mut **tx).await?;
+        // relative to the committed purse, a retried older sale can commit after a newer one of the same participant
+        let updated = sqlx::query("update participants set purse_remaining = purse_remaining - $1 where id=$2")
+            .bind(sale.bid_amount)
+            .bind(sale.participant_id)
+            .execute(&mut **tx).await?;
+        // rolls back the sold player too, a sale without its participant was never committed
+        if updated.rows_affected() != 1 {
+            tracing::error!("participant {} of the sale of the player {} was not found", sale.participant_id, sale.player_id);
+            return Err(sqlx::Error::RowNotFound);
+        }
+        if sale.decrement_rtm {
+            sqlx::query("update participants set remaining_rtms = remaining_rtms - 1 where id=$1")
+                .bind(sale.participant_id)
+                .execute(&mut **tx).await?;
+        }
+        tracing::info!("completed the sale of the player {} in the room {}", sale.player_id, sale.room_id);
+        Ok(())
+    }
+
+    pub async fn add_unsold_player(tx: &mut Transaction<'_, Postgres>, room_id: &str, player_id: i32) -> Result<(), sqlx::Error> {
         let result = sqlx::query("insert into unsold_players (room_id, player_id) values ($1,$2)")
             .bind(sqlx::types::Uuid::parse_str(room_id).expect("unable to parse the UUID"))
             .bind(player_id)
-            .execute(&self.connection).await ;
+            .execute(&mut **tx).await ;
         match result {
             Ok(_) => {
                 tracing::info!("added unsold player successfully") ;
@@ -247,12 +415,12 @@ impl DatabaseAccess {
         }
     }
 
-    pub async fn update_balance(&self, participant_id: i32, remaining_balance: f32) -> Result<(), sqlx::Error> {
+    pub async fn update_balance(tx: &mut Transaction<'_, Postgres>, participant_id: i32, remaining_balance: f32) -> Result<(), sqlx::Error> {
         tracing::info!("Executing the update_balance to update balance in psql") ;
         let updated = sqlx::query("update participants set purse_remaining=$1 where id=$2")
             .bind(remaining_balance)
             .bind(participant_id)
-            .execute(&self.connection).await ;
+            .execute(&mut **tx).await ;
 
         match updated {
             Ok(_) => Ok(()) ,
@@ -264,12 +432,13 @@ impl DatabaseAccess {
         }
     }
 
-    pub async fn update_room_status(&self, room_id: &str, status: &str) -> Result<(), sqlx::Error> {
+    // runs on the pool or inside the transaction of a background task
+    pub async fn update_room_status<'e>(executor: impl sqlx::PgExecutor<'e>, room_id: &str, status: &str) -> Result<(), sqlx::Error> {
         tracing::info!("Executing the update_room_status to update status in psql") ;
         let updated = sqlx::query("update rooms set status=$1::room_status  where id=$2")
             .bind(status)
             .bind(sqlx::types::Uuid::parse_str(room_id).expect("unable to parse the UUID"))
-        .execute(&self.connection).await ;
+        .execute(executor).await ;
 
         match updated {
             Ok(_) => Ok(()) ,
@@ -495,7 +664,7 @@ impl DatabaseAccess {
     }
 
     pub async fn get_participants_in_room(&self, room_id: String) -> Result<Vec<ParticipantResponse>, sqlx::Error> {
-        let participants = sqlx::query("select id,team_selected,user_id from participants where room_id=$1")
+        let participants = sqlx::query("select id,team_selected,user_id,is_bot from participants where room_id=$1")
             .bind(sqlx::types::Uuid::parse_str(&room_id).expect("unable to parse the UUID"))
             .fetch_all(&self.connection).await ;
 
@@ -507,9 +676,10 @@ impl DatabaseAccess {
                 for participant in participants.iter() {
                     let participant_id: i32 = participant.get("id") ;
                     let team_selected = participant.get("team_selected") ;
-                    let user_id = participant.get("user_id") ;
+                    let user_id = participant.get("user_id") ; // bots were not having user_id
+                    let is_bot = participant.get("is_bot") ;
                     participants_.push(ParticipantResponse{
-                        participant_id, team_name: team_selected, user_id
+                        participant_id, team_name: team_selected, user_id, is_bot
                     }) ;
                 }
                 Ok(participants_)
@@ -626,11 +796,11 @@ impl DatabaseAccess {
     }
 
 
-    pub async fn update_remaining_rtms(&self, participant_id: i32 ) -> Result<(), sqlx::Error> {
+    pub async fn update_remaining_rtms(tx: &mut Transaction<'_, Postgres>, participant_id: i32 ) -> Result<(), sqlx::Error> {
         tracing::info!("updating remaining rtms in psql");
         let result = sqlx::query("update participants set remaining_rtms = remaining_rtms - 1 where id=$1")
             .bind(participant_id)
-            .execute(&self.connection).await ;
+            .execute(&mut **tx).await ;
         match result {
             Ok(result) => {
                 tracing::info!("updated remaining rtms count") ;
@@ -664,7 +834,7 @@ impl DatabaseAccess {
         }
 
 
-    pub async fn set_completed_at(&self, room_id: &str) -> Result<(), sqlx::Error> {
+    pub async fn set_completed_at(tx: &mut Transaction<'_, Postgres>, room_id: &str) -> Result<(), sqlx::Error> {
         tracing::info!("setting the completed at time for the room") ;
         let result = sqlx::query( r#"
             UPDATE rooms
@@ -672,7 +842,7 @@ impl DatabaseAccess {
             WHERE id = $1 AND completed_at is NULL
             "#,)
             .bind(sqlx::types::Uuid::parse_str(&room_id).expect("unable to parse the UUID"))
-            .execute(&self.connection).await ;
+            .execute(&mut **tx).await ;
 
         match result {
             Ok(result) =>{
@@ -823,6 +993,21 @@ impl DatabaseAccess {
     }
 
 
+    /*
+        records the idempotency key of a background task inside the transaction of the task, false if the key was
+        already applied. tasks enqueued by the older servers were not having a key, so they were always applied.
+    */
+    pub async fn record_applied_task(tx: &mut Transaction<'_, Postgres>, idempotency_key: &str, task_type: &str) -> Result<bool, sqlx::Error> {
+        if idempotency_key.is_empty() {
+            return Ok(true);
+        }
+        let result = sqlx::query("insert into applied_tasks (idempotency_key, task_type) values ($1, $2) on conflict do nothing")
+            .bind(idempotency_key)
+            .bind(task_type)
+            .execute(&mut **tx).await?;
+        Ok(result.rows_affected() == 1)
+    }
+
     pub async fn add_to_dlq<T: AuctionRoomRetryTasks + Serialize>(&self, task_type: &str, payload: T, retry_count: i16, last_error: &str) -> Result<(), sqlx::Error> {
         tracing::info!("adding the task to the dlq") ;
         let result = sqlx::query("insert into dead_letter_tasks (task_type, payload, retry_count, last_error) values ($1, $2::jsonb, $3, $4)")
@@ -844,4 +1029,62 @@ impl DatabaseAccess {
         }
     }
 
-}
\ No newline at end of file
+    // the payload was saved as a json string, as it was not a task of any known type
+    pub async fn add_unparsed_to_dlq(&self, task_type: &str, payload: &str, last_error: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("insert into dead_letter_tasks (task_type, payload, retry_count, last_error) values ($1, to_jsonb($2::text), 0, $3)")
+            .bind(task_type)
+            .bind(payload)
+            .bind(last_error)
+            .execute(&self.connection).await?;
+        Ok(())
+    }
+
+    // newest first, room_id was matched with the room_id in the payload
+    pub async fn list_dead_letter_tasks(&self, task_type: Option<&str>, room_id: Option<&str>, status: &str, limit: i64, offset: i64) -> Result<(Vec<DeadLetterTask>, i64), sqlx::Error> {
+        let filter = "where ($1::text is null or task_type = $1) and ($2::text is null or payload->>'room_id' = $2) and status = $3" ;
+        let tasks = sqlx::query_as::<_, DeadLetterTask>(&format!(
+            "select id, task_type, payload, retry_count, last_error, status, created_at, resolved_at from dead_letter_tasks {} order by created_at desc, id desc limit $4 offset $5",
+            filter
+        ))
+            .bind(task_type)
+            .bind(room_id)
+            .bind(status)
+            .bind(limit)
+            .bind(offset)
+            .fetch_all(&self.connection).await ;
+        let total: Result<i64, sqlx::Error> = sqlx::query_scalar(&format!("select count(*) from dead_letter_tasks {}", filter))
+            .bind(task_type)
+            .bind(room_id)
+            .bind(status)
+            .fetch_one(&self.connection).await ;
+        match (tasks, total) {
+            (Ok(tasks), Ok(total)) => Ok((tasks, total)),
+            (Err(err), _) | (_, Err(err)) => {
+                tracing::error!("error while listing the dead letter tasks {}", err) ;
+                Err(err)
+            }
+        }
+    }
+
+    pub async fn get_dead_letter_task(&self, id: i64) -> Result<Option<DeadLetterTask>, sqlx::Error> {
+        sqlx::query_as::<_, DeadLetterTask>("select id, task_type, payload, retry_count, last_error, status, created_at, resolved_at from dead_letter_tasks where id=$1")
+            .bind(id)
+            .fetch_optional(&self.connection).await
+            .inspect_err(|err| tracing::error!("error while getting the dead letter task {} {}", id, err))
+    }
+
+    // only the tasks which were in the from status were moved, such that two admins never replay the same task
+    pub async fn update_dead_letter_tasks_status(&self, ids: &[i64], from: &str, to: &str) -> Result<Vec<DeadLetterTask>, sqlx::Error> {
+        sqlx::query_as::<_, DeadLetterTask>(
+            "update dead_letter_tasks set status=$3, resolved_at = case when $3 = 'pending' then null else now() end
+             where id = any($1) and status=$2
+             returning id, task_type, payload, retry_count, last_error, status, created_at, resolved_at"
+        )
+            .bind(ids)
+            .bind(from)
+            .bind(to)
+            .fetch_all(&self.connection).await
+            .inspect_err(|err| tracing::error!("error while moving the dead letter tasks from {} to {} {}", from, to, err))
+    }
+
+}
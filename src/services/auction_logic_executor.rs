@@ -1,10 +1,10 @@
 use std::time::Instant;
-use axum::extract::ws::Message;
-use crate::auction::{bid_allowance_handler, broadcast_handler, send_himself, send_message_to_participant};
+use crate::auction::{bid_allowance_handler, broadcast_server_message, participants_count, send_error, send_server_message};
 use crate::models;
 use crate::models::app_state::AppState;
-use crate::models::auction_models::{Bid, BidOutput};
-use crate::models::background_db_tasks::DBCommandsAuctionRoom;
+use crate::models::auction_models::{Bid, BidOutput, RoomSnapshot};
+use crate::models::background_db_tasks::{DBCommandsAuctionRoom, SkipVoted};
+use crate::models::protocol_models::{ErrorCode, ServerMessage};
 use metrics::counter ;
 use crate::services::other::get_previous_team_full_name;
 
@@ -29,10 +29,10 @@ pub async fn start_auction(room_id: &str, participant_id: i32, app_state: &AppSt
     if redis_connection.get_room_meta(room_id).await.unwrap().unwrap().room_creator_id != participant_id {
         counter!("auction_start_denied_total").increment(1);
 
-        send_himself(Message::text("You will not having permissions"), participant_id, room_id, app_state).await;
+        send_error(ErrorCode::NotPermitted, "You will not having permissions", participant_id, room_id, app_state).await;
     } else {
-        if app_state.rooms.read().await.get(room_id).unwrap().len() < 3 {
-            send_himself(Message::text("Min of 3 participants should be in the room to start auction"), participant_id, room_id, app_state).await;
+        if participants_count(room_id, app_state).await < 3 {
+            send_error(ErrorCode::NotEnoughParticipants, "Min of 3 participants should be in the room to start auction", participant_id, room_id, app_state).await;
         } else {
             match redis_connection.set_pause(room_id, false).await {
                 Ok(_) => {
@@ -43,7 +43,7 @@ pub async fn start_auction(room_id: &str, participant_id: i32, app_state: &AppSt
                     metrics::counter!("failed while checking pause status in auction_start").increment(1) ;
                     tracing::error!("error occurred while setting the pause status");
                     tracing::error!("err was {}", err);
-                    send_himself(Message::text("Technical Problem"), participant_id, room_id, app_state).await;
+                    send_error(ErrorCode::ServerError, "Technical Problem", participant_id, room_id, app_state).await;
                 }
             };
 
@@ -62,28 +62,33 @@ pub async fn start_auction(room_id: &str, participant_id: i32, app_state: &AppSt
                     player
                 }
             };
-            let message;
-
             if player.id == 1 {
                 // // changing room-status
-                app_state.auction_room_database_task_executor.send(DBCommandsAuctionRoom::UpdateRoomStatus(models::background_db_tasks::RoomStatus {
+                let room_status = app_state.auction_room_database_task_executor.send(DBCommandsAuctionRoom::UpdateRoomStatus(models::background_db_tasks::RoomStatus {
                     room_id: room_id.to_string(),
                     status: "in_progress".to_string(),
+                    idempotency_key: format!("room_status:{}:in_progress", room_id),
+                    enqueued_at: chrono::Utc::now().timestamp_millis(),
                     retry_count: 0,
                     last_error:"".to_string()
-                })).map_err(|err| {
+                })).await ;
+                // the first player was not put up, the creator can start again
+                if let Err(err) = room_status {
                     metrics::counter!("failed while sending update status in auction_start").increment(1);
-                }).expect("Failed to send");
+                    tracing::error!("{}", err);
+                    send_error(ErrorCode::ServerError, "Technical Problem", participant_id, room_id, app_state).await;
+                    return;
+                }
             }
 
-            message = Message::from(serde_json::to_string(&player).unwrap());
+            let message = ServerMessage::PlayerUp(player.clone());
             // here we are going to add the player as Bid to the redis
             let bid = Bid::new(0, player.id, 0.0, player.base_price, false, false); // no one yet bidded
             redis_connection.update_current_bid(room_id, bid, expiry_time, -1, room_mode,false).await.expect("unable to update the bid");
 
 
             // broadcasting
-            broadcast_handler(message, room_id, app_state).await;
+            broadcast_server_message(&message, room_id, app_state).await;
         }
     }
     // ---------- SUCCESS ----------
@@ -111,12 +116,12 @@ pub async fn start_auction(room_id: &str, participant_id: i32, app_state: &AppSt
     fields(
         room_id = %room_id,
         participant_id = participant_id,
-        team_name = team_name,
         room_mode = room_mode,
-        expiry_time = expiry_time
+        expiry_time = expiry_time,
+        jump_amount = jump_amount
     )
 )]
-pub async fn bid(room_id: &str, participant_id: i32,app_state: &AppState, timer_key: &str, team_name: &str, room_mode: bool, expiry_time: u8) {
+pub async fn bid(room_id: &str, participant_id: i32,app_state: &AppState, timer_key: &str, room_mode: bool, expiry_time: u8, jump_amount: Option<f32>) {
     let redis_connection = app_state.redis_connection.clone() ;
     /*
         If previously the same participant has send the bid, then that shouldn't be considered
@@ -139,58 +144,87 @@ pub async fn bid(room_id: &str, participant_id: i32,app_state: &AppState, timer_
         }
     } ;
     let current_player = redis_connection.get_current_player(room_id).await.unwrap().unwrap() ;
+    let rules = redis_connection.get_room_rules(room_id).await.unwrap() ;
     // if this key exists in the redis then only bids takes place
     if !redis_connection.check_key_exists(timer_key).await.unwrap() {
         tracing::info!("as the key doesn't exists we are not going to take this bid") ;
-        send_himself(Message::text("Bid is Invalid, RTM is taking place"), participant_id, room_id, app_state).await ;
-    } else if (!current_player.is_indian) && (participant.foreign_players_brought >= 8) {
+        send_error(ErrorCode::BidRejected, "Bid is Invalid, RTM is taking place", participant_id, room_id, app_state).await ;
+    } else if (!current_player.is_indian) && (participant.foreign_players_brought >= rules.max_foreign_players) {
         tracing::info!("foreign players has reached max for the participant, so bid becomes invalid") ;
-        send_himself(Message::text("You reached Foreign Player limit"), participant_id, room_id, app_state).await ;
+        send_error(ErrorCode::BidRejected, "You reached Foreign Player limit", participant_id, room_id, app_state).await ;
     } else {
         if redis_connection.is_skipped(room_id, participant_id).await.unwrap() {
             tracing::info!("skipped the player, the bid is not valid any more") ;
-            send_himself(Message::text("Bid is Invalid, you skipped the player"), participant_id, room_id, app_state).await ;
-        } else if app_state.rooms.read().await.get(room_id).unwrap().len() >= 3 {
+            send_error(ErrorCode::BidRejected, "Bid is Invalid, you skipped the player", participant_id, room_id, app_state).await ;
+        } else if participants_count(room_id, app_state).await >= 3 {
             // the participant has bided
             if current_bid.participant_id != participant_id {
-                current_bid.participant_id = participant_id ;
                 let expiry_time_ ;
-                if redis_connection.get_skipped_count(room_id).await.unwrap() as usize == app_state.rooms.read().await.get(room_id).unwrap().len() - 1 {
+                if redis_connection.get_skipped_count(room_id).await.unwrap() as usize == participants_count(room_id, app_state).await - 1 {
                     expiry_time_ = 1 ;
                 }else {
                     expiry_time_ = expiry_time ;
                 }
-                tracing::info!("the current bid {:?}", current_bid);
-                let result =  redis_connection.update_current_bid(room_id, current_bid, expiry_time_, participant_id, room_mode,false).await ;
-                match result {
-                    Ok(amount) => {
-                        let message = Message::from(serde_json::to_string(&BidOutput{
-                            bid_amount: amount,
-                            team: team_name.to_string()
-                        }).unwrap()) ;
-                        broadcast_handler(message,room_id,app_state).await ;
-                    }, Err(err) => {
-                        if err.contains("Bid not allowed") {
-                            send_himself(Message::text(&err), participant_id, room_id, app_state).await ;
-                        }else {
-                            send_himself(Message::text("Technical Issue"), participant_id, room_id, app_state).await ;
+                let bid_amount = match jump_amount {
+                    Some(jump_amount) => {
+                        // a jump bid, the amount was given by the participant, so it should be one of the next steps of the ladder
+                        let jump_amount = (jump_amount * 100.0).round() / 100.0 ;
+                        let minimum_amount = rules.next_bid_amount(current_bid.bid_amount, current_bid.base_price) ;
+                        if jump_amount < minimum_amount {
+                            tracing::info!("jump bid {} was less than the next bid amount {}", jump_amount, minimum_amount) ;
+                            let message = format!("Bid Amount should be at least {}", minimum_amount) ;
+                            send_error(ErrorCode::BidRejected, &message, participant_id, room_id, app_state).await ;
+                            return;
                         }
-
+                        if !rules.is_on_ladder(current_bid.bid_amount, current_bid.base_price, jump_amount) {
+                            tracing::info!("jump bid {} was not on the steps of the ladder", jump_amount) ;
+                            send_error(ErrorCode::BidRejected, "Bid Amount should be on the bid increments of the room", participant_id, room_id, app_state).await ;
+                            return;
+                        }
+                        jump_amount
+                    },
+                    // the increments were taken from the ladder in the room rules
+                    None => rules.next_bid_amount(current_bid.bid_amount, current_bid.base_price)
+                } ;
+                if !bid_allowance_handler(bid_amount, participant.balance, participant.total_players_brought, room_mode, &rules).await {
+                    let message = if room_mode { "Bid not allowed, Check strict mode rules" } else { "Bid not allowed" } ;
+                    send_error(ErrorCode::BidRejected, message, participant_id, room_id, app_state).await ;
+                    return;
+                }
+                let new_bid = Bid::new(participant_id, current_bid.player_id, bid_amount, current_bid.base_price, false, false) ;
+                tracing::info!("the new bid {:?}", new_bid);
+                // the bid was taken only if no other bid, skip or timer expiry happened after we read the current bid
+                match redis_connection.accept_bid(room_id, &current_bid, &new_bid, expiry_time_).await {
+                    Ok(Ok(timer)) => {
+                        redis_connection.publish_timer_deadline(room_id, "bidding", timer).await.expect("failed to publish the timer deadline") ;
+                        let message = ServerMessage::BidPlaced(BidOutput{
+                            bid_amount,
+                            team: participant.team_name.clone()
+                        }) ;
+                        broadcast_server_message(&message,room_id,app_state).await ;
+                    },
+                    Ok(Err(rejection)) => {
+                        metrics::counter!("bid_rejected_total").increment(1) ;
+                        send_error(ErrorCode::BidRejected, rejection.message(), participant_id, room_id, app_state).await ;
+                    },
+                    Err(err) => {
+                        tracing::error!("error while accepting the bid {}", err) ;
+                        send_error(ErrorCode::ServerError, "Technical Issue", participant_id, room_id, app_state).await ;
                     }
                 } ;
             }else {
-                send_himself(Message::text("You are already the highest bidder"), participant_id,room_id,app_state).await
+                send_error(ErrorCode::BidRejected, "You are already the highest bidder", participant_id, room_id, app_state).await
             }
 
         }else{
             //  we are going to stop the auction at this point, we will keep another state in redis, called pause, if it is true then we are going to pause the auction
             // make the auction paused
-            redis_connection.atomic_delete(timer_key).await.unwrap() ;
+            redis_connection.cancel_timer(timer_key).await.unwrap() ;
             // we need update the current_bid as well
             current_bid.participant_id = 0 ;
             current_bid.bid_amount = 0.0 ;
             redis_connection.update_current_bid(room_id, current_bid, 0,-1,room_mode,false).await.unwrap() ;
-            send_himself(Message::text("Min of 3 participants should be in the room to bid"), participant_id,room_id,app_state).await ;
+            send_error(ErrorCode::NotEnoughParticipants, "Min of 3 participants should be in the room to bid", participant_id, room_id, app_state).await ;
         }
     }
 }
@@ -207,13 +241,16 @@ pub async fn bid(room_id: &str, participant_id: i32,app_state: &AppState, timer_
         team_name = team_name
     )
 )]
-pub async fn skip(room_id: &str, participant_id: i32, app_state: &AppState, timer_key: &str, room_mode: bool, text: String, team_name: &str) {
+pub async fn skip(room_id: &str, participant_id: i32, app_state: &AppState, timer_key: &str, room_mode: bool, reason: Option<String>, team_name: &str) {
     let redis_connection = app_state.redis_connection.clone() ;
     // if skip-s, then it's a strict-mode.
     tracing::info!("message skip was received") ;
     // we need to add a state in redis
     let mut skipped_count = redis_connection.mark_skipped(room_id, participant_id).await.unwrap() ;
-    let live_participants_count = { app_state.rooms.read().await.get(room_id).unwrap().len() } as u8;
+    if let Some(current_player) = redis_connection.get_current_player(room_id).await.unwrap() {
+        record_skip_vote(room_id, participant_id, "player", current_player.id, app_state).await ;
+    }
+    let live_participants_count = participants_count(room_id, app_state).await as u8;
     tracing::info!("total participants skipped till now was {}", skipped_count) ;
     tracing::info!("total live participants {}", live_participants_count) ;
     if skipped_count == live_participants_count - 1 {
@@ -237,22 +274,14 @@ pub async fn skip(room_id: &str, participant_id: i32, app_state: &AppState, time
     }
     if skipped_count == live_participants_count {
         if redis_connection.check_key_exists(timer_key).await.unwrap() {
-            redis_connection.atomic_delete(timer_key).await.expect("unable to delete the room inside skip");
+            redis_connection.cancel_timer(timer_key).await.expect("unable to delete the room inside skip");
             let current_bid = redis_connection.get_current_bid(room_id).await.unwrap().unwrap() ;
             redis_connection.update_current_bid(room_id, current_bid,1, -1, room_mode, true).await.unwrap() ;
         }else {
-            let message = "At this Stage Skip won't work";
-            send_himself(Message::text(message), participant_id, room_id, app_state).await ;
+            send_error(ErrorCode::NotPermitted, "At this Stage Skip won't work", participant_id, room_id, app_state).await ;
         }
     }else {
-        let message ;
-        if text.contains("-") {
-            let reason = text.split("-").collect::<Vec<&str>>()[1].parse::<String>().unwrap() ;
-            message = format!("{} was out of bid, due to {}", team_name, reason) ;
-        }else {
-            message = format!("{} skipped the player", team_name) ;
-        }
-        broadcast_handler(Message::text(&message),room_id,app_state).await ;
+        broadcast_server_message(&ServerMessage::PlayerSkipped { team_name: team_name.to_string(), reason },room_id,app_state).await ;
     }
 
 
@@ -275,11 +304,12 @@ pub async fn skip(room_id: &str, participant_id: i32, app_state: &AppState, time
 pub async fn instant_rtm_cancel(room_id: &str, participant_id: i32, app_state: &AppState, room_mode: bool, rtm_timer_key: &str) {
     let redis_connection = app_state.redis_connection.clone() ;
     tracing::info!("cancelling the rtm instantly, where the previous team , don't want to use the rtm for the current player") ;
-    redis_connection.atomic_delete(rtm_timer_key).await.unwrap() ;
-    let mut current_bid = redis_connection.get_current_bid(room_id).await.unwrap().unwrap() ;
-    current_bid.rtm_bid = true ;
-    redis_connection.update_current_bid(room_id, current_bid, 1, -1, room_mode, true).await.unwrap() ;
-    send_message_to_participant(participant_id, String::from("Cancelled the RTM"), room_id, app_state).await ;
+    let current_bid = redis_connection.get_current_bid(room_id).await.unwrap().unwrap() ;
+    let mut bid = current_bid.clone() ;
+    bid.rtm_bid = true ;
+    if settle_rtm(room_id, participant_id, app_state, rtm_timer_key, &current_bid, bid, 1).await {
+        send_server_message(&ServerMessage::RtmCancelled, participant_id, room_id, app_state).await ;
+    }
 }
 
 
@@ -296,12 +326,13 @@ pub async fn instant_rtm_cancel(room_id: &str, participant_id: i32, app_state: &
 pub async fn rtm_cancel(room_id: &str, participant_id: i32, app_state: &AppState, room_mode: bool, rtm_timer_key: &str) {
     let redis_connection = app_state.redis_connection.clone() ;
     tracing::info!("cancelling the offer by the highest bidder") ;
-    redis_connection.atomic_delete(rtm_timer_key).await.unwrap() ;
     // now we are going to send the same bid with expiry 0
-    let mut current_bid = redis_connection.get_current_bid(room_id).await.unwrap().unwrap() ;
-    current_bid.is_rtm = true ;  // where the last bided person is the person who used rtm, so we need to keep it as rtm only, such that his rtms will decreased
-    redis_connection.update_current_bid(room_id, current_bid, 1, -1, room_mode, true).await.unwrap() ;
-    send_message_to_participant(participant_id, String::from("Cancelled the RTM Price"), room_id, app_state).await ;
+    let current_bid = redis_connection.get_current_bid(room_id).await.unwrap().unwrap() ;
+    let mut bid = current_bid.clone() ;
+    bid.is_rtm = true ;  // where the last bided person is the person who used rtm, so we need to keep it as rtm only, such that his rtms will decreased
+    if settle_rtm(room_id, participant_id, app_state, rtm_timer_key, &current_bid, bid, 1).await {
+        send_server_message(&ServerMessage::RtmPriceCancelled, participant_id, room_id, app_state).await ;
+    }
 }
 
 
@@ -316,17 +347,15 @@ pub async fn rtm_cancel(room_id: &str, participant_id: i32, app_state: &AppState
         expiry_time = expiry_time
     )
 )]
-pub async fn use_rtm(room_id: &str, participant_id: i32, app_state: &AppState, room_mode: bool, rtm_timer_key: &str, text: String, expiry_time: u8) {
+pub async fn use_rtm(room_id: &str, participant_id: i32, app_state: &AppState, room_mode: bool, rtm_timer_key: &str, amount: f32, expiry_time: u8) {
     let redis_connection = app_state.redis_connection.clone() ;
-    tracing::info!("rtm was accepted with the following {}",text) ;
+    tracing::info!("rtm was accepted with the amount {}",amount) ;
     // we need to check
     // if this key exists in the redis then no bids takes place
     if redis_connection.check_key_exists(rtm_timer_key).await.unwrap() { // if normal bids were not taking place on in that scenario
-        redis_connection.atomic_delete(rtm_timer_key).await.unwrap();
         // rtm-amount eg : rtm-5.00 means increasing 5.00cr from the current price
-        let mut bid = redis_connection.get_current_bid(room_id).await.unwrap().unwrap() ;
-
-        let amount = text.split("-").collect::<Vec<&str>>()[1].parse::<f32>().unwrap() ;
+        let seen_bid = redis_connection.get_current_bid(room_id).await.unwrap().unwrap() ;
+        let mut bid = seen_bid.clone() ;
 
         // now we are going to check whether the specific participant has the authority to use the rtm, means the current player
         // previous team should be the participant playing team
@@ -340,57 +369,137 @@ pub async fn use_rtm(room_id: &str, participant_id: i32, app_state: &AppState, r
             // here we need to check whether the rtm placer having that much enough money and as well the same other guy having that much enough money
             if rtm_placer_participant.remaining_rtms > 0 {
                 let highest_bidder_participant = redis_connection.get_participant(room_id, bid.participant_id).await.unwrap().unwrap() ;
-                let rtm_placer_participant_bid_allowance = bid_allowance_handler(new_amount, rtm_placer_participant.balance, rtm_placer_participant.total_players_brought, room_mode).await ;
-                let highest_bidder_participant_allowance = bid_allowance_handler(new_amount, highest_bidder_participant.balance, highest_bidder_participant.total_players_brought, room_mode).await ;
+                let rules = redis_connection.get_room_rules(room_id).await.unwrap() ;
+                let rtm_placer_participant_bid_allowance = bid_allowance_handler(new_amount, rtm_placer_participant.balance, rtm_placer_participant.total_players_brought, room_mode, &rules).await ;
+                let highest_bidder_participant_allowance = bid_allowance_handler(new_amount, highest_bidder_participant.balance, highest_bidder_participant.total_players_brought, room_mode, &rules).await ;
                 if rtm_placer_participant_bid_allowance && highest_bidder_participant_allowance {
                     tracing::info!("both having money") ;
                     // creating the new Bid
                     let bid_ = Bid::new(participant_id, bid.player_id, new_amount, bid.base_price, true, false) ;
                     // adding the bid to the redis
-                    match redis_connection.update_current_bid(room_id, bid_, expiry_time, participant_id, room_mode, true).await {
-                        Ok(_) => {},
-                        Err(err) => {
-                            if err.contains("Bid not allowed") {
-                                send_himself(Message::text(&err), participant_id, room_id, &app_state).await ;
-                            }else {
-                                send_himself(Message::text("Technical Issue"), participant_id, room_id, app_state).await ;
-                            }
-                        }
-                    };
-                    send_message_to_participant(bid.participant_id, format!("rtm-amount-{}", new_amount), room_id, app_state).await ;
+                    if settle_rtm(room_id, participant_id, app_state, rtm_timer_key, &seen_bid, bid_, expiry_time).await {
+                        send_server_message(&ServerMessage::RtmAmount { amount: new_amount }, bid.participant_id, room_id, app_state).await ;
+                    }
                     return;
                 }else if rtm_placer_participant_bid_allowance {
                     tracing::info!("rtm bidder has enough money, so bid goes to him") ;
                     // delete the key and add the new bid with expiry 0 seconds
 
                     // new bid
-                    match redis_connection.update_current_bid(room_id, Bid::new(participant_id, bid.player_id, new_amount, bid.base_price, true, false),1, participant_id, room_mode, true).await {
-                        Ok(_) => {},
-                        Err(err) => {
-                            if err.contains("Bid not allowed") {
-                                send_himself(Message::text(&err), participant_id, room_id, app_state).await ;
-                            }else {
-                                send_himself(Message::text("Technical Issue"), participant_id, room_id, app_state).await ;
-                            }
-                        }
-                    };
-                    // send to the highest bidder the reason
-                    send_message_to_participant(bid.participant_id, format!("no balance to accept the bid price of {}",new_amount), room_id, app_state).await ;
+                    if settle_rtm(room_id, participant_id, app_state, rtm_timer_key, &seen_bid, Bid::new(participant_id, bid.player_id, new_amount, bid.base_price, true, false), 1).await {
+                        // send to the highest bidder the reason
+                        send_server_message(&ServerMessage::RtmNotAffordable { amount: new_amount }, bid.participant_id, room_id, app_state).await ;
+                    }
                     return;
                 }else {
                     tracing::info!("only the person having the rtm having the enough money") ;
-                    send_himself(Message::text("Invalid Price, You Lost RTM for this Bid"), participant_id, &room_id, &app_state).await ;
+                    send_error(ErrorCode::RtmRejected, "Invalid Price, You Lost RTM for this Bid", participant_id, &room_id, &app_state).await ;
                 }
             }else{
-                send_himself(Message::text("All RTMS were used"), participant_id, room_id, app_state).await ;
+                send_error(ErrorCode::RtmRejected, "All RTMS were used", participant_id, room_id, app_state).await ;
             }
         }else {
-            send_himself(Message::text("The current player is not in ur team previously"), participant_id, room_id, app_state).await ;
+            send_error(ErrorCode::RtmRejected, "The current player is not in ur team previously", participant_id, room_id, app_state).await ;
         }
         bid.rtm_bid = true ; // it will be rtm_bid , but for remaining rtms will be same, only thing is in subscriber making sure no infinite loop takes place, where we are going to inifinetly if there previous
-        let _ = redis_connection.update_current_bid(room_id, bid, 1, -1, room_mode, true).await.unwrap() ;
+        settle_rtm(room_id, participant_id, app_state, rtm_timer_key, &seen_bid, bid, 1).await ;
     }else {
         tracing::info!("Now no RTM bids were taking place") ;
-        send_himself(Message::text("No RTM Bids are taking place"), participant_id, room_id, app_state).await ;
+        send_error(ErrorCode::RtmRejected, "No RTM Bids are taking place", participant_id, room_id, app_state).await ;
+    }
+}
+
+// skips were kept in postgres along with redis, such that a room rebuilt after redis lost it keeps them
+pub async fn record_skip_vote(room_id: &str, participant_id: i32, kind: &str, target: i32, app_state: &AppState) {
+    let skip = SkipVoted::new(room_id, participant_id, kind, target) ;
+    if let Err(err) = app_state.auction_room_database_task_executor.send(DBCommandsAuctionRoom::SkipVoted(skip)).await {
+        tracing::error!("unable to queue the skip of the participant {} {}", participant_id, err) ;
+    }
+}
+
+/*
+    writes the bid of an RTM action along with its timer, only if the RTM was not resolved by another action or by
+    the expiry of the RTM timer after the current bid was read. returns whether the action was taken.
+*/
+pub async fn settle_rtm(room_id: &str, participant_id: i32, app_state: &AppState, rtm_timer_key: &str, seen_bid: &Bid, bid: Bid, bid_expiry: u8) -> bool {
+    let redis_connection = app_state.redis_connection.clone() ;
+    let phase = if bid.is_rtm { "rtm" } else { "bidding" } ;
+    match redis_connection.resolve_rtm(room_id, rtm_timer_key, seen_bid, &bid, bid_expiry).await {
+        Ok(Some(timer)) => {
+            redis_connection.publish_timer_deadline(room_id, phase, timer).await.expect("failed to publish the timer deadline") ;
+            true
+        },
+        Ok(None) => {
+            metrics::counter!("rtm_rejected_total").increment(1) ;
+            send_error(ErrorCode::RtmRejected, "RTM was already closed", participant_id, room_id, app_state).await ;
+            false
+        },
+        Err(err) => {
+            tracing::error!("error while resolving the rtm {}", err) ;
+            send_error(ErrorCode::ServerError, "Technical Issue", participant_id, room_id, app_state).await ;
+            false
+        }
     }
-}
\ No newline at end of file
+}
+
+#[tracing::instrument(
+    name = "send_room_snapshot",
+    skip(app_state),
+    fields(
+        room_id = %room_id,
+        participant_id = participant_id,
+        room_mode = room_mode
+    )
+)]
+pub async fn send_room_snapshot(room_id: &str, participant_id: i32, app_state: &AppState, room_mode: bool) {
+    match room_snapshot(room_id, app_state, room_mode).await {
+        Ok(Some(snapshot)) => {
+            send_server_message(&ServerMessage::RoomSnapshot(Box::new(snapshot)), participant_id, room_id, app_state).await ;
+        },
+        Ok(None) => {
+            tracing::info!("room was not in redis, so no snapshot") ;
+        },
+        Err(err) => {
+            tracing::error!("error while creating the room snapshot {}", err) ;
+            send_error(ErrorCode::ServerError, "Technical Issue", participant_id, room_id, app_state).await ;
+        }
+    }
+}
+
+
+async fn room_snapshot(room_id: &str, app_state: &AppState, room_mode: bool) -> Result<Option<RoomSnapshot>, redis::RedisError> {
+    let redis_connection = app_state.redis_connection.clone() ;
+    let Some(room_meta) = redis_connection.get_room_meta(room_id).await? else {
+        return Ok(None);
+    } ;
+    let current_player = redis_connection.get_current_player(room_id).await? ;
+    let current_bid = redis_connection.get_current_bid(room_id).await? ;
+
+    let mut participants = vec![] ;
+    for participant_id in redis_connection.list_participants(room_id).await? {
+        if let Some(participant) = redis_connection.get_participant(room_id, participant_id).await? {
+            participants.push(participant) ;
+        }
+    }
+    let highest_bidder = current_bid.as_ref()
+        .and_then(|bid| participants.iter().find(|participant| participant.id == bid.participant_id))
+        .map(|participant| participant.team_name.clone()) ;
+
+    let rtm_time_remaining = redis_connection.get_ttl(&format!("auction:timer:rtms:{}", room_id)).await? ;
+    Ok(Some(RoomSnapshot {
+        room_creator_id: room_meta.room_creator_id,
+        pause: room_meta.pause,
+        strict_mode: room_mode,
+        current_player,
+        current_bid,
+        highest_bidder,
+        bid_time_remaining: redis_connection.get_ttl(&format!("auction:timer:{}", room_id)).await?,
+        rtm_in_progress: rtm_time_remaining.is_some(),
+        rtm_time_remaining,
+        skipped: redis_connection.list_skipped(room_id).await?,
+        skipped_pool: redis_connection.list_skipped_pool(room_id).await?,
+        participants,
+        bots: redis_connection.list_bots(room_id).await?,
+        autopilot: redis_connection.list_autopilot(room_id).await?,
+    }))
+}
@@ -1,9 +1,12 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 use std::sync::Arc;
 use redis::{AsyncCommands, Commands, RedisResult};
-use crate::auction::{bid_allowance_handler, broadcast_handler, send_himself, send_message_to_participant};
+use crate::auction::{bid_allowance_handler, broadcast_server_message, participants_count, send_server_message};
 use crate::models::app_state::{AppState, Player, PoolPlayer};
-use crate::models::auction_models::{AuctionParticipant, Bid, RoomMeta, SoldPlayer};
+use crate::models::auction_models::{amount_in_hundredths, AuctionParticipant, Bid, BidRejection, RoomMeta, RoomRules, SoldPlayer, TimerClaim, TimerDeadline, TimerWindow};
+use crate::models::protocol_models::{ErrorCode, ServerMessage};
+use crate::models::room_models::{RoomCommand, RoomEvent};
+use crate::services::room_actor::existing_room;
 
 #[derive(Debug, Clone)]
 pub struct RedisConnection {
@@ -166,6 +169,39 @@ impl RedisConnection {
 
     }
 
+    pub async fn set_room_rules(&self, room_id: &str, rules: &RoomRules) -> Result<(), redis::RedisError> {
+        let mut conn = self.connection.clone();
+
+        let key = format!("room:{}:rules", room_id);
+        let rules = serde_json::to_string(rules).map_err(|_| {
+            redis::RedisError::from((
+                redis::ErrorKind::TypeError,
+                "Unable to serialize room rules"
+            ))
+        })?;
+
+        conn.set::<_, _, ()>(&key, rules).await?;
+        Ok(())
+    }
+
+    // rooms which were opened in redis before rules existed will not have the key, those get the default rules
+    pub async fn get_room_rules(&self, room_id: &str) -> Result<RoomRules, redis::RedisError> {
+        let mut conn = self.connection.clone();
+
+        let key = format!("room:{}:rules", room_id);
+        let rules: Option<String> = conn.get(&key).await?;
+
+        match rules {
+            Some(rules) => serde_json::from_str(&rules).map_err(|_| {
+                redis::RedisError::from((
+                    redis::ErrorKind::TypeError,
+                    "Invalid JSON for room rules"
+                ))
+            }),
+            None => Ok(RoomRules::default())
+        }
+    }
+
     pub async fn set_current_player(&self, room_id: &str, player: Player) -> Result<(), redis::RedisError> {
         let mut conn = self.connection.clone();
 
@@ -214,6 +250,28 @@ impl RedisConnection {
         Ok(())
     }
 
+    // players brought by the participant for each role (BAT, BOWL, AR), used by the bots to balance the squad
+    pub async fn increment_role_count(&self, room_id: &str, participant_id: i32, role: &str) -> Result<(), redis::RedisError> {
+        let mut conn = self.connection.clone();
+        let key = format!("room:{}:participant:{}:roles", room_id, participant_id);
+        conn.hincr::<_, _, _, ()>(&key, role, 1).await?;
+        Ok(())
+    }
+
+    pub async fn set_role_counts(&self, room_id: &str, participant_id: i32, bat_count: i32, bowl_count: i32, ar_count: i32) -> Result<(), redis::RedisError> {
+        let mut conn = self.connection.clone();
+        let key = format!("room:{}:participant:{}:roles", room_id, participant_id);
+        conn.hset_multiple::<_, _, _, ()>(&key, &[("BAT", bat_count), ("BOWL", bowl_count), ("AR", ar_count)]).await?;
+        Ok(())
+    }
+
+    pub async fn get_role_counts(&self, room_id: &str, participant_id: i32) -> Result<HashMap<String, i32>, redis::RedisError> {
+        let mut conn = self.connection.clone();
+        let key = format!("room:{}:participant:{}:roles", room_id, participant_id);
+        let role_counts: HashMap<String, i32> = conn.hgetall(&key).await?;
+        Ok(role_counts)
+    }
+
     pub async fn increment_total_players_brought(&self, room_id: &str, participant_id: i32) -> Result<(), redis::RedisError> {
         let mut conn = self.connection.clone();
 
@@ -426,31 +484,24 @@ impl RedisConnection {
             // in cases where, bid is not supposed to increment like in rtm cases, where we infront increment the bid
             // and we will pass new bid , in such cases no need to increment and also in skip cases the highest bidder
             // will get the players, in such case also no need to increment, so this is very important in those cases.
-            if previous_bid_amount == 0.0 {
-                next_bid_increment = bid.base_price;
-            } else if previous_bid_amount < 1.0 {
-                // we are going to increment by 0.05
-                next_bid_increment = 0.05;
-            } else if previous_bid_amount < 10.0 {
-                next_bid_increment = 0.10;
-            } else {
-                next_bid_increment = 0.25;
-            }
-            tracing::info!("incrementing bid by {}", next_bid_increment);
-            next_bid_increment = round_two_decimals(bid.bid_amount + next_bid_increment);
+            // the increments were taken from the ladder in the room rules
+            let rules = self.get_room_rules(room_id).await.expect("unable to get room rules");
+            next_bid_increment = rules.next_bid_amount(previous_bid_amount, bid.base_price);
             tracing::info!("after increment the bid amount was {}", next_bid_increment);
             bid.bid_amount = next_bid_increment;
             if participant_id != -1 {
                 let participant = self.get_participant(room_id, participant_id).await.expect("team name not found").expect("no participant found");
-                allowed = bid_allowance_handler(bid.bid_amount, participant.balance, participant.total_players_brought,strict_mode).await;
+                allowed = bid_allowance_handler(bid.bid_amount, participant.balance, participant.total_players_brought,strict_mode, &rules).await;
             }
         }
         if allowed {
             tracing::info!("the bid we are currently storing was {:?}", bid);
+            let phase = if bid.is_rtm { "rtm" } else { "bidding" } ;
             self.set_current_bid(room_id, bid).await.expect("failed to set current bid");
 
             if bid_expiry != 0 {
-                conn.set_ex::<_,_, ()>(&timer_key, "active", bid_expiry as u64).await.expect("failed to set timer key");
+                let timer = self.set_timer(&timer_key, "active", bid_expiry).await.expect("failed to set timer key");
+                self.publish_timer_deadline(room_id, phase, timer).await.expect("failed to publish the timer deadline");
             }
             Ok(next_bid_increment)
         }else{
@@ -464,6 +515,179 @@ impl RedisConnection {
         }
     }
 
+    /*
+        bid acceptance was done in a single script, such that two bids at the same time or a bid after the timer
+        was expired can not be taken. the bid was taken only if the current bid was still the one the bidder has seen,
+        the timer was running and the bidder not skipped the player. then the bid was written and the timer was reset.
+        returns the reset timer, or the rejection reason when the bid was not taken.
+    */
+    pub async fn accept_bid(&self, room_id: &str, seen_bid: &Bid, bid: &Bid, bid_expiry: u8) -> Result<Result<TimerWindow, BidRejection>, redis::RedisError> {
+        let mut conn = self.connection.clone();
+        let (accepted, reason, server_time, deadline): (i32, String, i64, i64) = redis::Script::new(ACCEPT_BID_SCRIPT)
+            .key(format!("room:{}:current_bid", room_id))
+            .key(format!("auction:timer:{}", room_id))
+            .key(format!("room:{}:skip_state", room_id))
+            .key(TIMERS_ZSET)
+            .arg(seen_bid.participant_id)
+            .arg(seen_bid.player_id)
+            .arg(amount_in_hundredths(seen_bid.bid_amount))
+            .arg(bid.participant_id)
+            .arg(bid.bid_amount)
+            .arg(bid_expiry)
+            .invoke_async(&mut conn)
+            .await?;
+        if accepted == 1 {
+            return Ok(Ok(TimerWindow { server_time, deadline }));
+        }
+        tracing::info!("bid of participant {} was rejected due to {}", bid.participant_id, reason);
+        Ok(Err(BidRejection::from_reason(&reason).unwrap_or(BidRejection::BiddingClosed)))
+    }
+
+    /*
+        every RTM action (using the RTM, accepting or cancelling the RTM price) was resolved in a single script, such that
+        two RTM actions or an RTM action and the expiry of the RTM timer can not both be taken. the action was taken only if
+        the RTM timer was still running and the current bid was still the one that was read, then the RTM timer was
+        deleted, the bid was written and the timer of the bid was set. returns none when the action was not taken.
+    */
+    pub async fn resolve_rtm(&self, room_id: &str, rtm_timer_key: &str, seen_bid: &Bid, bid: &Bid, bid_expiry: u8) -> Result<Option<TimerWindow>, redis::RedisError> {
+        let mut conn = self.connection.clone();
+        let timer_key = if bid.is_rtm {
+            format!("auction:timer:rtms:{}", room_id)
+        } else {
+            format!("auction:timer:{}", room_id)
+        };
+        let (resolved, server_time, deadline): (i32, i64, i64) = redis::Script::new(RESOLVE_RTM_SCRIPT)
+            .key(format!("room:{}:current_bid", room_id))
+            .key(rtm_timer_key)
+            .key(timer_key)
+            .key(TIMERS_ZSET)
+            .key(TIMER_CLAIMS)
+            .arg(seen_bid.participant_id)
+            .arg(seen_bid.player_id)
+            .arg(amount_in_hundredths(seen_bid.bid_amount))
+            .arg(bid.participant_id)
+            .arg(bid.bid_amount)
+            .arg(if bid.is_rtm { 1 } else { 0 })
+            .arg(if bid.rtm_bid { 1 } else { 0 })
+            .arg(bid_expiry)
+            .invoke_async(&mut conn)
+            .await?;
+        if resolved == 1 {
+            return Ok(Some(TimerWindow { server_time, deadline }));
+        }
+        tracing::info!("rtm action of participant {} was not taken, the rtm was already resolved", bid.participant_id);
+        Ok(None)
+    }
+
+    // every time a timer was set, the deadline was broadcasted to the room through the channel of the room
+    pub async fn publish_timer_deadline(&self, room_id: &str, phase: &str, timer: TimerWindow) -> Result<(), redis::RedisError> {
+        let deadline = TimerDeadline {
+            room_id: room_id.to_string(),
+            phase: phase.to_string(),
+            deadline: timer.deadline,
+            server_time: timer.server_time,
+        };
+        let event = RoomEvent::new(None, ServerMessage::Timer(deadline).to_message()).expect("timer was a text message");
+        self.publish_room_event(room_id, &event).await
+    }
+
+    // messages of the room were delivered by listen_for_room_events of every server
+    pub async fn publish_room_event(&self, room_id: &str, event: &RoomEvent) -> Result<(), redis::RedisError> {
+        let mut conn = self.connection.clone();
+        conn.publish::<_, _, ()>(format!("room:{}:events", room_id), serde_json::to_string(event).unwrap()).await?;
+        Ok(())
+    }
+
+    /*
+        presence of the room was a sorted set of "participant_id:node_id" members scored with the last seen time,
+        each server refreshes the members of its own connections, such that the members of a crashed server goes stale.
+    */
+    pub async fn refresh_presence(&self, room_id: &str, participant_ids: &[i32], node_id: &str) -> Result<(), redis::RedisError> {
+        let mut conn = self.connection.clone();
+        let key = format!("room:{}:presence", room_id);
+        let now = Utc::now().timestamp_millis();
+        let mut pipe = redis::pipe();
+        pipe.cmd("ZREMRANGEBYSCORE").arg(&key).arg("-inf").arg(now - PRESENCE_TTL_MS).ignore();
+        for participant_id in participant_ids {
+            pipe.cmd("ZADD").arg(&key).arg(now).arg(format!("{}:{}", participant_id, node_id)).ignore();
+        }
+        pipe.query_async::<()>(&mut conn).await
+    }
+
+    pub async fn remove_presence(&self, room_id: &str, participant_id: i32, node_id: &str) -> Result<(), redis::RedisError> {
+        let mut conn = self.connection.clone();
+        conn.zrem::<_, _, ()>(format!("room:{}:presence", room_id), format!("{}:{}", participant_id, node_id)).await
+    }
+
+    /*
+        participants disconnected within the grace period, scored with the end of their grace period (ms). it was kept
+        next to the presence, such that a reconnect to any of the servers ends the grace period started by another one.
+        the score was also the token of the disconnect, a later disconnect of the same participant replaces it.
+    */
+    pub async fn start_reconnect_grace(&self, room_id: &str, participant_id: i32, until: i64) -> Result<(), redis::RedisError> {
+        let mut conn = self.connection.clone();
+        let key = format!("room:{}:reconnecting", room_id);
+        let mut pipe = redis::pipe();
+        // grace periods of a crashed server were never ended, so they were removed once they were over
+        pipe.cmd("ZREMRANGEBYSCORE").arg(&key).arg("-inf").arg(Utc::now().timestamp_millis()).ignore();
+        pipe.cmd("ZADD").arg(&key).arg(until).arg(participant_id).ignore();
+        pipe.query_async::<()>(&mut conn).await
+    }
+
+    // true if the participant was within the grace period
+    pub async fn stop_reconnect_grace(&self, room_id: &str, participant_id: i32) -> Result<bool, redis::RedisError> {
+        let mut conn = self.connection.clone();
+        let removed: i32 = conn.zrem(format!("room:{}:reconnecting", room_id), participant_id).await?;
+        Ok(removed == 1)
+    }
+
+    // true if the grace period was still the one started with until, then he was not reconnected
+    pub async fn end_reconnect_grace(&self, room_id: &str, participant_id: i32, until: i64) -> Result<bool, redis::RedisError> {
+        let mut conn = self.connection.clone();
+        let script = r#"
+            local score = redis.call('ZSCORE', KEYS[1], ARGV[1])
+            if score and tonumber(score) == tonumber(ARGV[2]) then
+                redis.call('ZREM', KEYS[1], ARGV[1])
+                return 1
+            end
+            return 0
+        "#;
+        let ended: i32 = redis::Script::new(script)
+            .key(format!("room:{}:reconnecting", room_id))
+            .arg(participant_id)
+            .arg(until)
+            .invoke_async(&mut conn)
+            .await?;
+        Ok(ended == 1)
+    }
+
+    pub async fn reconnecting_participants(&self, room_id: &str) -> Result<Vec<i32>, redis::RedisError> {
+        let mut conn = self.connection.clone();
+        conn.zrangebyscore(format!("room:{}:reconnecting", room_id), Utc::now().timestamp_millis(), "+inf").await
+    }
+
+    // true if any other server was having a connection of the room
+    pub async fn is_served_by_other_nodes(&self, room_id: &str, node_id: &str) -> Result<bool, redis::RedisError> {
+        let mut conn = self.connection.clone();
+        let since = Utc::now().timestamp_millis() - PRESENCE_TTL_MS;
+        let members: Vec<String> = conn.zrangebyscore(format!("room:{}:presence", room_id), since, "+inf").await?;
+        let own_suffix = format!(":{}", node_id);
+        Ok(members.iter().any(|member| !member.ends_with(&own_suffix)))
+    }
+
+    // participants having at least one connection on any of the servers
+    pub async fn present_participants(&self, room_id: &str) -> Result<Vec<i32>, redis::RedisError> {
+        let mut conn = self.connection.clone();
+        let since = Utc::now().timestamp_millis() - PRESENCE_TTL_MS;
+        let members: Vec<String> = conn.zrangebyscore(format!("room:{}:presence", room_id), since, "+inf").await?;
+        let mut participants: Vec<i32> = members.iter()
+            .filter_map(|member| member.split(':').next()?.parse().ok())
+            .collect();
+        participants.sort_unstable();
+        participants.dedup();
+        Ok(participants)
+    }
+
     pub async fn check_room_existence(&self, room_id: &str) -> Result<bool, redis::RedisError> {
         let mut conn = self.connection.clone();
 
@@ -550,6 +774,20 @@ impl RedisConnection {
         Ok(skip_count)
     }
     
+    pub async fn list_skipped(&self, room_id: &str) -> Result<Vec<i32>, redis::RedisError> {
+        let mut conn = self.connection.clone();
+        let key = format!("room:{}:skip_state", room_id);
+        let skipped: Vec<i32> = conn.smembers(&key).await?;
+        Ok(skipped)
+    }
+
+    pub async fn list_skipped_pool(&self, room_id: &str) -> Result<Vec<i32>, redis::RedisError> {
+        let mut conn = self.connection.clone();
+        let key = format!("room:{}:skipped_pool", room_id);
+        let skipped_pool: Vec<i32> = conn.smembers(&key).await?;
+        Ok(skipped_pool)
+    }
+
     pub async fn get_skipped_count(&self, room_id: &str) -> Result<u8, redis::RedisError> {
         let mut conn = self.connection.clone();
         let key = format!("room:{}:skip_state", room_id);
@@ -600,6 +838,134 @@ impl RedisConnection {
         }
     }
 
+    // proxy bids of a player, room_id:proxy_bids:player_id is the key and field was participant_id with the max amount
+    pub async fn set_room_mode(&self, room_id: &str, room_mode: bool) -> Result<(), redis::RedisError> {
+        let mut conn = self.connection.clone();
+        let key = format!("room:{}:strict_mode", room_id);
+        conn.set::<_, _, ()>(&key, room_mode).await?;
+        Ok(())
+    }
+
+    pub async fn get_room_mode(&self, room_id: &str) -> Result<Option<bool>, redis::RedisError> {
+        let mut conn = self.connection.clone();
+        let key = format!("room:{}:strict_mode", room_id);
+        let room_mode: Option<bool> = conn.get(&key).await?;
+        Ok(room_mode)
+    }
+
+    pub async fn set_proxy_bid(&self, room_id: &str, player_id: i32, participant_id: i32, max_amount: f32) -> Result<(), redis::RedisError> {
+        let mut conn = self.connection.clone();
+        let key = format!("room:{}:proxy_bids:{}", room_id, player_id);
+        conn.hset::<_, _, _, ()>(&key, participant_id, max_amount).await?;
+        Ok(())
+    }
+
+    pub async fn remove_proxy_bid(&self, room_id: &str, player_id: i32, participant_id: i32) -> Result<(), redis::RedisError> {
+        let mut conn = self.connection.clone();
+        let key = format!("room:{}:proxy_bids:{}", room_id, player_id);
+        conn.hdel::<_, _, ()>(&key, participant_id).await?;
+        Ok(())
+    }
+
+    pub async fn get_proxy_bids(&self, room_id: &str, player_id: i32) -> Result<HashMap<i32, f32>, redis::RedisError> {
+        let mut conn = self.connection.clone();
+        let key = format!("room:{}:proxy_bids:{}", room_id, player_id);
+        let proxy_bids: HashMap<i32, f32> = conn.hgetall(&key).await?;
+        Ok(proxy_bids)
+    }
+
+    pub async fn clear_proxy_bids(&self, room_id: &str, player_id: i32) -> Result<(), redis::RedisError> {
+        let mut conn = self.connection.clone();
+        let key = format!("room:{}:proxy_bids:{}", room_id, player_id);
+        conn.del::<_, ()>(&key).await?;
+        Ok(())
+    }
+
+    // bots of the room, they were also in the participants set but were not having any connection
+    pub async fn add_bot(&self, room_id: &str, participant_id: i32) -> Result<(), redis::RedisError> {
+        let mut conn = self.connection.clone();
+        let key = format!("room:{}:bots", room_id);
+        conn.sadd::<_, _, ()>(&key, participant_id).await?;
+        Ok(())
+    }
+
+    pub async fn list_bots(&self, room_id: &str) -> Result<Vec<i32>, redis::RedisError> {
+        let mut conn = self.connection.clone();
+        let key = format!("room:{}:bots", room_id);
+        let bots: Vec<i32> = conn.smembers(&key).await?;
+        Ok(bots)
+    }
+
+    pub async fn get_bots_count(&self, room_id: &str) -> Result<usize, redis::RedisError> {
+        let mut conn = self.connection.clone();
+        let key = format!("room:{}:bots", room_id);
+        let count: usize = conn.scard(&key).await?;
+        Ok(count)
+    }
+
+    pub async fn is_bot(&self, room_id: &str, participant_id: i32) -> Result<bool, redis::RedisError> {
+        let mut conn = self.connection.clone();
+        let key = format!("room:{}:bots", room_id);
+        let is_bot: bool = conn.sismember(&key, participant_id).await?;
+        Ok(is_bot)
+    }
+
+    // participants who opted for the autopilot, the autopilot starts only when they got disconnected
+    pub async fn set_autopilot_opt_in(&self, room_id: &str, participant_id: i32, opt_in: bool) -> Result<(), redis::RedisError> {
+        let mut conn = self.connection.clone();
+        let key = format!("room:{}:autopilot_opt_in", room_id);
+        if opt_in {
+            conn.sadd::<_, _, ()>(&key, participant_id).await?;
+        } else {
+            conn.srem::<_, _, ()>(&key, participant_id).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn is_autopilot_opted_in(&self, room_id: &str, participant_id: i32) -> Result<bool, redis::RedisError> {
+        let mut conn = self.connection.clone();
+        let key = format!("room:{}:autopilot_opt_in", room_id);
+        let opted_in: bool = conn.sismember(&key, participant_id).await?;
+        Ok(opted_in)
+    }
+
+    // disconnected participants whose team was on autopilot
+    pub async fn start_autopilot(&self, room_id: &str, participant_id: i32) -> Result<(), redis::RedisError> {
+        let mut conn = self.connection.clone();
+        let key = format!("room:{}:autopilot", room_id);
+        conn.sadd::<_, _, ()>(&key, participant_id).await?;
+        Ok(())
+    }
+
+    // returns true if the participant was on autopilot
+    pub async fn stop_autopilot(&self, room_id: &str, participant_id: i32) -> Result<bool, redis::RedisError> {
+        let mut conn = self.connection.clone();
+        let key = format!("room:{}:autopilot", room_id);
+        let removed: i32 = conn.srem(&key, participant_id).await?;
+        Ok(removed == 1)
+    }
+
+    pub async fn list_autopilot(&self, room_id: &str) -> Result<Vec<i32>, redis::RedisError> {
+        let mut conn = self.connection.clone();
+        let key = format!("room:{}:autopilot", room_id);
+        let participants: Vec<i32> = conn.smembers(&key).await?;
+        Ok(participants)
+    }
+
+    pub async fn get_autopilot_count(&self, room_id: &str) -> Result<usize, redis::RedisError> {
+        let mut conn = self.connection.clone();
+        let key = format!("room:{}:autopilot", room_id);
+        let count: usize = conn.scard(&key).await?;
+        Ok(count)
+    }
+
+    pub async fn is_on_autopilot(&self, room_id: &str, participant_id: i32) -> Result<bool, redis::RedisError> {
+        let mut conn = self.connection.clone();
+        let key = format!("room:{}:autopilot", room_id);
+        let on_autopilot: bool = conn.sismember(&key, participant_id).await?;
+        Ok(on_autopilot)
+    }
+
     pub async fn check_participant(&self, room_id: &str, participant_id: i32) -> Result<bool, redis::RedisError>{
         let mut conn = self.connection.clone();
         let key = format!("room:{}:participants", room_id);
@@ -847,21 +1213,326 @@ impl RedisConnection {
     }
 
 
-    pub async fn atomic_delete(&self, key: &str) -> redis::RedisResult<i32> {
+    // deletes the timer along with its deadline in the schedule, such that it will not be expired
+    pub async fn cancel_timer(&self, timer_key: &str) -> redis::RedisResult<i32> {
         let mut conn = self.connection.clone();
         let script = r#"
+        redis.call('ZREM', KEYS[2], KEYS[1])
+        redis.call('HDEL', KEYS[3], KEYS[1])
         local existed = redis.call('DEL', KEYS[1])
         return existed
     "#;
 
         let result: i32 = redis::Script::new(script)
-            .key(key)
+            .key(timer_key)
+            .key(TIMERS_ZSET)
+            .key(TIMER_CLAIMS)
             .invoke_async(&mut conn)
             .await?;
 
         Ok(result)
     }
 
+    /*
+        every timer was a key with the expiry, along with its deadline in the timers sorted set. the key tells whether
+        the timer was running, the sorted set tells when it has to be expired. deadlines were taken from the redis
+        clock, such that all the servers agree on them. a new timer replaces the claim of the previous one.
+    */
+    // the deadline was from the redis clock, the same one the scheduler claims the due timers with
+    pub async fn set_timer(&self, timer_key: &str, value: &str, seconds: u8) -> redis::RedisResult<TimerWindow> {
+        let mut conn = self.connection.clone();
+        let script = r#"
+        redis.call('SET', KEYS[1], ARGV[1], 'EX', ARGV[2])
+        local time = redis.call('TIME')
+        local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+        local deadline = now + tonumber(ARGV[2]) * 1000
+        redis.call('ZADD', KEYS[2], deadline, KEYS[1])
+        redis.call('HDEL', KEYS[3], KEYS[1])
+        return {now, deadline}
+    "#;
+
+        let (server_time, deadline): (i64, i64) = redis::Script::new(script)
+            .key(timer_key)
+            .key(TIMERS_ZSET)
+            .key(TIMER_CLAIMS)
+            .arg(value)
+            .arg(seconds)
+            .invoke_async(&mut conn)
+            .await?;
+
+        Ok(TimerWindow { server_time, deadline })
+    }
+
+    /*
+        timers whose deadline was passed were claimed and deleted in a single script, so only one server claims a
+        timer and no bid can be taken after it was claimed. the claim was a lease, the timer stays in the schedule
+        until the lease ends, such that a claim of a server which was down before completing it was claimed again.
+    */
+    pub async fn claim_due_timers(&self, limit: usize, node_id: &str) -> redis::RedisResult<Vec<TimerClaim>> {
+        let mut conn = self.connection.clone();
+        let script = r#"
+        local time = redis.call('TIME')
+        local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+        local due = redis.call('ZRANGEBYSCORE', KEYS[1], '-inf', now, 'LIMIT', 0, ARGV[1])
+        local claims = {}
+        for _, timer_key in ipairs(due) do
+            local previous = redis.call('HGET', KEYS[2], timer_key) or ''
+            local claim = ARGV[3] .. ':' .. now
+            redis.call('ZADD', KEYS[1], now + tonumber(ARGV[2]), timer_key)
+            redis.call('HSET', KEYS[2], timer_key, claim)
+            redis.call('DEL', timer_key)
+            table.insert(claims, timer_key)
+            table.insert(claims, claim)
+            table.insert(claims, previous)
+        end
+        return claims
+    "#;
+
+        let claims: Vec<String> = redis::Script::new(script)
+            .key(TIMERS_ZSET)
+            .key(TIMER_CLAIMS)
+            .arg(limit)
+            .arg(TIMER_CLAIM_LEASE_MS)
+            .arg(node_id)
+            .invoke_async(&mut conn)
+            .await?;
+
+        Ok(claims.chunks_exact(3).map(|claim| TimerClaim {
+            timer_key: claim[0].clone(),
+            claim: claim[1].clone(),
+            previous_claim: (!claim[2].is_empty()).then(|| claim[2].clone()),
+        }).collect())
+    }
+
+    // the timer was removed from the schedule, unless a new timer was set while the claim was handled
+    pub async fn complete_timer_claim(&self, timer_key: &str, claim: &str) -> redis::RedisResult<bool> {
+        let mut conn = self.connection.clone();
+        let script = r#"
+        if redis.call('HGET', KEYS[2], KEYS[1]) ~= ARGV[1] then
+            return 0
+        end
+        redis.call('HDEL', KEYS[2], KEYS[1])
+        redis.call('ZREM', KEYS[3], KEYS[1])
+        return 1
+    "#;
+
+        let completed: i32 = redis::Script::new(script)
+            .key(timer_key)
+            .key(TIMER_CLAIMS)
+            .key(TIMERS_ZSET)
+            .arg(claim)
+            .invoke_async(&mut conn)
+            .await?;
+        Ok(completed == 1)
+    }
+
+    /*
+        running timers of the room were stopped along with their remaining time, used while the server was shutting
+        down, such that the bidders will not lose the time while they were reconnecting.
+    */
+    pub async fn pause_timers(&self, room_id: &str) -> redis::RedisResult<i32> {
+        let mut conn = self.connection.clone();
+        let script = r#"
+        local paused = 0
+        for i = 1, 2 do
+            local remaining = redis.call('PTTL', KEYS[i])
+            if remaining > 0 then
+                redis.call('HSET', KEYS[3], KEYS[i], remaining)
+                redis.call('ZREM', KEYS[4], KEYS[i])
+                redis.call('HDEL', KEYS[5], KEYS[i])
+                redis.call('DEL', KEYS[i])
+                paused = paused + 1
+            end
+        end
+        return paused
+    "#;
+
+        redis::Script::new(script)
+            .key(format!("auction:timer:{}", room_id))
+            .key(format!("auction:timer:rtms:{}", room_id))
+            .key(format!("room:{}:paused_timers", room_id))
+            .key(TIMERS_ZSET)
+            .key(TIMER_CLAIMS)
+            .invoke_async(&mut conn)
+            .await
+    }
+
+    // paused timers were started again with their remaining time, false if the room was not having them
+    pub async fn resume_timers(&self, room_id: &str) -> redis::RedisResult<bool> {
+        let mut conn = self.connection.clone();
+        let key = format!("room:{}:paused_timers", room_id);
+        let paused: HashMap<String, i64> = redis::pipe()
+            .atomic()
+            .hgetall(&key)
+            .del(&key).ignore()
+            .query_async::<(HashMap<String, i64>,)>(&mut conn)
+            .await?
+            .0;
+        for (timer_key, remaining) in paused.iter() {
+            let seconds = ((*remaining + 999) / 1000).clamp(1, u8::MAX as i64) as u8;
+            let timer = self.set_timer(timer_key, "active", seconds).await?;
+            let phase = if timer_key.contains(":rtms:") { "rtm" } else { "bidding" };
+            self.publish_timer_deadline(room_id, phase, timer).await?;
+        }
+        Ok(!paused.is_empty())
+    }
+
+    // a new timer or a claim of another server replaces the claim
+    pub async fn is_current_claim(&self, timer_key: &str, claim: &str) -> redis::RedisResult<bool> {
+        let mut conn = self.connection.clone();
+        let current: Option<String> = conn.hget(TIMER_CLAIMS, timer_key).await?;
+        Ok(current.as_deref() == Some(claim))
+    }
+
+    /*
+        claims a timer which was neither running nor in the schedule, used by the recovery of the rooms whose timer
+        was lost. the claim was leased like the scheduler claims, so only one server recovers the room.
+    */
+    pub async fn claim_lost_timer(&self, timer_key: &str, claim: &str) -> redis::RedisResult<bool> {
+        let mut conn = self.connection.clone();
+        let script = r#"
+        if redis.call('EXISTS', KEYS[1]) == 1 or redis.call('ZSCORE', KEYS[2], KEYS[1]) then
+            return 0
+        end
+        local time = redis.call('TIME')
+        local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+        redis.call('ZADD', KEYS[2], now + tonumber(ARGV[2]), KEYS[1])
+        redis.call('HSET', KEYS[3], KEYS[1], ARGV[1])
+        return 1
+    "#;
+
+        let claimed: i32 = redis::Script::new(script)
+            .key(timer_key)
+            .key(TIMERS_ZSET)
+            .key(TIMER_CLAIMS)
+            .arg(claim)
+            .arg(TIMER_CLAIM_LEASE_MS)
+            .invoke_async(&mut conn)
+            .await?;
+        Ok(claimed == 1)
+    }
+
+    pub async fn is_timer_scheduled(&self, timer_key: &str) -> redis::RedisResult<bool> {
+        let mut conn = self.connection.clone();
+        let score: Option<f64> = conn.zscore(TIMERS_ZSET, timer_key).await?;
+        Ok(score.is_some())
+    }
+
+    // rooms having a current bid, which were the rooms whose auction was started
+    pub async fn rooms_with_current_bid(&self) -> redis::RedisResult<Vec<String>> {
+        let mut conn = self.connection.clone();
+        let mut cursor: u64 = 0;
+        let mut rooms = Vec::new();
+        loop {
+            let (new_cursor, keys): (u64, Vec<String>) = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg("room:*:current_bid")
+                .arg("COUNT")
+                .arg(200)
+                .query_async(&mut conn)
+                .await?;
+            cursor = new_cursor;
+            rooms.extend(keys.iter().filter_map(|key| {
+                key.strip_prefix("room:")?.strip_suffix(":current_bid").map(str::to_string)
+            }));
+            if cursor == 0 {
+                break;
+            }
+        }
+        Ok(rooms)
+    }
+
+    /*
+        only one claim of a room was handled at a time, across the servers. the lock expires along with the lease,
+        such that a server which was down while holding it will not block the room.
+    */
+    pub async fn lock_expiry(&self, room_id: &str, claim: &str) -> redis::RedisResult<Option<String>> {
+        let mut conn = self.connection.clone();
+        let script = r#"
+        if redis.call('SET', KEYS[1], ARGV[1], 'NX', 'PX', ARGV[2]) then
+            return false
+        end
+        return redis.call('GET', KEYS[1])
+    "#;
+
+        redis::Script::new(script)
+            .key(format!("room:{}:expiry_lock", room_id))
+            .arg(claim)
+            .arg(TIMER_CLAIM_LEASE_MS)
+            .invoke_async(&mut conn)
+            .await
+    }
+
+    pub async fn unlock_expiry(&self, room_id: &str, claim: &str) -> redis::RedisResult<()> {
+        let mut conn = self.connection.clone();
+        let script = r#"
+        if redis.call('GET', KEYS[1]) == ARGV[1] then
+            redis.call('DEL', KEYS[1])
+        end
+        return 1
+    "#;
+
+        redis::Script::new(script)
+            .key(format!("room:{}:expiry_lock", room_id))
+            .arg(claim)
+            .invoke_async::<i32>(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    /*
+        only one server rebuilds a room at a time, the lock was taken with a token, such that a rebuild which took
+        longer than the lock can not release the lock of the next one. returns false if the room was being rebuilt.
+    */
+    pub async fn lock_rehydration(&self, room_id: &str, token: &str) -> redis::RedisResult<bool> {
+        let mut conn = self.connection.clone();
+        let locked: Option<String> = redis::cmd("SET")
+            .arg(format!("room:{}:rehydration_lock", room_id))
+            .arg(token)
+            .arg("NX")
+            .arg("PX")
+            .arg(REHYDRATION_LOCK_MS)
+            .query_async(&mut conn)
+            .await?;
+        Ok(locked.is_some())
+    }
+
+    pub async fn unlock_rehydration(&self, room_id: &str, token: &str) -> redis::RedisResult<()> {
+        let mut conn = self.connection.clone();
+        let script = r#"
+        if redis.call('GET', KEYS[1]) == ARGV[1] then
+            redis.call('DEL', KEYS[1])
+        end
+        return 1
+    "#;
+
+        redis::Script::new(script)
+            .key(format!("room:{}:rehydration_lock", room_id))
+            .arg(token)
+            .invoke_async::<i32>(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn is_rehydrating(&self, room_id: &str) -> redis::RedisResult<bool> {
+        self.check_key_exists(&format!("room:{}:rehydration_lock", room_id)).await
+    }
+
+    // epoch millis of the redis clock, the timer deadlines were from this clock
+    pub async fn server_time(&self) -> Result<i64, redis::RedisError> {
+        let mut conn = self.connection.clone();
+        let (seconds, micros): (i64, i64) = redis::cmd("TIME").query_async(&mut conn).await?;
+        Ok(seconds * 1000 + micros / 1000)
+    }
+
+    // remaining seconds of a timer key, none if the key doesn't exists
+    pub async fn get_ttl(&self, key: &str) -> Result<Option<i64>, redis::RedisError> {
+        let mut conn = self.connection.clone();
+        let ttl: i64 = conn.ttl(key).await?;
+        Ok(if ttl >= 0 { Some(ttl) } else { None })
+    }
+
     pub async fn check_key_exists(&self, key: &str) -> Result<bool, redis::RedisError> {
         tracing::info!("checking key exists redis function was called");
 
@@ -875,55 +1546,195 @@ impl RedisConnection {
     // this is enough to check whether the participant or the room exists or not
 
 
-    pub async fn add_retry_task(&self, val: &DBCommandsAuctionRoom, app_state: &AppState) -> Result<(), redis::RedisError> {
+    /*
+        every server appends the auction room tasks to its own journal (a stream) before queueing them, and the servers
+        which had a journal were kept in auction:db_journals, such that the journal of a crashed server was found.
+    */
+    pub async fn append_to_journal(&self, node_id: &str, command: &DBCommandsAuctionRoom) -> RedisResult<String> {
+        let mut conn = self.connection.clone();
+        let (entry_id,): (String,) = redis::pipe()
+            .atomic()
+            .cmd("XADD").arg(db_journal_key(node_id)).arg("*").arg("command").arg(serde_json::to_string(command).unwrap())
+            .cmd("SADD").arg(DB_JOURNALS).arg(node_id).ignore()
+            .query_async(&mut conn)
+            .await?;
+        Ok(entry_id)
+    }
 
-        let retry_count = match val.clone() {
-            DBCommandsAuctionRoom::PlayerSold(player_sold) => {
+    pub async fn remove_from_journal(&self, node_id: &str, entry_id: &str) -> RedisResult<()> {
+        let mut conn = self.connection.clone();
+        redis::cmd("XDEL").arg(db_journal_key(node_id)).arg(entry_id).query_async::<()>(&mut conn).await
+    }
 
-                player_sold.retry_count
-            },
-            DBCommandsAuctionRoom::BalanceUpdate(balance_update) => {
-                balance_update.retry_count
-            },
-            DBCommandsAuctionRoom::PlayerUnSold(unsold_player) => {
-                unsold_player.retry_count
-            },
-            DBCommandsAuctionRoom::UpdateRoomStatus(room_status_update) => {
-                room_status_update.retry_count
-            },
-            DBCommandsAuctionRoom::UpdateRemainingRTMS(remaining_rtms) => {
-                remaining_rtms.retry_count
-            },
-            DBCommandsAuctionRoom::CompletedRoomCompletedAt(completed_room) => {
-                completed_room.retry_count
-            },
-            DBCommandsAuctionRoom::CompletedRoomUnsoldPlayers(completed_room) => {
-                completed_room.retry_count
-            },
-            DBCommandsAuctionRoom::CompletedRoomSoldPlayers(completed_room) => {
-                completed_room.retry_count
+    // entries of the journal in the order they were appended
+    pub async fn journal_entries(&self, node_id: &str) -> RedisResult<Vec<(String, DBCommandsAuctionRoom)>> {
+        let mut conn = self.connection.clone();
+        let entries: Vec<(String, Vec<String>)> = redis::cmd("XRANGE")
+            .arg(db_journal_key(node_id)).arg("-").arg("+")
+            .query_async(&mut conn)
+            .await?;
+        Ok(entries.into_iter()
+            .filter_map(|(entry_id, fields)| {
+                let command = fields.chunks(2).find(|field| field[0] == "command").and_then(|field| field.get(1))?;
+                parse_journal_entry(entry_id, command)
+            })
+            .collect())
+    }
+
+    // servers which were having a journal, alive or not
+    pub async fn journal_nodes(&self) -> RedisResult<Vec<String>> {
+        let mut conn = self.connection.clone();
+        conn.smembers(DB_JOURNALS).await
+    }
+
+    // auction room tasks waiting for their retry, in the order they were due. entries which were not parsed were left to the retries listener
+    pub async fn pending_retries(&self) -> RedisResult<Vec<DBCommandsAuctionRoom>> {
+        let mut conn = self.connection.clone();
+        let retries: Vec<String> = conn.zrange(RETRIES_ZSET, 0, -1).await?;
+        Ok(retries.iter().filter_map(|task_json| serde_json::from_str(task_json).ok()).collect())
+    }
+
+    pub async fn refresh_node_heartbeat(&self, node_id: &str) -> RedisResult<()> {
+        let mut conn = self.connection.clone();
+        conn.set_ex(node_heartbeat_key(node_id), Utc::now().timestamp_millis(), NODE_HEARTBEAT_TTL_SECS).await
+    }
+
+    // servers other than this one which were having a journal but no heartbeat
+    pub async fn orphaned_journals(&self, node_id: &str) -> RedisResult<Vec<String>> {
+        let mut conn = self.connection.clone();
+        let nodes: Vec<String> = conn.smembers(DB_JOURNALS).await?;
+        let mut orphans = Vec::new();
+        for node in nodes.into_iter().filter(|node| node != node_id) {
+            let alive: bool = conn.exists(node_heartbeat_key(&node)).await?;
+            if !alive {
+                orphans.push(node);
             }
-        } ;
+        }
+        Ok(orphans)
+    }
+
+    /*
+        moves the entries of the journal of a crashed server to the journal of this server, and returns them with their
+        new entry ids. the script was atomic, so only one server takes over the journal, and it was left untouched
+        if the server was back by then.
+    */
+    pub async fn adopt_journal(&self, orphan_node_id: &str, node_id: &str) -> RedisResult<Vec<(String, DBCommandsAuctionRoom)>> {
+        let mut conn = self.connection.clone();
+        let script = r#"
+        if redis.call('EXISTS', KEYS[3]) == 1 then
+            return {}
+        end
+        local moved = {}
+        for _, entry in ipairs(redis.call('XRANGE', KEYS[1], '-', '+')) do
+            local fields = entry[2]
+            local entry_id = redis.call('XADD', KEYS[2], '*', unpack(fields))
+            for i = 1, #fields, 2 do
+                if fields[i] == 'command' then
+                    table.insert(moved, entry_id)
+                    table.insert(moved, fields[i + 1])
+                end
+            end
+        end
+        redis.call('DEL', KEYS[1])
+        redis.call('SREM', KEYS[4], ARGV[1])
+        if #moved > 0 then
+            redis.call('SADD', KEYS[4], ARGV[2])
+        end
+        return moved
+    "#;
+
+        let moved: Vec<String> = redis::Script::new(script)
+            .key(db_journal_key(orphan_node_id))
+            .key(db_journal_key(node_id))
+            .key(node_heartbeat_key(orphan_node_id))
+            .key(DB_JOURNALS)
+            .arg(orphan_node_id)
+            .arg(node_id)
+            .invoke_async(&mut conn)
+            .await?;
+        Ok(moved.chunks(2)
+            .filter_map(|entry| parse_journal_entry(entry[0].clone(), entry.get(1)?))
+            .collect())
+    }
 
-        let retry_delay = match retry_count{
-            0 => 12,  // 2 minutes
-            1 => 30,  // 5 minutes
-            2 => 60,  // 10 minutes
-            _ => {
-                tracing::info!("moving to Dead Letter Queue, all retries are exhausted") ;
+    /*
+        on shutdown the journal of this server was removed once all of its entries were executed, otherwise it was left
+        for the other servers. the heartbeat was removed either way, so they take it over without waiting for its expiry.
+    */
+    pub async fn retire_journal(&self, node_id: &str) -> RedisResult<bool> {
+        let mut conn = self.connection.clone();
+        let script = r#"
+        redis.call('DEL', KEYS[3])
+        if redis.call('XLEN', KEYS[1]) > 0 then
+            return 0
+        end
+        redis.call('DEL', KEYS[1])
+        redis.call('SREM', KEYS[2], ARGV[1])
+        return 1
+    "#;
+
+        let retired: i32 = redis::Script::new(script)
+            .key(db_journal_key(node_id))
+            .key(DB_JOURNALS)
+            .key(node_heartbeat_key(node_id))
+            .arg(node_id)
+            .invoke_async(&mut conn)
+            .await?;
+        Ok(retired == 1)
+    }
 
-                // moving to DLQ
-                app_state.dlq_task_executor.send(val.clone()).unwrap() ;
-                return Ok(())
+    /*
+        the delay and the attempts were from the retry policy of the task type, see models/retry_policy.rs
+        true if the task was sent to the DLQ channel, then the journal entry was removed by save_to_DLQ after saving it.
+    */
+    pub async fn add_retry_task(&self, val: &DBCommandsAuctionRoom, entry_id: Option<String>, app_state: &AppState) -> Result<bool, redis::RedisError> {
+        let policy = app_state.retry_policy.for_task(val.task_type());
+        let failures = val.retry_count();
+        metrics::counter!("db_task_failures_total", "task_type" => val.task_type()).increment(1);
+        if policy.is_exhausted(failures) {
+            tracing::info!("moving to Dead Letter Queue, all {} attempts are exhausted", failures) ;
+            metrics::counter!("db_task_dead_lettered_total", "task_type" => val.task_type()).increment(1);
+            if app_state.dlq_task_executor.send(JournaledTask { entry_id, command: val.clone() }).is_err() {
+                tracing::error!("DLQ channel was closed, the {} task was left in the journal", val.task_type()) ;
             }
-        };
+            return Ok(true)
+        }
 
+        self.schedule_retry(RETRIES_ZSET, val.task_type(), serde_json::to_string(val).unwrap(), policy.delay(failures)).await?;
+        Ok(false)
+    }
 
+    // the tasks outside the auction room were saved to the dead_letter_tasks right away, the DLQ channel was for the auction room tasks
+    pub async fn add_outside_retry_task(&self, val: &DBCommandsAuction, app_state: &AppState) -> Result<(), redis::RedisError> {
+        let policy = app_state.retry_policy.for_task(val.task_type());
+        let failures = val.retry_count();
+        metrics::counter!("db_task_failures_total", "task_type" => val.task_type()).increment(1);
+        if policy.is_exhausted(failures) {
+            tracing::info!("moving to Dead Letter Queue, all {} attempts are exhausted", failures) ;
+            metrics::counter!("db_task_dead_lettered_total", "task_type" => val.task_type()).increment(1);
+            let saved = match val {
+                DBCommandsAuction::AddUserExternalDetails(user_details) => app_state.database_connection
+                    .add_to_dlq::<UserExternalDetails>(val.task_type(), user_details.clone(), failures as i16, &user_details.last_error).await,
+                DBCommandsAuction::FavoriteTeamUpdated(fav_team) => app_state.database_connection
+                    .add_to_dlq::<FavoriteTeamUpdated>(val.task_type(), fav_team.clone(), failures as i16, &fav_team.last_error).await,
+            };
+            if let Err(err) = saved {
+                tracing::error!("unable to save the {} task to the DLQ, it was lost {}", val.task_type(), err) ;
+                metrics::counter!("db_task_lost_total", "task_type" => val.task_type()).increment(1);
+            }
+            return Ok(())
+        }
 
-        let retry_at = Utc::now().timestamp() + retry_delay;
+        self.schedule_retry(RETRIES_OUTSIDE_ZSET, val.task_type(), serde_json::to_string(val).unwrap(), policy.delay(failures)).await
+    }
+
+    async fn schedule_retry(&self, zset: &str, task_type: &str, task_json: String, delay: std::time::Duration) -> Result<(), redis::RedisError> {
+        tracing::info!("retrying the {} task in {:?}", task_type, delay) ;
+        metrics::counter!("db_task_retries_total", "task_type" => task_type.to_string()).increment(1);
+        let retry_at = Utc::now().timestamp() + delay.as_secs_f64().ceil() as i64;
         let mut conn = self.connection.clone();
         let _: usize = conn
-            .zadd("auction:retry:zset", serde_json::to_string(val).unwrap(), retry_at)
+            .zadd(zset, task_json, retry_at)
             .await?;
 
         Ok(())
@@ -936,33 +1747,172 @@ impl RedisConnection {
 // -------------------------- Spawning the task for expiry bid logic ------------------------------------
 use tokio_stream::StreamExt;
 use redis::{Client};
-use axum::extract::ws::{Message};
 use chrono::Utc;
 use crate::models;
-use crate::models::background_db_tasks::{CompletedRoom, DBCommandsAuctionRoom, ParticipantId, RoomStatus, UnSoldPlayer};
+use crate::models::background_db_tasks::{CompletedRoom, DBCommandsAuction, DBCommandsAuctionRoom, FavoriteTeamUpdated, JournaledTask, ParticipantId, RoomStatus, UnSoldPlayer, UserExternalDetails};
 use crate::services::other::get_previous_team_full_name;
 
-pub async fn listen_for_expiry_events(redis_url: &str, app_state: &Arc<AppState>) -> redis::RedisResult<()> {
-    tracing::info!("🔔 Redis expiry listener started");
-    let client = Client::open(redis_url)?;
-    let mut pubsub = client.get_async_pubsub().await?;
-    let mut conn = client.get_multiplexed_async_connection().await?;
-    // Subscribe to key event notifications for expired keys
-    pubsub.subscribe("__keyevent@0__:expired").await?;
-
+pub const TIMERS_ZSET: &str = "auction:timers:zset";
+// claims of the timers which were being handled, timer key -> claim
+const TIMER_CLAIMS: &str = "auction:timers:claims";
+// a claim which was not completed within this time was claimed again
+const TIMER_CLAIM_LEASE_MS: i64 = 30_000;
+// a rebuild of a room which was not completed within this time (a crashed server) can be taken by another server
+pub const REHYDRATION_LOCK_MS: i64 = 30_000;
+const TIMER_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(250);
+const TIMER_CLAIM_BATCH: usize = 100;
+
+/*
+    timers were scheduled in the auction:timers:zset with their deadlines, this worker polls for the due timers and
+    claims them. a claimed timer was leased to this server, so it was handled once even across servers, and the
+    timers which were due while the worker was down were handled once it was back.
+*/
+pub async fn run_timer_scheduler(app_state: &Arc<AppState>) -> redis::RedisResult<()> {
+    tracing::info!("⏱️ timer scheduler started");
     let redis_connection = app_state.redis_connection.clone();
 
     let bid_expiry = std::env::var("BID_EXPIRY").unwrap().parse::<u8>().unwrap();
 
+    // timers which were not claimed were left in the schedule for the other servers or the next start
+    while !app_state.is_shutting_down() {
+        let due_timers = redis_connection.claim_due_timers(TIMER_CLAIM_BATCH, &app_state.node_id).await?;
+        for timer_claim in due_timers.iter() {
+            tracing::info!("timer expired: {}", timer_claim.timer_key);
+            metrics::counter!("auction_timers_expired_total").increment(1);
+            if let Some(previous_claim) = &timer_claim.previous_claim {
+                // the previous claim was not completed within the lease, the server may be down or the handling failed
+                tracing::warn!("timer {} was claimed again, the claim {} was lost", timer_claim.timer_key, previous_claim);
+                metrics::counter!("auction_timer_claims_lost_total").increment(1);
+            }
+            let parts: Vec<&str> = timer_claim.timer_key.split(':').collect();
+
+            let room_id = parts.get(parts.len() - 1).unwrap_or(&"").to_string();
+            let is_rtm = parts.get(parts.len() - 2).unwrap_or(&"").to_string();
+            // the room actor applies the expiry along with the other commands of the room
+            crate::services::room_actor::send_command(&room_id, RoomCommand::TimerExpired {
+                timer_key: timer_claim.timer_key.clone(),
+                claim: timer_claim.claim.clone(),
+                is_rtm,
+                bid_expiry
+            }, app_state).await ;
+        }
+        // when a full batch was claimed there may be more due timers
+        if due_timers.len() < TIMER_CLAIM_BATCH {
+            tokio::time::sleep(TIMER_POLL_INTERVAL).await;
+        }
+    }
+    tracing::info!("⏱️ timer scheduler stopped");
+    Ok(())
+}
+
+
+
+// a presence member which was not refreshed within this time belongs to a server which was down
+pub const PRESENCE_TTL_MS: i64 = 15_000;
+
+// failed auction room tasks scored with the time (seconds) of their next attempt
+pub const RETRIES_ZSET: &str = "auction:retry:zset";
+// same for the tasks outside the auction room
+pub const RETRIES_OUTSIDE_ZSET: &str = "auction:retry:external:zset";
+
+// servers which were having a journal of the auction room tasks
+const DB_JOURNALS: &str = "auction:db_journals";
+// a server whose heartbeat was not refreshed within this time was down, and its journal was taken over
+const NODE_HEARTBEAT_TTL_SECS: u64 = 15;
+
+fn db_journal_key(node_id: &str) -> String {
+    format!("auction:db_journal:{}", node_id)
+}
+
+fn node_heartbeat_key(node_id: &str) -> String {
+    format!("auction:node:{}", node_id)
+}
+
+// an entry which was not parsed was left in the journal
+fn parse_journal_entry(entry_id: String, command: &str) -> Option<(String, DBCommandsAuctionRoom)> {
+    match serde_json::from_str(command) {
+        Ok(command) => Some((entry_id, command)),
+        Err(err) => {
+            tracing::error!("unable to parse the journal entry {} {}", entry_id, err);
+            None
+        }
+    }
+}
+
+// KEYS: current bid, timer, skipped set, timers schedule. ARGV: seen participant, seen player, seen amount in hundredths, bidder, amount, expiry
+const ACCEPT_BID_SCRIPT: &str = r#"
+if redis.call('EXISTS', KEYS[2]) == 0 then
+    return {0, 'bidding-closed', 0, 0}
+end
+if redis.call('SISMEMBER', KEYS[3], ARGV[4]) == 1 then
+    return {0, 'skipped', 0, 0}
+end
+local current = redis.call('HMGET', KEYS[1], 'participant_id', 'player_id', 'bid_amount')
+if not current[1] then
+    return {0, 'no-current-bid', 0, 0}
+end
+if tonumber(current[2]) ~= tonumber(ARGV[2]) then
+    return {0, 'player-changed', 0, 0}
+end
+if tonumber(current[1]) == tonumber(ARGV[4]) then
+    return {0, 'already-highest-bidder', 0, 0}
+end
+if tonumber(current[1]) ~= tonumber(ARGV[1]) or math.floor(tonumber(current[3]) * 100 + 0.5) ~= tonumber(ARGV[3]) then
+    return {0, 'outbid', 0, 0}
+end
+redis.call('HSET', KEYS[1], 'participant_id', ARGV[4], 'bid_amount', ARGV[5], 'is_rtm', 0, 'rtm_bid', 0)
+redis.call('SET', KEYS[2], 'active', 'EX', ARGV[6])
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+local deadline = now + tonumber(ARGV[6]) * 1000
+redis.call('ZADD', KEYS[4], deadline, KEYS[2])
+return {1, 'accepted', now, deadline}
+"#;
+
+// KEYS: current bid, RTM timer, timer of the bid, timers schedule, timer claims.
+// ARGV: seen participant, seen player, seen amount in hundredths, participant, amount, is rtm, rtm bid, expiry
+const RESOLVE_RTM_SCRIPT: &str = r#"
+if redis.call('EXISTS', KEYS[2]) == 0 then
+    return {0, 0, 0}
+end
+local current = redis.call('HMGET', KEYS[1], 'participant_id', 'player_id', 'bid_amount')
+if not current[1] or tonumber(current[1]) ~= tonumber(ARGV[1]) or tonumber(current[2]) ~= tonumber(ARGV[2])
+    or math.floor(tonumber(current[3]) * 100 + 0.5) ~= tonumber(ARGV[3]) then
+    return {0, 0, 0}
+end
+redis.call('ZREM', KEYS[4], KEYS[2])
+redis.call('HDEL', KEYS[5], KEYS[2])
+redis.call('DEL', KEYS[2])
+redis.call('HSET', KEYS[1], 'participant_id', ARGV[4], 'bid_amount', ARGV[5], 'is_rtm', ARGV[6], 'rtm_bid', ARGV[7])
+redis.call('SET', KEYS[3], 'active', 'EX', ARGV[8])
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+local deadline = now + tonumber(ARGV[8]) * 1000
+redis.call('ZADD', KEYS[4], deadline, KEYS[3])
+redis.call('HDEL', KEYS[5], KEYS[3])
+return {1, now, deadline}
+"#;
+
+pub async fn listen_for_room_events(redis_url: &str, app_state: &Arc<AppState>) -> redis::RedisResult<()> {
+    tracing::info!("Redis room events listener started");
+    let client = Client::open(redis_url)?;
+    let mut pubsub = client.get_async_pubsub().await?;
+    pubsub.psubscribe("room:*:events").await?;
+
     let mut stream = pubsub.on_message();
     while let Some(msg) = stream.next().await {
-        let expired_key: String = msg.get_payload()?;
-        tracing::info!("Key expired: {}", expired_key);
-        let parts: Vec<&str> = expired_key.split(':').collect();
-
-        let room_id = parts.get(parts.len() - 1).unwrap_or(&"").to_string();
-        let is_rtm = parts.get(parts.len() - 2).unwrap_or(&"").to_string();
-        handling_expiry_events(&app_state,&room_id, is_rtm, bid_expiry).await ;
+        let Some(room_id) = msg.get_channel_name().strip_prefix("room:").and_then(|channel| channel.strip_suffix(":events")) else {
+            continue;
+        };
+        let payload: String = msg.get_payload()?;
+        let Ok(event) = serde_json::from_str::<RoomEvent>(&payload) else {
+            tracing::warn!("invalid room event {}", payload);
+            continue;
+        };
+        // rooms which were not having any connection on this server were skipped
+        if let Some(room) = existing_room(room_id, app_state).await {
+            let _ = room.fan_out.send(event.into_fan_out());
+        }
     }
 
     Ok(())
@@ -970,6 +1920,105 @@ pub async fn listen_for_expiry_events(redis_url: &str, app_state: &Arc<AppState>
 
 
 
+// the claim was handled only if no other claim of the room was being handled, on any of the servers
+pub async fn handle_timer_claim(app_state: &Arc<AppState>, room_id: &str, timer_key: &str, claim: &str, is_rtm: String, bid_expiry: u8) {
+    let redis_connection = app_state.redis_connection.clone() ;
+    match redis_connection.lock_expiry(room_id, claim).await {
+        Ok(None) => {},
+        Ok(Some(holder)) => {
+            tracing::warn!("expiry of the room {} was being handled by the claim {}, skipping the claim {}", room_id, holder, claim) ;
+            metrics::counter!("auction_timer_claims_duplicate_total").increment(1) ;
+            return;
+        },
+        Err(err) => {
+            // the claim was claimed again once its lease ends
+            tracing::error!("unable to lock the expiry of the room {} {}", room_id, err) ;
+            return;
+        }
+    }
+    // the claim may be superseded while it was waiting for the lock, then it was already handled
+    let handled = if redis_connection.is_current_claim(timer_key, claim).await.unwrap_or(false) {
+        handling_expiry_events(app_state, room_id, is_rtm, bid_expiry).await
+    } else {
+        tracing::warn!("claim {} of the timer {} was superseded, skipping it", claim, timer_key) ;
+        metrics::counter!("auction_timer_claims_duplicate_total").increment(1) ;
+        true
+    } ;
+    // a sale which was not journaled keeps its claim, such that it was claimed again after the lease
+    if handled && let Err(err) = redis_connection.complete_timer_claim(timer_key, claim).await {
+        tracing::error!("unable to complete the claim {} of the timer {} {}", claim, timer_key, err) ;
+    }
+    if let Err(err) = redis_connection.unlock_expiry(room_id, claim).await {
+        tracing::error!("unable to unlock the expiry of the room {} {}", room_id, err) ;
+    }
+}
+
+/*
+    timers were never lost by the scheduler, but the rooms of the older releases or a redis which was restored without
+    the schedule may have a current bid with no timer, such rooms never moves to the next player. on startup the rooms
+    in progress were checked, a running timer which was not in the schedule was scheduled again, and a bid whose timer
+    was lost was resolved as an expired timer through the room actor.
+*/
+pub async fn recover_in_flight_auctions(app_state: &Arc<AppState>) -> redis::RedisResult<()> {
+    let redis_connection = app_state.redis_connection.clone() ;
+    let bid_expiry = std::env::var("BID_EXPIRY").unwrap().parse::<u8>().unwrap() ;
+    let rooms = redis_connection.rooms_with_current_bid().await? ;
+    tracing::info!("checking {} rooms in progress for lost timers", rooms.len()) ;
+    for room_id in rooms {
+        // timers paused by the shutdown of a server continues with their remaining time
+        if redis_connection.resume_timers(&room_id).await? {
+            tracing::info!("room {} timers paused on shutdown were resumed", room_id) ;
+            continue;
+        }
+        let Some(room_meta) = redis_connection.get_room_meta(&room_id).await? else {
+            continue;
+        } ;
+        let Some(current_bid) = redis_connection.get_current_bid(&room_id).await? else {
+            continue;
+        } ;
+        // paused rooms and the rooms between the players were not having a timer
+        if room_meta.pause || current_bid.player_id == 0 {
+            continue;
+        }
+        let (timer_key, is_rtm) = if current_bid.is_rtm {
+            (format!("auction:timer:rtms:{}", room_id), "rtms")
+        } else {
+            (format!("auction:timer:{}", room_id), "timer")
+        } ;
+        // due timers in the schedule were handled by the scheduler
+        if redis_connection.is_timer_scheduled(&timer_key).await? {
+            continue;
+        }
+        if let Some(remaining) = redis_connection.get_ttl(&timer_key).await? {
+            tracing::info!("room {} timer {} was running but not scheduled, scheduled it again with {} seconds", room_id, timer_key, remaining) ;
+            redis_connection.set_timer(&timer_key, "active", remaining.clamp(1, u8::MAX as i64) as u8).await? ;
+            metrics::counter!("auction_rooms_recovered_total").increment(1) ;
+            continue;
+        }
+        // the bids were rejected without a timer, so a player with no bid was also resolved as expired (unsold)
+        let claim = format!("{}:recovery", app_state.node_id) ;
+        if !redis_connection.claim_lost_timer(&timer_key, &claim).await? {
+            continue;
+        }
+        if current_bid.participant_id == 0 {
+            tracing::warn!("room {} was having no bid on player {} and no timer, resolving it as unsold", room_id, current_bid.player_id) ;
+        } else {
+            tracing::warn!(
+                "room {} was having the bid {} of participant {} on player {} with no timer, resolving it as expired",
+                room_id, current_bid.bid_amount, current_bid.participant_id, current_bid.player_id
+            ) ;
+        }
+        metrics::counter!("auction_rooms_recovered_total").increment(1) ;
+        crate::services::room_actor::send_command(&room_id, RoomCommand::TimerExpired {
+            timer_key,
+            claim,
+            is_rtm: is_rtm.to_string(),
+            bid_expiry
+        }, app_state).await ;
+    }
+    Ok(())
+}
+
 #[tracing::instrument(
     name = "handling_expiry_events",
     skip(app_state),
@@ -979,7 +2028,7 @@ pub async fn listen_for_expiry_events(redis_url: &str, app_state: &Arc<AppState>
         bid_expiry = bid_expiry
     )
 )]
-pub async fn handling_expiry_events(app_state: &Arc<AppState>, room_id: &str,is_rtm: String, bid_expiry: u8) {
+pub async fn handling_expiry_events(app_state: &Arc<AppState>, room_id: &str,is_rtm: String, bid_expiry: u8) -> bool {
     let mut sold = false ;
     let redis_connection = app_state.redis_connection.clone() ;
     tracing::info!("is rtm ---------------> {}", is_rtm) ;
@@ -990,7 +2039,7 @@ pub async fn handling_expiry_events(app_state: &Arc<AppState>, room_id: &str,is_
     let room_meta = redis_connection.get_room_meta(room_id).await.unwrap() ;
     let room_meta: RoomMeta = match room_meta {
         Some(room_meta) => room_meta,
-        None => { return; }
+        None => { return true; }
     } ;
     let message;
     let participant_id = current_bid.clone().participant_id ;
@@ -1002,7 +2051,7 @@ pub async fn handling_expiry_events(app_state: &Arc<AppState>, room_id: &str,is_
         Some(current_player) => current_player,
         None => {
             tracing::warn!("No current player") ;
-            return;
+            return true;
         }
     } ;
     let pause_status = room_meta.pause ;
@@ -1016,47 +2065,30 @@ pub async fn handling_expiry_events(app_state: &Arc<AppState>, room_id: &str,is_
         let mut participant = match details {
             Some(details) => details,
             None => {
-                return ;
+                return true;
             }
         } ;
+        // we are going to sell the player to the person,
+        tracing::info!("player was a sold player") ;
+        let remaining_balance = round_two_decimals(participant.balance -  bid.bid_amount);
+        // the sold player, the participant balance and the rtm were written in a single transaction
+        // keyed by the player, such that a re-claimed timer lease enqueuing the sale again was a no-op
+        if !queue_sale(app_state, room_id, &bid, remaining_balance, bid.is_rtm).await {
+            return false;
+        }
         let mut remaining_rtms = participant.remaining_rtms ;
         if bid.is_rtm {
-            // we are going to update the rtms of the user
-            // redis_connection.update_remaining_rtms(room_id.clone(), participant_id).await?;
-            // we are going to update in the sql as well.
-            app_state.auction_room_database_task_executor.send(DBCommandsAuctionRoom::UpdateRemainingRTMS(models::background_db_tasks::ParticipantId{
-                id: bid.participant_id,
-                retry_count: 0,
-                last_error: String::from("")
-            })).expect("Error while sending participant id for updating rtms to a unbounded channel") ;
+            // we are going to update the rtms of the user, the sql was updated along with the sale
             redis_connection.decrement_rtm(room_id, participant_id).await.unwrap() ;
             remaining_rtms -= 1 ;
         }
-        // we are going to sell the player to the person,
-        tracing::info!("player was a sold player") ;
-        app_state.auction_room_database_task_executor.send(DBCommandsAuctionRoom::PlayerSold(models::background_db_tasks::SoldPlayer {
-            room_id: room_id.to_string(),
-            player_id: bid.player_id,
-            participant_id: bid.participant_id,
-            bid_amount: bid.bid_amount,
-            retry_count: 0,
-            last_error: String::from("")
-        })).expect("Error While adding Player sold to the unbounded channel") ;
-        let remaining_balance = round_two_decimals(participant.balance -  bid.bid_amount);
         redis_connection.increment_total_players_brought(room_id, participant_id).await.expect("error while updating total players brought") ;
+        redis_connection.increment_role_count(room_id, participant_id, &current_player.role).await.expect("error while updating the role count") ;
         let total_players_brought = participant.total_players_brought + 1 ;
         redis_connection.update_balance(room_id, participant_id, remaining_balance).await.expect("error while updating balance") ;
         /*
             for a new player brought, we need to update balance total players brought, foreign player
         */
-        // updating the participant balance in the participant table
-        app_state.auction_room_database_task_executor.send(DBCommandsAuctionRoom::BalanceUpdate(models::background_db_tasks::BalanceUpdate {
-            participant_id: bid.participant_id,
-            remaining_balance,
-            retry_count: 0,
-            last_error: String::from("")
-        })).expect("Error While update balance to the unbounded channel") ;
-        tracing::info!("successfully updated the balance in the psql") ;
         tracing::info!("updating in the redis along with the balance and bid") ;
         let bid_ = Bid::new(0, 0,0.0,0.0, false, false) ;
         redis_connection.reset_skip(room_id).await.expect("error while resetting skip_count") ;
@@ -1076,15 +2108,13 @@ pub async fn handling_expiry_events(app_state: &Arc<AppState>, room_id: &str,is_
 
         sold = true ;
 
-        broadcast_handler(Message::from(
-            serde_json::to_string(&SoldPlayer {
+        broadcast_server_message(&ServerMessage::PlayerSold(SoldPlayer {
                 team_name: app_state.database_connection.get_team_name(participant_id).await.unwrap(),
                 sold_price: bid.bid_amount,
                 remaining_balance,
                 remaining_rtms,
                 foreign_players_brought
-            }).unwrap()
-        ), room_id, &app_state).await ;
+            }), room_id, app_state).await ;
 
 
     }else{
@@ -1114,6 +2144,7 @@ pub async fn handling_expiry_events(app_state: &Arc<AppState>, room_id: &str,is_
             }
         }
         let current_bid= current_bid.clone() ;
+        let rules = redis_connection.get_room_rules(room_id).await.expect("error while getting room rules") ;
         tracing::info!("xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx") ;
         tracing::info!("previous team {}", current_player.previous_team) ;
         tracing::info!("remaining rtms {}",remaining_rtms) ;
@@ -1122,46 +2153,48 @@ pub async fn handling_expiry_events(app_state: &Arc<AppState>, room_id: &str,is_
         tracing::info!("xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx") ;
         if ((!current_player.previous_team.contains("-"))  && remaining_rtms > 0) && (!current_bid.rtm_bid)
             && current_bid.participant_id > 0 && previous_team_participant_id != current_bid.participant_id
-            && !redis_connection.is_skipped(room_id,previous_team_participant_id).await.expect("") && (current_player.clone().is_indian || previous_team_foreign_player_count < 8)
+            && !redis_connection.is_skipped(room_id,previous_team_participant_id).await.expect("") && (current_player.clone().is_indian || previous_team_foreign_player_count < rules.max_foreign_players)
+            && !redis_connection.is_bot(room_id, previous_team_participant_id).await.expect("error while checking the bot") // bots and autopilot were not using rtms
+            && !redis_connection.is_on_autopilot(room_id, previous_team_participant_id).await.expect("error while checking the autopilot")
         { // if it is rtm_bid means rtm was accepted such that the highest bidder willing to buy the player with the price quoted by the rtm team
             tracing::info!("going to send the Use RTM") ;
             // so we are going to create a new expiry key, and for that key there will be another subscriber
             // now we are going to send the notification to the previous team to use the RTM, if he not uses it
             // then this will expiry in 20 seconds.
 
-            send_message_to_participant(previous_team_participant_id, String::from("Use RTM"), room_id, &app_state).await ;
+            send_server_message(&ServerMessage::UseRtm, previous_team_participant_id, room_id, app_state).await ;
 
             // setting the new timer
             let rtm_timer_key = format!("auction:timer:rtms:{}", room_id); // if this key exists in the redis then no bids takes place
-            let mut conn = redis_connection.connection.clone() ;
-            conn.set_ex::<_, _, ()>(&rtm_timer_key, "rtm", bid_expiry as u64).await.expect("unable to set the updated value in new_bid");
+            let timer = redis_connection.set_timer(&rtm_timer_key, "rtm", bid_expiry).await.expect("unable to set the updated value in new_bid");
+            redis_connection.publish_timer_deadline(room_id, "rtm", timer).await.expect("unable to publish the rtm deadline");
             tracing::info!("we have successfully sent the message to the previous team, regarding RTM") ;
-            return;
+            return true;
         }else {
 
             tracing::info!("we are going to update the balance of the participant") ;
 
-            let length ;
-            {
-                let rooms = &app_state.rooms;
-                let mut rooms_lock = rooms.read().await; // acquire mutable write lock
-                length = rooms_lock.get(room_id).unwrap().len() ;
-            }
+            let length = participants_count(room_id, app_state).await ;
 
             tracing::info!("length of room {}", length) ;
-            let mut remaining_balance: f32 = 0.0 ;
             if current_bid.bid_amount != 0.0 {
                 let participant = redis_connection.get_participant(room_id, current_bid.participant_id).await.unwrap() ;
                 let participant = match participant {
                     Some(participant) => participant,
                     None => {
-                        return;
+                        return true;
                     }
                 };
-                remaining_balance = participant.balance -  current_bid.bid_amount;
+                let remaining_balance = participant.balance -  current_bid.bid_amount;
+                // the sold player and the participant balance were written in a single transaction
+                // keyed by the player, such that a re-claimed timer lease enqueuing the sale again was a no-op
+                if !queue_sale(app_state, room_id, &current_bid, remaining_balance, false).await {
+                    return false;
+                }
                 remaining_rtms = participant.remaining_rtms ;
                 let total_players_brought = participant.total_players_brought + 1 ;
                 redis_connection.increment_total_players_brought(room_id, participant_id).await.expect("error while updating total players brought") ;
+                redis_connection.increment_role_count(room_id, participant_id, &current_player.role).await.expect("error while updating the role count") ;
                 redis_connection.update_balance(room_id, participant_id, remaining_balance).await.expect("error while updating balance") ;
 
                 let bid = Bid::new(0, 0,0.0,0.0, false, false) ;
@@ -1172,52 +2205,36 @@ pub async fn handling_expiry_events(app_state: &Arc<AppState>, room_id: &str,is_
                     redis_connection.increment_foreign_player_count(room_id, participant_id).await.unwrap();
                     foreign_players_brought += 1 ;
                 }
-                message= Message::from(
-                    serde_json::to_string(&SoldPlayer {
+                message= ServerMessage::PlayerSold(SoldPlayer {
                         team_name: participant.team_name.clone(),
                         sold_price: current_bid.bid_amount,
                         remaining_balance,
                         remaining_rtms,
                         foreign_players_brought
-                    }).unwrap()
-                );
+                    });
             }else{
-                message = Message::text("UnSold") ;
-            }
-
-            // making sure no skipped count
-            redis_connection.reset_skip(room_id).await.expect("error while reseting the skip count") ;
-            tracing::info!("we are going to broadcast the message to the room participant") ;
-            broadcast_handler(message,room_id, &app_state ).await ;
-
-            // -------------------- over here we need to add the player to the sold player list with room-id and player-id
-            if current_bid.bid_amount != 0.0 {
-                tracing::info!("player was a sold player") ;
-                app_state.auction_room_database_task_executor.send(DBCommandsAuctionRoom::PlayerSold(models::background_db_tasks::SoldPlayer {
-                    room_id: room_id.to_string(),
-                    player_id: current_bid.player_id,
-                    participant_id: current_bid.participant_id,
-                    bid_amount: current_bid.bid_amount,
-                    retry_count: 0,
-                    last_error: String::from("")
-                })).expect("Error While adding Player sold to the unbounded channel") ;
-                // updating the participant balance in the participant table
-                app_state.auction_room_database_task_executor.send(DBCommandsAuctionRoom::BalanceUpdate(models::background_db_tasks::BalanceUpdate {
-                    participant_id: current_bid.participant_id,
-                    remaining_balance,
-                    retry_count: 0,
-                    last_error: String::from("")
-                })).expect("Error While update balance to the unbounded channel") ;
-                tracing::info!("successfully updated the balance in the psql") ;
-            }else {
                 tracing::info!("player was an unsold player") ;
-                app_state.auction_room_database_task_executor.send(DBCommandsAuctionRoom::PlayerUnSold(models::background_db_tasks::UnSoldPlayer {
+                let unsold = app_state.auction_room_database_task_executor.send(DBCommandsAuctionRoom::PlayerUnSold(models::background_db_tasks::UnSoldPlayer {
                     room_id: room_id.to_string(),
                     player_id: current_bid.player_id,
+                    idempotency_key: format!("unsold:{}:{}", room_id, current_bid.player_id),
+                    enqueued_at: chrono::Utc::now().timestamp_millis(),
                     retry_count: 0,
                     last_error: String::from("")
-                })).expect("Error While adding Player Unsold to the unbounded channel") ;
+                })).await ;
+                if let Err(err) = unsold {
+                    tracing::error!("{}, the expiry was handled again once its claim was re-claimed", err) ;
+                    metrics::counter!("auction_expiry_not_durable_total").increment(1) ;
+                    return false;
+                }
+                message = ServerMessage::PlayerUnsold ;
             }
+
+            // making sure no skipped count
+            redis_connection.reset_skip(room_id).await.expect("error while reseting the skip count") ;
+            tracing::info!("we are going to broadcast the message to the room participant") ;
+            broadcast_server_message(&message,room_id, app_state ).await ;
+
             sold = true ;
         }
 
@@ -1225,13 +2242,57 @@ pub async fn handling_expiry_events(app_state: &Arc<AppState>, room_id: &str,is_
 
     let message ;
     if sold  {
+        // the player was sold or unsold, so the proxy bids registered for him are no more needed
+        redis_connection.clear_proxy_bids(room_id, player_id).await.expect("error while clearing the proxy bids") ;
         message = get_next_player(room_id, player_id, bid_expiry, pause_status, &app_state).await ;
     }else {
-        message = Message::text("Auction was Paused");
+        message = ServerMessage::AuctionPaused;
+    }
+
+    let player_up = matches!(message, ServerMessage::PlayerUp(_)) ;
+    broadcast_server_message(&message,room_id, app_state ).await ;
+
+    if player_up {
+        // proxies registered for the next player open the bidding before the bots think about it
+        match room_mode(room_id, app_state).await {
+            Ok(room_mode) => {
+                let timer_key = format!("auction:timer:{}", room_id) ;
+                crate::services::proxy_bidding::run_proxy_bids(room_id, app_state, &timer_key, room_mode, bid_expiry).await ;
+            },
+            Err(err) => tracing::error!("unable to get the room mode for the proxy bids {}", err)
+        }
     }
 
-    broadcast_handler(message,room_id, &app_state ).await ;
+    if sold && !pause_status {
+        // bots and the teams on autopilot will be deciding on the next player
+        crate::services::bot_participants::schedule_bot_bids(room_id.to_string(), app_state.clone()) ;
+    }
+    true
+}
 
+/*
+    the sale was journaled before the room state in redis was changed or broadcasted, a sale which was not durable
+    leaves the room as it was, and the expiry was handled again once its claim was re-claimed.
+*/
+async fn queue_sale(app_state: &AppState, room_id: &str, bid: &Bid, remaining_balance: f32, decrement_rtm: bool) -> bool {
+    let sale = app_state.auction_room_database_task_executor.send(DBCommandsAuctionRoom::SaleCompleted(models::background_db_tasks::SaleCompleted {
+        room_id: room_id.to_string(),
+        player_id: bid.player_id,
+        participant_id: bid.participant_id,
+        bid_amount: bid.bid_amount,
+        remaining_balance,
+        decrement_rtm,
+        idempotency_key: format!("sale:{}:{}", room_id, bid.player_id),
+        enqueued_at: chrono::Utc::now().timestamp_millis(),
+        retry_count: 0,
+        last_error: String::from("")
+    })).await ;
+    if let Err(err) = sale {
+        tracing::error!("{}, the expiry was handled again once its claim was re-claimed", err) ;
+        metrics::counter!("auction_expiry_not_durable_total").increment(1) ;
+        return false;
+    }
+    true
 }
 
 #[tracing::instrument(
@@ -1243,17 +2304,11 @@ pub async fn handling_expiry_events(app_state: &Arc<AppState>, room_id: &str,is_
         bid_expiry = bid_expiry
     )
 )]
-pub async fn get_next_player(room_id: &str, player_id: i32, bid_expiry: u8, pause_status: bool, app_state: &Arc<AppState>) -> Message {
+pub async fn get_next_player(room_id: &str, player_id: i32, bid_expiry: u8, pause_status: bool, app_state: &Arc<AppState>) -> ServerMessage {
     // we are going to get the next player and broadcasting the next player
     let mut next_player = player_id + 1 ;
     let mut redis_connection = app_state.redis_connection.clone();
-    let participants_count = app_state
-    .rooms
-    .read()
-    .await
-    .get(room_id)
-    .unwrap()
-    .len() ;
+    let participants_count = participants_count(room_id, app_state).await ;
     tracing::info!("*=* participants count is {}", participants_count) ;
     let skipped_pool_count = app_state.redis_connection.get_skipped_pool_count(room_id).await.unwrap() as usize ;
     tracing::info!("*=* skipped pool count is {}", skipped_pool_count) ;
@@ -1282,32 +2337,185 @@ pub async fn get_next_player(room_id: &str, player_id: i32, bid_expiry: u8, paus
             tracing::info!("now updating last player id") ;
             redis_connection.set_current_player(room_id, player.clone()).await.expect("unable to update last player id");
             // we are going to update the current bid
-            message = Message::text("Auction was Paused") ;
+            message = ServerMessage::AuctionPaused ;
             tracing::info!("auction was paused and updated last player in redis") ;
            if !pause_status {
                redis_connection.update_current_bid(room_id, Bid::new(0, next_player, 0.0, player.base_price, false, false), bid_expiry, -1, true, false).await.expect("unable to update current bid");
                tracing::info!("we are going to broadcast the next player, completed with updating current bid with new player") ;
-               message = Message::from(serde_json::to_string(&player).unwrap()) ;
+               message = ServerMessage::PlayerUp(player) ;
            }
         },
         Err(err) => {
             if err.kind() == redis::ErrorKind::TypeError
                 && err.to_string().contains("Player not found")
             {
-                message = Message::text("Auction Completed") ;
+                message = ServerMessage::AuctionCompleted ;
                 tracing::warn!("Player with ID {} not found in Redis", next_player);
                 // Handle "not found" case separately
             } else {
                 tracing::error!("Redis error occurred: {:?}", err);
                 tracing::warn!("error occurred while getting players") ;
                 tracing::error!("error was {}", err) ;
-                message = Message::text("Error Occurred while getting players from redis") ;
+                message = ServerMessage::error(ErrorCode::ServerError, "Error Occurred while getting players from redis") ;
             }
         }
     };
     message
 }
 
+/*
+    strict mode of the room, it never changes once the room was created, so it was read from postgres only once
+    and kept along with the other keys of the room, which the clean up removes.
+*/
+pub async fn room_mode(room_id: &str, app_state: &AppState) -> Result<bool, String> {
+    let redis_connection = app_state.redis_connection.clone() ;
+    if let Some(room_mode) = redis_connection.get_room_mode(room_id).await.map_err(|err| err.to_string())? {
+        return Ok(room_mode);
+    }
+    let room_mode = app_state.database_connection.get_room_mode(room_id).await.map_err(|err| err.to_string())? ;
+    redis_connection.set_room_mode(room_id, room_mode).await.map_err(|err| err.to_string())? ;
+    Ok(room_mode)
+}
+
 fn round_two_decimals(value: f32) -> f32 {
     (value * 100.0).round() / 100.0
 }
+
+
+/*
+    these tests run against the redis of REDIS_URL, they were skipped when it was not set. the timers schedule and the
+    journals were shared by all the servers, so it should be a redis of its own, not the one of a running server.
+*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::background_db_tasks::SkipVoted;
+
+    async fn redis() -> Option<RedisConnection> {
+        if std::env::var("REDIS_URL").is_err() {
+            eprintln!("REDIS_URL was not set, skipping the test");
+            return None;
+        }
+        Some(RedisConnection::new().await)
+    }
+
+    async fn room_with_bid(redis: &RedisConnection, bid: Bid) -> String {
+        let room_id = uuid::Uuid::new_v4().to_string();
+        redis.set_current_bid(&room_id, bid).await.unwrap();
+        room_id
+    }
+
+    async fn clean_up(redis: &RedisConnection, room_id: &str) {
+        redis.cancel_timer(&format!("auction:timer:{}", room_id)).await.unwrap();
+        redis.cancel_timer(&format!("auction:timer:rtms:{}", room_id)).await.unwrap();
+        redis.auction_clean_up(room_id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn bid_was_taken_only_against_the_bid_it_has_seen() {
+        let Some(redis) = redis().await else { return };
+        let opening = Bid::new(0, 7, 0.0, 0.5, false, false);
+        let room_id = room_with_bid(&redis, opening.clone()).await;
+        let timer_key = format!("auction:timer:{}", room_id);
+        redis.set_timer(&timer_key, "active", 30).await.unwrap();
+
+        let first = Bid::new(1, 7, 0.5, 0.5, false, false);
+        assert!(redis.accept_bid(&room_id, &opening, &first, 30).await.unwrap().is_ok());
+        // a second bid which has seen the opening bid was late
+        let late = Bid::new(2, 7, 0.5, 0.5, false, false);
+        assert_eq!(redis.accept_bid(&room_id, &opening, &late, 30).await.unwrap().err(), Some(BidRejection::Outbid));
+        assert_eq!(redis.accept_bid(&room_id, &first, &Bid::new(1, 7, 0.55, 0.5, false, false), 30).await.unwrap().err(), Some(BidRejection::AlreadyHighestBidder));
+        assert_eq!(redis.accept_bid(&room_id, &Bid::new(1, 8, 0.5, 0.5, false, false), &Bid::new(2, 8, 0.55, 0.5, false, false), 30).await.unwrap().err(), Some(BidRejection::PlayerChanged));
+        redis.mark_skipped(&room_id, 3).await.unwrap();
+        assert_eq!(redis.accept_bid(&room_id, &first, &Bid::new(3, 7, 0.55, 0.5, false, false), 30).await.unwrap().err(), Some(BidRejection::Skipped));
+
+        let current_bid = redis.get_current_bid(&room_id).await.unwrap().unwrap();
+        assert_eq!((current_bid.participant_id, current_bid.bid_amount), (1, 0.5));
+
+        // the timer was claimed by the expiry, no bid was taken after it
+        redis.cancel_timer(&timer_key).await.unwrap();
+        assert_eq!(redis.accept_bid(&room_id, &first, &Bid::new(2, 7, 0.55, 0.5, false, false), 30).await.unwrap().err(), Some(BidRejection::BiddingClosed));
+        clean_up(&redis, &room_id).await;
+    }
+
+    #[tokio::test]
+    async fn rtm_was_resolved_only_once() {
+        let Some(redis) = redis().await else { return };
+        let highest_bid = Bid::new(1, 7, 2.0, 0.5, false, false);
+        let room_id = room_with_bid(&redis, highest_bid.clone()).await;
+        let rtm_timer_key = format!("auction:timer:rtms:{}", room_id);
+        redis.set_timer(&rtm_timer_key, "rtm", 30).await.unwrap();
+
+        let rtm = Bid::new(2, 7, 2.0, 0.5, true, false);
+        assert!(redis.resolve_rtm(&room_id, &rtm_timer_key, &highest_bid, &rtm, 30).await.unwrap().is_some());
+        // an action on the bid which was already resolved, like a cancel sent along with the use
+        let cancel = Bid::new(1, 7, 2.0, 0.5, false, false);
+        assert!(redis.resolve_rtm(&room_id, &rtm_timer_key, &highest_bid, &cancel, 30).await.unwrap().is_none());
+        let current_bid = redis.get_current_bid(&room_id).await.unwrap().unwrap();
+        assert_eq!((current_bid.participant_id, current_bid.is_rtm), (2, true));
+
+        // once the rtm timer was gone, the rtm was resolved by its expiry
+        redis.cancel_timer(&rtm_timer_key).await.unwrap();
+        let accept = Bid::new(1, 7, 2.5, 0.5, false, true);
+        assert!(redis.resolve_rtm(&room_id, &rtm_timer_key, &current_bid, &accept, 30).await.unwrap().is_none());
+        clean_up(&redis, &room_id).await;
+    }
+
+    #[tokio::test]
+    async fn due_timer_was_claimed_once_until_its_lease_ends() {
+        let Some(redis) = redis().await else { return };
+        let mut conn = redis.connection.clone();
+        let timer_key = format!("auction:timer:{}", uuid::Uuid::new_v4());
+        conn.zadd::<_, _, _, ()>(TIMERS_ZSET, &timer_key, 1).await.unwrap();
+
+        let claims = redis.claim_due_timers(TIMER_CLAIM_BATCH, "test-node-a").await.unwrap();
+        let first = claims.iter().find(|claim| claim.timer_key == timer_key).expect("due timer was not claimed");
+        assert_eq!(first.previous_claim, None);
+        // leased, so the other servers were not claiming it meanwhile
+        let claims = redis.claim_due_timers(TIMER_CLAIM_BATCH, "test-node-b").await.unwrap();
+        assert!(claims.iter().all(|claim| claim.timer_key != timer_key));
+
+        // the lease of the first claim ends without it being completed, like a server which was down
+        conn.zadd::<_, _, _, ()>(TIMERS_ZSET, &timer_key, 1).await.unwrap();
+        let claims = redis.claim_due_timers(TIMER_CLAIM_BATCH, "test-node-b").await.unwrap();
+        let second = claims.iter().find(|claim| claim.timer_key == timer_key).expect("timer was not claimed again");
+        assert_eq!(second.previous_claim.as_deref(), Some(first.claim.as_str()));
+
+        // the first server was back, its claim was superseded
+        assert!(!redis.is_current_claim(&timer_key, &first.claim).await.unwrap());
+        assert!(!redis.complete_timer_claim(&timer_key, &first.claim).await.unwrap());
+        assert!(redis.complete_timer_claim(&timer_key, &second.claim).await.unwrap());
+        assert!(!redis.is_timer_scheduled(&timer_key).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn journal_of_a_crashed_server_was_adopted_once() {
+        let Some(redis) = redis().await else { return };
+        let orphan = format!("test-orphan-{}", uuid::Uuid::new_v4());
+        let node = format!("test-node-{}", uuid::Uuid::new_v4());
+        let other_node = format!("test-node-{}", uuid::Uuid::new_v4());
+        let command = DBCommandsAuctionRoom::SkipVoted(SkipVoted::new(&uuid::Uuid::new_v4().to_string(), 1, "player", 7));
+        redis.append_to_journal(&orphan, &command).await.unwrap();
+
+        assert!(redis.orphaned_journals(&node).await.unwrap().contains(&orphan));
+        let adopted = redis.adopt_journal(&orphan, &node).await.unwrap();
+        assert_eq!(adopted.len(), 1);
+        assert_eq!(adopted[0].1.idempotency_key(), command.idempotency_key());
+        // the other servers finds it already taken
+        assert!(redis.adopt_journal(&orphan, &other_node).await.unwrap().is_empty());
+        assert!(!redis.orphaned_journals(&other_node).await.unwrap().contains(&orphan));
+        assert_eq!(redis.journal_entries(&node).await.unwrap().len(), 1);
+
+        // a server which was only slow keeps its journal
+        let alive = format!("test-node-{}", uuid::Uuid::new_v4());
+        let entry_id = redis.append_to_journal(&alive, &command).await.unwrap();
+        redis.refresh_node_heartbeat(&alive).await.unwrap();
+        assert!(redis.adopt_journal(&alive, &node).await.unwrap().is_empty());
+        assert_eq!(redis.journal_entries(&alive).await.unwrap().len(), 1);
+
+        redis.remove_from_journal(&node, &adopted[0].0).await.unwrap();
+        redis.remove_from_journal(&alive, &entry_id).await.unwrap();
+        assert!(redis.retire_journal(&node).await.unwrap());
+        assert!(redis.retire_journal(&alive).await.unwrap());
+    }
+}
@@ -3,225 +3,162 @@ use std::time::Duration;
 use chrono::Utc;
 use redis::AsyncCommands;
 use crate::models::app_state::AppState;
-use crate::models::background_db_tasks::{DBCommandsAuctionRoom, DBCommandsAuction, IpInfoResponse, SoldPlayer, CompletedRoom, UnSoldPlayer, RoomStatus, ParticipantId, BalanceUpdate, AuctionRoomRetryTasks};
+use crate::services::auction_room::{RETRIES_OUTSIDE_ZSET, RETRIES_ZSET};
+use crate::services::dead_letters::UNPARSED_RETRY_TASK;
+use crate::models::background_db_tasks::{DBCommandsAuctionRoom, DBCommandsAuction, JournaledTask, IpInfoResponse, SoldPlayer, SaleCompleted, CompletedRoom, UnSoldPlayer, RoomStatus, ParticipantId, BalanceUpdate, AuctionRoomRetryTasks, SkipVoted};
 
-pub async fn background_tasks_executor(app_state: Arc<AppState>, mut rx: tokio::sync::mpsc::UnboundedReceiver<DBCommandsAuctionRoom>) {
+pub async fn background_tasks_executor(app_state: Arc<AppState>, mut rx: tokio::sync::mpsc::UnboundedReceiver<JournaledTask>) {
     tracing::info!("Background tasks executor for postgres sql started");
-    while let Some(command) = rx.recv().await {
-        match command {
-            DBCommandsAuctionRoom::UpdateRemainingRTMS(mut participant) => {
-                tracing::info!("Updating remaining rtms for {}", participant.id);
-                match app_state.database_connection.update_remaining_rtms(participant.id).await {
-                   Ok(_res) => {
-                       tracing::info!("Updated remaining rtms for {}", participant.id);
-                   } ,
-                    Err(err) =>{
-                        tracing::error!("Failed to update remaining rtms for {} : {}", participant.id, err);
-                        participant.retry_count += 1 ;
-                        participant.last_error =  err.to_string() ;
-                        app_state.redis_connection.add_retry_task(&DBCommandsAuctionRoom::UpdateRemainingRTMS(participant), &app_state).await.unwrap();
-                    } // we need to check a way for the failed tasks, retry logics
-                } ;
-            },
-            DBCommandsAuctionRoom::BalanceUpdate(mut balance_update) => {
-                tracing::info!("balance update was being called") ;
-                match app_state.database_connection.update_balance(balance_update.participant_id, balance_update.remaining_balance).await {
-                    Ok(_res) => {
-                        tracing::info!("balance successfully updated") ;
-                    },
-                    Err(err) => {
-                        tracing::error!("error for balance update was {}", err) ;
-                        balance_update.retry_count += 1 ;
-                        balance_update.last_error = err.to_string() ;
-                        app_state.redis_connection.add_retry_task(&DBCommandsAuctionRoom::BalanceUpdate(balance_update), &app_state).await.unwrap();
-                    }
-                }
-            },
-            DBCommandsAuctionRoom::PlayerSold(mut player_sold) => {
-                tracing::info!("player_sold was being being executed as a background task") ;
-                match app_state.database_connection.add_sold_player(&player_sold.room_id,player_sold.player_id,player_sold.participant_id, player_sold.bid_amount).await {
-                    Ok(_res) => {
-                        tracing::info!("successfully executed the add_sold_player") ;
-                    },
-                    Err(err) => {
-                        tracing::error!("error for player unsold was {}", err) ;
-                        player_sold.retry_count += 1 ;
-                        player_sold.last_error = err.to_string() ;
-                        app_state.redis_connection.add_retry_task(&DBCommandsAuctionRoom::PlayerSold(player_sold), &app_state).await.unwrap();
-                    }
-                }
-            },
-            DBCommandsAuctionRoom::PlayerUnSold(mut player_un_sold) => {
-                tracing::info!("player_un_sold was being executed as a background task") ;
-                match app_state.database_connection.add_unsold_player(&player_un_sold.room_id,player_un_sold.player_id).await {
-                    Ok(_res) => {
-                        tracing::info!("successfully executed player unsold") ;
-                    },
-                    Err(err) => {
-                        tracing::error!("error for player unsold was {}", err) ;
-                        player_un_sold.retry_count += 1 ;
-                        player_un_sold.last_error = err.to_string() ;
-                        app_state.redis_connection.add_retry_task(&DBCommandsAuctionRoom::PlayerUnSold(player_un_sold), &app_state).await.unwrap();
-                    }
+    while let Some(JournaledTask { mut entry_id, mut command }) = rx.recv().await {
+        tracing::info!("{} task was being executed as a background task", command.task_type()) ;
+        match execute_auction_room_task(&app_state.database_connection, &command).await {
+            Ok(true) => {
+                tracing::info!("successfully executed the {} task", command.task_type()) ;
+                metrics::histogram!("db_task_attempts", "task_type" => command.task_type()).record(f64::from(command.retry_count()) + 1.0);
+                if command.enqueued_at() > 0 {
+                    let elapsed = (Utc::now().timestamp_millis() - command.enqueued_at()).max(0) as f64 / 1000.0;
+                    metrics::histogram!("db_task_time_to_success_seconds", "task_type" => command.task_type()).record(elapsed);
                 }
             },
-            DBCommandsAuctionRoom::UpdateRoomStatus(mut room_status) => {
-                tracing::info!("Room status update was being executed as background task") ;
-                match app_state.database_connection.update_room_status(&room_status.room_id, &room_status.status).await {
-                    Ok(_res) => {
-                        tracing::info!("successfully executed update room-status") ;
-                    },
-                    Err(err) => {
-                        tracing::error!("error for room-status updating {}", err) ;
-                        room_status.retry_count += 1 ;
-                        room_status.last_error = err.to_string() ;
-                        app_state.redis_connection.add_retry_task(&DBCommandsAuctionRoom::UpdateRoomStatus(
-                            room_status
-                        ), &app_state).await.unwrap();
-                    }
-                }
-            },
-            DBCommandsAuctionRoom::CompletedRoomCompletedAt(mut completed_room) => {
-                tracing::info!("updating completed_at") ;
-                match app_state.database_connection.set_completed_at(&completed_room.room_id).await {
-                    Ok(_) => {
-                        tracing::info!("successfully updated completed_at for room_id {}", completed_room.room_id) ;
-                    },
-                    Err(err) => {
-                        tracing::error!("error while updating the completedAt for room_id {} and error was {}", completed_room.room_id, err.to_string()) ;
-                        completed_room.retry_count += 1 ;
-                        completed_room.last_error = err.to_string() ;
-                        app_state.redis_connection.add_retry_task(&DBCommandsAuctionRoom::CompletedRoomCompletedAt(
-                            completed_room
-                        ), &app_state).await.unwrap();
-                    }
-                }
-            },
-            DBCommandsAuctionRoom::CompletedRoomUnsoldPlayers(mut completed_room) => {
-                tracing::info!("completed unsold players") ;
-                // we need to make sure an atomicity to be takes place
-                let mut tx: Transaction<Postgres> = app_state.database_connection.connection.begin().await.expect("") ;
-                // firstly move the players to the completed rooms unsold players table
-                match DatabaseAccess::add_to_completed_room_unsold_players(&mut tx, &completed_room.room_id).await {
-                    Ok(_) => {
-                        tracing::info!("sucessfully add unsold players to completed_rooms table for room_id {}", completed_room.room_id) ;
-
-                        match DatabaseAccess::remove_unsold_players(&mut tx, &completed_room.room_id).await {
-                            Ok(_) => {
-                                tx.commit().await.expect("unable to commit transaction") ;
-                                tracing::info!("successfully removed unsold players from unsold_players room for room_id {}", completed_room.room_id) ;
-                            },
-                            Err(err) => {
-                                tx.rollback().await.expect("unable to rollback") ;
-                                completed_room.retry_count += 1 ;
-                                completed_room.last_error = err.to_string() ;
-                                app_state.redis_connection.add_retry_task(&DBCommandsAuctionRoom::CompletedRoomUnsoldPlayers(
-                                    completed_room
-                                ), &app_state).await.unwrap();
-                            }
-                        }
-                    },
-                    Err(err) => {
-                        tx.rollback().await.expect("unable to rollback") ;
-                        completed_room.retry_count += 1 ;
-                        completed_room.last_error = err.to_string() ;
-                        app_state.redis_connection.add_retry_task(&DBCommandsAuctionRoom::CompletedRoomUnsoldPlayers(
-                            completed_room
-                        ), &app_state).await.unwrap();
-                    }
-                }
-
+            Ok(false) => {
+                // the task was committed before, but it was replayed from the retries or the journal
+                tracing::warn!("{} task {} was already applied, skipping it", command.task_type(), command.idempotency_key()) ;
+                metrics::counter!("db_tasks_already_applied_total").increment(1);
             },
-            DBCommandsAuctionRoom::CompletedRoomSoldPlayers(mut completed_room) => {
-                tracing::info!("completed sold players") ;
-                // we need to make sure an atomicity to be takes place
-                let mut tx: Transaction<Postgres> = app_state.database_connection.connection.begin().await.expect("") ;
-                // firstly move the players to the completed rooms unsold players table
-                match DatabaseAccess::add_to_completed_room_sold_players(&mut tx, &completed_room.room_id).await {
-                    Ok(_) => {
-                        tracing::info!("successfully add sold players to completed_rooms table for room_id {}", completed_room.room_id) ;
-
-                        match DatabaseAccess::remove_sold_players(&mut tx, &completed_room.room_id).await {
-                            Ok(_) => {
-                                tx.commit().await.expect("unable to commit transaction") ;
-                                tracing::info!("successfully removed sold players from sold_players room for room_id {}", completed_room.room_id) ;
-                            },
-                            Err(err) => {
-                                tx.rollback().await.expect("unable to rollback") ;
-                                completed_room.retry_count += 1 ;
-                                completed_room.last_error = err.to_string() ;
-                                app_state.redis_connection.add_retry_task(&DBCommandsAuctionRoom::CompletedRoomSoldPlayers(
-                                    completed_room
-                                ), &app_state).await.unwrap();
-                            }
-                        }
-                    },
+            Err(err) => {
+                tracing::error!("error for the {} task was {}", command.task_type(), err) ;
+                command.failed(err.to_string()) ;
+                match app_state.redis_connection.add_retry_task(&command, entry_id.clone(), &app_state).await {
+                    Ok(false) => {},
+                    // the DLQ removes the journal entry once the task was saved
+                    Ok(true) => entry_id = None,
                     Err(err) => {
-                        tx.rollback().await.expect("unable to rollback") ;
-                        completed_room.retry_count += 1 ;
-                        completed_room.last_error = err.to_string() ;
-                        app_state.redis_connection.add_retry_task(&DBCommandsAuctionRoom::CompletedRoomSoldPlayers(
-                            completed_room
-                        ), &app_state).await.unwrap();
+                        tracing::error!("unable to schedule the retry of the {} task, it was left in the journal {}", command.task_type(), err) ;
+                        entry_id = None ;
                     }
                 }
             }
         }
+        // a failed task was already in the retries, so it was still not lost
+        app_state.auction_room_database_task_executor.done(entry_id).await;
     }
 }
 
+// the task and its idempotency key were committed in one transaction, false if the key was already applied
+pub async fn execute_auction_room_task(database: &DatabaseAccess, command: &DBCommandsAuctionRoom) -> Result<bool, sqlx::Error> {
+    let mut tx: Transaction<Postgres> = database.connection.begin().await?;
+    if !DatabaseAccess::record_applied_task(&mut tx, command.idempotency_key(), command.task_type()).await? {
+        return Ok(false);
+    }
+    match command {
+        DBCommandsAuctionRoom::UpdateRemainingRTMS(participant) => {
+            DatabaseAccess::update_remaining_rtms(&mut tx, participant.id).await?;
+        },
+        DBCommandsAuctionRoom::BalanceUpdate(balance_update) => {
+            DatabaseAccess::update_balance(&mut tx, balance_update.participant_id, balance_update.remaining_balance).await?;
+        },
+        DBCommandsAuctionRoom::PlayerSold(player_sold) => {
+            DatabaseAccess::add_sold_player(&mut tx, &player_sold.room_id, player_sold.player_id, player_sold.participant_id, player_sold.bid_amount).await?;
+        },
+        DBCommandsAuctionRoom::SaleCompleted(sale) => {
+            DatabaseAccess::complete_sale(&mut tx, sale).await?;
+        },
+        DBCommandsAuctionRoom::PlayerUnSold(player_un_sold) => {
+            DatabaseAccess::add_unsold_player(&mut tx, &player_un_sold.room_id, player_un_sold.player_id).await?;
+        },
+        DBCommandsAuctionRoom::UpdateRoomStatus(room_status) => {
+            DatabaseAccess::update_room_status(&mut *tx, &room_status.room_id, &room_status.status).await?;
+        },
+        DBCommandsAuctionRoom::CompletedRoomCompletedAt(completed_room) => {
+            DatabaseAccess::set_completed_at(&mut tx, &completed_room.room_id).await?;
+        },
+        DBCommandsAuctionRoom::CompletedRoomUnsoldPlayers(completed_room) => {
+            // firstly move the players to the completed rooms unsold players table
+            DatabaseAccess::add_to_completed_room_unsold_players(&mut tx, &completed_room.room_id).await?;
+            DatabaseAccess::remove_unsold_players(&mut tx, &completed_room.room_id).await?;
+        },
+        DBCommandsAuctionRoom::CompletedRoomSoldPlayers(completed_room) => {
+            DatabaseAccess::add_to_completed_room_sold_players(&mut tx, &completed_room.room_id).await?;
+            DatabaseAccess::remove_sold_players(&mut tx, &completed_room.room_id).await?;
+        },
+        DBCommandsAuctionRoom::SkipVoted(skip) => {
+            DatabaseAccess::add_skip_vote(&mut tx, skip).await?;
+        }
+    }
+    tx.commit().await?;
+    Ok(true)
+}
+
 
 pub async fn background_task_executor_outside_auction_db_calls(app_state: Arc<AppState>, mut rx: tokio::sync::mpsc::UnboundedReceiver<DBCommandsAuction>) {
     tracing::info!("Background tasks executor for postgres sql started");
-    let api_key = std::env::var("IP_INFO_API_KEY").unwrap();
-    while let Some(command) = rx.recv().await {
-        match command {
-            DBCommandsAuction::AddUserExternalDetails(user_details) => {
-                tracing::info!("Got user details") ;
-                /*
-                    over here we are going to get the location based on the ip address using the API.
-                */
-                tracing::info!("using the ip address to get the geo location") ;
-                let location = get_location(&user_details.ip_address, &api_key).await.unwrap();
-                tracing::warn!("location was {}", location) ;
-
-                tracing::info!("starting to store the details in the database") ;
-                match app_state.database_connection.add_location(user_details.user_id, &location).await { 
-                  Ok(_res) => {
-                       tracing::info!("successfully stored the location in the database") ;
-                   } ,
-                    Err(err) =>{
-                        tracing::error!("error while storing the location in the database {}", err) ;
-                    }  
-                };
+    let api_key = std::env::var("IP_INFO_API_KEY").unwrap_or_else(|_| {
+        tracing::warn!("IP_INFO_API_KEY was not set, the location lookups will fail and be retried") ;
+        String::new()
+    });
+    while let Some(mut command) = rx.recv().await {
+        tracing::info!("{} task was being executed as a background task", command.task_type()) ;
+        match execute_outside_auction_task(&app_state.database_connection, &command, &api_key).await {
+            Ok(()) => {
+                tracing::info!("successfully executed the {} task", command.task_type()) ;
+                metrics::histogram!("db_task_attempts", "task_type" => command.task_type()).record(f64::from(command.retry_count()) + 1.0);
             },
-            DBCommandsAuction::FavoriteTeamUpdated(fav_team) => {
-                tracing::info!("favorite team was updated") ;
-                /*
-                    over here we are going to add the new record to the table favorite_teams_update.
-                */
+            Err(err) => {
+                tracing::error!("error for the {} task was {}", command.task_type(), err) ;
+                command.failed(err) ;
+                if let Err(err) = app_state.redis_connection.add_outside_retry_task(&command, &app_state).await {
+                    tracing::error!("unable to schedule the retry of the {} task, it was lost {}", command.task_type(), err) ;
+                    metrics::counter!("db_task_lost_total", "task_type" => command.task_type()).increment(1);
+                }
             }
         }
+        app_state.database_task_executor.done();
     }
 }
 
+// the failures were returned instead of panicking, so they were retried like the auction room tasks
+async fn execute_outside_auction_task(database: &DatabaseAccess, command: &DBCommandsAuction, api_key: &str) -> Result<(), String> {
+    match command {
+        DBCommandsAuction::AddUserExternalDetails(user_details) => {
+            /*
+                over here we are going to get the location based on the ip address using the API.
+            */
+            tracing::info!("using the ip address to get the geo location") ;
+            let location = get_location(&user_details.ip_address, api_key).await
+                .map_err(|err| format!("unable to get the location {}", err))?;
+            tracing::warn!("location was {}", location) ;
+
+            tracing::info!("starting to store the details in the database") ;
+            database.add_location(user_details.user_id, &location).await
+                .map_err(|err| format!("error while storing the location in the database {}", err))?;
+        },
+        DBCommandsAuction::FavoriteTeamUpdated(_fav_team) => {
+            tracing::info!("favorite team was updated") ;
+            /*
+                over here we are going to add the new record to the table favorite_teams_update.
+            */
+        }
+    }
+    Ok(())
+}
+
 
 use reqwest::Client;
 use sqlx::{Postgres, Transaction};
 use crate::services::auction::DatabaseAccess;
 
 pub async fn get_location(ip_address: &str, api_key: &str) -> Result<String, reqwest::Error> {
-    println!("Getting Location for IP Address: {}", ip_address);
+    tracing::debug!("getting the location of the ip address {}", ip_address);
 
     let url = format!("https://ipinfo.io/{}?token={}", ip_address, api_key);
 
     let client = Client::new();
-    let response = client.get(&url).send().await?;
-
-    println!("Response from IP Geolocation API: {:?}", response);
+    // the url carries the api key, so it was removed from the errors which were logged and saved to the DLQ
+    let response = client.get(&url).send().await
+        .and_then(|response| response.error_for_status())
+        .map_err(reqwest::Error::without_url)?;
 
-    let mut data: IpInfoResponse = response.json().await?;
-    println!("Data from IP Geolocation API: {:?}", data);
+    let mut data: IpInfoResponse = response.json().await.map_err(reqwest::Error::without_url)?;
 
     // Convert IN → India
     if let Some(country) = &data.country {
@@ -240,106 +177,282 @@ pub async fn get_location(ip_address: &str, api_key: &str) -> Result<String, req
 }
 
 
-pub async fn listening_to_retries(app_state: Arc<AppState>) {
+// the retries were kept in the zset while the server was down, the loop stops after the batch it was moving on shutdown
+pub async fn listening_to_retries(app_state: Arc<AppState>) -> tokio::task::JoinHandle<()> {
 
     tokio::spawn(async move {
         tracing::info!("starting listening to retries") ;
         let mut redis_connection = app_state.redis_connection.connection.clone();
-        loop {
+        let mut shutdown = app_state.shutdown.subscribe();
+        while !*shutdown.borrow() {
             let now = Utc::now().timestamp();
 
-            let due_tasks: Vec<String> = redis_connection
-                .zrangebyscore(
-                    "auction:retry:zset",
-                    "-inf",
-                    now
-                )
-                .await.unwrap();
-
-            for task_json in due_tasks {
-               let _: usize =  redis_connection.zrem("auction:retry:zset", &task_json).await.expect("error while retrieving auction") ;
-
-
-                match serde_json::from_str(&task_json).unwrap() {
-                    DBCommandsAuctionRoom::UpdateRemainingRTMS(participant) => {
-                        tracing::info!("Updating remaining rtms all retries were Exhausted");
-                        // need to figure out what envelope was it , list at least the task name
-                        app_state.auction_room_database_task_executor.send(DBCommandsAuctionRoom::UpdateRemainingRTMS(participant)).unwrap() ;
-                    },
-                    DBCommandsAuctionRoom::BalanceUpdate(balance_update) => {
-                        tracing::info!("balance update all retries were Exhausted") ;
-                        app_state.auction_room_database_task_executor.send(DBCommandsAuctionRoom::BalanceUpdate(balance_update)).expect("") ;
-                    },
-                    DBCommandsAuctionRoom::PlayerSold(player_sold) => {
-                        tracing::info!("player_sold all retries were Exhausted") ;
-                        app_state.auction_room_database_task_executor.send(DBCommandsAuctionRoom::PlayerSold(player_sold)).expect("") ;
-                    },
-                    DBCommandsAuctionRoom::PlayerUnSold(player_un_sold) => {
-                        tracing::info!("player_un_sold all retries were Exhausted") ;
-                        app_state.auction_room_database_task_executor.send(DBCommandsAuctionRoom::PlayerUnSold(player_un_sold)).expect("") ;
-                    },
-                    DBCommandsAuctionRoom::UpdateRoomStatus(room_status) => {
-                        tracing::info!("Room status update all retries were Exhausted") ;
-                        app_state.auction_room_database_task_executor.send(DBCommandsAuctionRoom::UpdateRoomStatus(room_status)).expect("") ;
-                    },
-                    DBCommandsAuctionRoom::CompletedRoomSoldPlayers(completed_room) => {
-                        tracing::info!("this never exists, the tasks inside this will execute independently") ;
-                        app_state.auction_room_database_task_executor.send(DBCommandsAuctionRoom::CompletedRoomSoldPlayers(completed_room) ).expect("") ;
-                    },
-                    DBCommandsAuctionRoom::CompletedRoomCompletedAt(completed_room) => {
-                        app_state.auction_room_database_task_executor.send(DBCommandsAuctionRoom::CompletedRoomCompletedAt(completed_room)).expect("") ;
-                    },
-                    DBCommandsAuctionRoom::CompletedRoomUnsoldPlayers(completed_room) => {
-                        app_state.auction_room_database_task_executor.send(DBCommandsAuctionRoom::CompletedRoomUnsoldPlayers(completed_room)).expect("") ;
-
-                    }
+            // a failed poll was tried again after the sleep, the retries stay in the zset till then
+            let due_tasks: Vec<String> = match redis_connection.zrangebyscore(RETRIES_ZSET, "-inf", now).await {
+                Ok(due_tasks) => due_tasks,
+                Err(err) => {
+                    tracing::error!("unable to get the due retries {}", err) ;
+                    metrics::counter!("db_task_retry_poll_failures_total").increment(1);
+                    vec![]
                 }
+            };
 
+            for task_json in due_tasks {
+                requeue_due_task(&app_state, &mut redis_connection, task_json).await ;
+            }
 
+            if let Err(err) = requeue_due_outside_tasks(&app_state, &mut redis_connection, now).await {
+                tracing::error!("unable to move the due retries of the outside tasks {}", err) ;
             }
 
-            tokio::time::sleep(Duration::from_secs(1)).await;
+            // sleeps until the next due retry, the retries added meanwhile were picked within the poll interval
+            let mut next_due: Vec<(String, i64)> = redis_connection.zrange_withscores(RETRIES_ZSET, 0, 0).await.unwrap_or_default();
+            next_due.extend(redis_connection.zrange_withscores::<_, Vec<(String, i64)>>(RETRIES_OUTSIDE_ZSET, 0, 0).await.unwrap_or_default());
+            let sleep = match next_due.iter().min_by_key(|(_, retry_at)| *retry_at) {
+                Some((_, retry_at)) => Duration::from_secs((retry_at - Utc::now().timestamp()).max(1) as u64).min(app_state.retry_policy.poll_max),
+                None => app_state.retry_policy.poll_max
+            };
+            tokio::select! {
+                _ = tokio::time::sleep(sleep) => {},
+                _ = shutdown.changed() => {}
+            }
         }
         tracing::warn!("Stopped listening to the retries") ;
-    }) ;
+    })
 
 }
 
 
-pub async fn save_to_DLQ(app_state: Arc<AppState>, mut rx: tokio::sync::mpsc::UnboundedReceiver<DBCommandsAuctionRoom>) {
+/*
+    the task was removed from the retries only after it was queued (and journaled) again, a task which was not
+    queued was tried again on the next poll. an entry which was not parsed was saved to the DLQ as it was.
+*/
+async fn requeue_due_task(app_state: &AppState, redis_connection: &mut redis::aio::MultiplexedConnection, task_json: String) {
+    match serde_json::from_str::<DBCommandsAuctionRoom>(&task_json) {
+        Ok(command) => {
+            let task_type = command.task_type() ;
+            tracing::info!("retrying the {} task, attempt {}", task_type, command.retry_count() + 1) ;
+            if let Err(err) = app_state.auction_room_database_task_executor.send(command).await {
+                tracing::error!("unable to queue the retry of the {} task, it was tried again on the next poll {}", task_type, err) ;
+                metrics::counter!("db_task_retry_requeue_failures_total", "task_type" => task_type).increment(1);
+                return;
+            }
+        },
+        Err(err) => {
+            tracing::error!("unable to parse the retried task, moving it to the DLQ {}", err) ;
+            metrics::counter!("db_task_retry_parse_failures_total").increment(1);
+            if let Err(err) = app_state.database_connection.add_unparsed_to_dlq(UNPARSED_RETRY_TASK, &task_json, &err.to_string()).await {
+                tracing::error!("unable to save the unparsed retry to the DLQ, it was tried again on the next poll {}", err) ;
+                return;
+            }
+        }
+    }
+    if let Err(err) = redis_connection.zrem::<_, _, usize>(RETRIES_ZSET, &task_json).await {
+        // the task may be queued again on the next poll, which was skipped by its idempotency key
+        tracing::error!("unable to remove the queued retry {}", err) ;
+    }
+}
+
+
+async fn requeue_due_outside_tasks(app_state: &AppState, redis_connection: &mut redis::aio::MultiplexedConnection, now: i64) -> redis::RedisResult<()> {
+    let due_tasks: Vec<String> = redis_connection.zrangebyscore(RETRIES_OUTSIDE_ZSET, "-inf", now).await?;
+    for task_json in due_tasks {
+        let _: usize = redis_connection.zrem(RETRIES_OUTSIDE_ZSET, &task_json).await?;
+        match serde_json::from_str::<DBCommandsAuction>(&task_json) {
+            Ok(command) => {
+                tracing::info!("retrying the {} task", command.task_type()) ;
+                if let Err(err) = app_state.database_task_executor.send(command) {
+                    tracing::error!("unable to send the retried task to the executor {}", err) ;
+                }
+            },
+            Err(err) => tracing::error!("unable to parse the retried outside task {}", err)
+        }
+    }
+    Ok(())
+}
 
-    while let Some(command) = rx.recv().await {
-        match  command {
+
+pub async fn save_to_DLQ(app_state: Arc<AppState>, mut rx: tokio::sync::mpsc::UnboundedReceiver<JournaledTask>) {
+
+    while let Some(JournaledTask { entry_id, command }) = rx.recv().await {
+        let task_type = command.task_type() ;
+        tracing::info!("saving the {} task to the DLQ after {} failed attempts", task_type, command.retry_count()) ;
+        let saved = match command {
             DBCommandsAuctionRoom::UpdateRemainingRTMS(participant) => {
-                tracing::info!("Updating remaining rtms all retries were Exhausted");
-                app_state.database_connection.add_to_dlq::<ParticipantId>("UpdatingRTMs", participant.clone(),participant.retry_count as i16, &participant.last_error ).await.expect("") ;
+                app_state.database_connection.add_to_dlq::<ParticipantId>("UpdatingRTMs", participant.clone(),participant.retry_count as i16, &participant.last_error ).await
             },
             DBCommandsAuctionRoom::BalanceUpdate(balance_update) => {
-                tracing::info!("balance update all retries were Exhausted") ;
-                app_state.database_connection.add_to_dlq::<BalanceUpdate>("UpdatingBalance", balance_update.clone(), balance_update.retry_count as i16, &balance_update.last_error).await.expect("") ;
+                app_state.database_connection.add_to_dlq::<BalanceUpdate>("UpdatingBalance", balance_update.clone(), balance_update.retry_count as i16, &balance_update.last_error).await
             },
             DBCommandsAuctionRoom::PlayerSold(player_sold) => {
-                tracing::info!("player_sold all retries were Exhausted") ;
-                app_state.database_connection.add_to_dlq::<SoldPlayer>("SellingPlayer", player_sold.clone(), player_sold.retry_count as i16, &player_sold.last_error).await.expect("") ;
+                app_state.database_connection.add_to_dlq::<SoldPlayer>("SellingPlayer", player_sold.clone(), player_sold.retry_count as i16, &player_sold.last_error).await
             },
             DBCommandsAuctionRoom::PlayerUnSold(player_un_sold) => {
-                tracing::info!("player_un_sold all retries were Exhausted") ;
-                app_state.database_connection.add_to_dlq::<UnSoldPlayer>("UnsoldPlayer", player_un_sold.clone(), player_un_sold.retry_count as i16, &player_un_sold.last_error).await.expect("") ;
+                app_state.database_connection.add_to_dlq::<UnSoldPlayer>("UnsoldPlayer", player_un_sold.clone(), player_un_sold.retry_count as i16, &player_un_sold.last_error).await
             },
             DBCommandsAuctionRoom::UpdateRoomStatus(room_status) => {
-                tracing::info!("Room status update all retries were Exhausted") ;
-                app_state.database_connection.add_to_dlq::<RoomStatus>("UpdatingRoomStatus", room_status.clone(), room_status.retry_count as i16, &room_status.last_error).await.expect("") ;
+                app_state.database_connection.add_to_dlq::<RoomStatus>("UpdatingRoomStatus", room_status.clone(), room_status.retry_count as i16, &room_status.last_error).await
             },
             DBCommandsAuctionRoom::CompletedRoomSoldPlayers(completed_room) => {
                 tracing::info!("this never exists, the tasks inside this will execute independently") ;
-                app_state.database_connection.add_to_dlq::<CompletedRoom>("CompletedRoomSoldPlayers", completed_room.clone(), completed_room.retry_count as i16, &completed_room.last_error).await.expect("") ;
+                app_state.database_connection.add_to_dlq::<CompletedRoom>("CompletedRoomSoldPlayers", completed_room.clone(), completed_room.retry_count as i16, &completed_room.last_error).await
             },
             DBCommandsAuctionRoom::CompletedRoomCompletedAt(completed_room) => {
-                app_state.database_connection.add_to_dlq::<CompletedRoom>("CompletedRoomCompletedAt", completed_room.clone(), completed_room.retry_count as i16, &completed_room.last_error).await.expect("") ;
+                app_state.database_connection.add_to_dlq::<CompletedRoom>("CompletedRoomCompletedAt", completed_room.clone(), completed_room.retry_count as i16, &completed_room.last_error).await
             },
             DBCommandsAuctionRoom::CompletedRoomUnsoldPlayers(completed_room) => {
-                app_state.database_connection.add_to_dlq::<CompletedRoom>("CompletedRoomUnsoldPlayers", completed_room.clone(), completed_room.retry_count as i16, &completed_room.last_error).await.expect("") ;
+                app_state.database_connection.add_to_dlq::<CompletedRoom>("CompletedRoomUnsoldPlayers", completed_room.clone(), completed_room.retry_count as i16, &completed_room.last_error).await
+            },
+            DBCommandsAuctionRoom::SaleCompleted(sale) => {
+                app_state.database_connection.add_to_dlq::<SaleCompleted>("SaleCompleted", sale.clone(), sale.retry_count as i16, &sale.last_error).await
+            },
+            DBCommandsAuctionRoom::SkipVoted(skip) => {
+                app_state.database_connection.add_to_dlq::<SkipVoted>("SkipVoted", skip.clone(), skip.retry_count as i16, &skip.last_error).await
             }
         };
+        // the journal entry was removed only after the task was committed to the dead_letter_tasks
+        match saved {
+            Ok(()) => if let Some(entry_id) = entry_id {
+                app_state.auction_room_database_task_executor.forget(&entry_id).await ;
+            },
+            Err(err) => {
+                tracing::error!("unable to save the {} task to the DLQ, it was left in the journal {}", task_type, err) ;
+                metrics::counter!("db_task_dlq_write_failures_total", "task_type" => task_type).increment(1);
+            }
+        }
+        app_state.dlq_task_executor.done();
+    }
+}
+
+/*
+    these tests run against the postgres of DATABASE_URL with the migrations applied, they were skipped when it was
+    not set. every test creates a room of its own, so they can run along with each other.
+*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::postgres::PgPoolOptions;
+    use sqlx::Row;
+
+    async fn database() -> Option<DatabaseAccess> {
+        let Ok(url) = std::env::var("DATABASE_URL") else {
+            eprintln!("DATABASE_URL was not set, skipping the test");
+            return None;
+        };
+        let connection = PgPoolOptions::new().max_connections(2).connect(&url).await.expect("unable to connect to postgres");
+        Some(DatabaseAccess { connection })
+    }
+
+    // a room with one participant, returns the room id and the participant id
+    async fn room_with_participant(database: &DatabaseAccess) -> (String, i32) {
+        let name = uuid::Uuid::new_v4().to_string();
+        let user_id: i32 = sqlx::query_scalar("insert into users (username, mail_id) values ($1, $1) returning id")
+            .bind(&name)
+            .fetch_one(&database.connection).await.unwrap();
+        let room_id: sqlx::types::Uuid = sqlx::query_scalar("insert into rooms (creator_id, status) values ($1, 'in_progress') returning id")
+            .bind(user_id)
+            .fetch_one(&database.connection).await.unwrap();
+        let participant_id: i32 = sqlx::query_scalar("insert into participants (user_id, room_id, team_selected, purse_remaining) values ($1, $2, 'Mumbai Indians', 100) returning id")
+            .bind(user_id)
+            .bind(room_id)
+            .fetch_one(&database.connection).await.unwrap();
+        (room_id.to_string(), participant_id)
     }
-}
\ No newline at end of file
+
+    // players were created with an id of their own, such that a test can sell a player before it exists
+    async fn add_player(database: &DatabaseAccess, player_id: i32) {
+        sqlx::query("insert into players (id, name, base_price, role, is_indian, pool_no) values ($1, 'Test Player', 0.5, 'BAT', true, 1)")
+            .bind(player_id)
+            .execute(&database.connection).await.unwrap();
+    }
+
+    fn sale(room_id: &str, player_id: i32, participant_id: i32) -> DBCommandsAuctionRoom {
+        DBCommandsAuctionRoom::SaleCompleted(SaleCompleted {
+            room_id: room_id.to_string(),
+            player_id,
+            participant_id,
+            bid_amount: 7.5,
+            remaining_balance: 92.5,
+            decrement_rtm: true,
+            idempotency_key: format!("sale:{}:{}", room_id, player_id),
+            enqueued_at: 0,
+            retry_count: 0,
+            last_error: String::new()
+        })
+    }
+
+    // purse, rtms and the sold players of the participant
+    async fn participant_state(database: &DatabaseAccess, participant_id: i32) -> (f32, i16, i64) {
+        let row = sqlx::query("select p.purse_remaining, p.remaining_rtms, (select count(*) from sold_players s where s.participant_id = p.id) as sold from participants p where p.id = $1")
+            .bind(participant_id)
+            .fetch_one(&database.connection).await.unwrap();
+        (row.get("purse_remaining"), row.get("remaining_rtms"), row.get("sold"))
+    }
+
+    fn unique_player_id() -> i32 {
+        rand::random_range(1_000_000..i32::MAX)
+    }
+
+    #[tokio::test]
+    async fn replayed_sale_was_applied_once() {
+        let Some(database) = database().await else { return };
+        let (room_id, participant_id) = room_with_participant(&database).await;
+        let player_id = unique_player_id();
+        add_player(&database, player_id).await;
+        let command = sale(&room_id, player_id, participant_id);
+
+        assert!(execute_auction_room_task(&database, &command).await.unwrap());
+        // the same entry replayed from the journal, the retries or the DLQ
+        assert!(!execute_auction_room_task(&database, &command).await.unwrap());
+        assert_eq!(participant_state(&database, participant_id).await, (92.5, 2, 1));
+    }
+
+    #[tokio::test]
+    async fn failed_sale_was_rolled_back_along_with_its_key() {
+        let Some(database) = database().await else { return };
+        let (room_id, participant_id) = room_with_participant(&database).await;
+        let player_id = unique_player_id();
+        let command = sale(&room_id, player_id, participant_id);
+
+        // the player was not there, so the sold player was not inserted
+        assert!(execute_auction_room_task(&database, &command).await.is_err());
+        assert_eq!(participant_state(&database, participant_id).await, (100.0, 3, 0));
+        let applied: i64 = sqlx::query_scalar("select count(*) from applied_tasks where idempotency_key = $1")
+            .bind(command.idempotency_key())
+            .fetch_one(&database.connection).await.unwrap();
+        assert_eq!(applied, 0);
+
+        // the retry of the task was applied, as its key was not recorded
+        add_player(&database, player_id).await;
+        assert!(execute_auction_room_task(&database, &command).await.unwrap());
+        assert_eq!(participant_state(&database, participant_id).await, (92.5, 2, 1));
+    }
+
+    #[tokio::test]
+    async fn sale_without_its_participant_was_not_committed() {
+        let Some(database) = database().await else { return };
+        let (room_id, participant_id) = room_with_participant(&database).await;
+        let player_id = unique_player_id();
+        add_player(&database, player_id).await;
+        // the participant was removed after the sale was queued
+        sqlx::query("delete from participants where id = $1").bind(participant_id).execute(&database.connection).await.unwrap();
+
+        assert!(execute_auction_room_task(&database, &sale(&room_id, player_id, participant_id)).await.is_err());
+        let sold: i64 = sqlx::query_scalar("select count(*) from sold_players where player_id = $1")
+            .bind(player_id)
+            .fetch_one(&database.connection).await.unwrap();
+        assert_eq!(sold, 0);
+    }
+
+    #[tokio::test]
+    async fn skip_vote_was_recorded_once() {
+        let Some(database) = database().await else { return };
+        let (room_id, participant_id) = room_with_participant(&database).await;
+        let command = DBCommandsAuctionRoom::SkipVoted(SkipVoted::new(&room_id, participant_id, "player", 12));
+
+        assert!(execute_auction_room_task(&database, &command).await.unwrap());
+        assert!(!execute_auction_room_task(&database, &command).await.unwrap());
+        assert_eq!(database.get_skip_votes(&room_id, "player", 12).await.unwrap(), vec![participant_id]);
+        assert!(database.get_skip_votes(&room_id, "pool", 12).await.unwrap().is_empty());
+    }
+}
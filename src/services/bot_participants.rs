@@ -0,0 +1,269 @@
+use std::sync::Arc;
+use std::time::Duration;
+use crate::auction::{bid_allowance_handler, broadcast_server_message, send_error, send_server_message};
+use crate::models::app_state::{AppState, Player};
+use crate::models::auction_models::{AuctionParticipant, RoomRules};
+use crate::models::protocol_models::{ErrorCode, ServerMessage};
+use crate::models::room_models::RoomCommand;
+use crate::services::auction_logic_executor::{bid, skip};
+use crate::services::proxy_bidding::run_proxy_bids;
+
+/*
+    Bot participants: the creator of the room can add bots from the lobby with add-bot, such that small groups
+    can reach the min of 3 participants. a bot takes one of the remaining teams, gets a participant row like any other
+    participant and bids from the server, based on the balance it has and the roles it still needs.
+    bots were counted as participants, but they were not having any websocket connection.
+
+    Autopilot: a participant can opt in with autopilot-on (and opt out with autopilot-off), when he got disconnected
+    his team stays in the room and bids with the same strategy as bots, until he reconnects.
+*/
+
+// time taken by the bots before responding to a new player or a new bid, such that humans can also bid
+const BOT_THINKING_TIME: Duration = Duration::from_millis(1500);
+
+
+#[tracing::instrument(
+    name = "add_bot",
+    skip(app_state),
+    fields(
+        room_id = %room_id,
+        participant_id = participant_id
+    )
+)]
+pub async fn add_bot(room_id: &str, participant_id: i32, app_state: &AppState) {
+    let redis_connection = app_state.redis_connection.clone() ;
+    if redis_connection.get_room_meta(room_id).await.unwrap().unwrap().room_creator_id != participant_id {
+        send_error(ErrorCode::NotPermitted, "Only Creator can have permission", participant_id, room_id, app_state).await ;
+        return;
+    }
+    match app_state.database_connection.get_room_status(room_id.to_string()).await {
+        Ok(room_status) if room_status == "not_started" => {},
+        Ok(_) => {
+            send_error(ErrorCode::NotPermitted, "Bots can be added only before the Auction Starts", participant_id, room_id, app_state).await ;
+            return;
+        },
+        Err(err) => {
+            tracing::error!("error while getting the room status for adding a bot {}", err) ;
+            send_error(ErrorCode::ServerError, "Technical Issue", participant_id, room_id, app_state).await ;
+            return;
+        }
+    }
+
+    let team_name = match app_state.database_connection.get_remaining_teams(room_id.to_string()).await {
+        Ok(teams) => match teams.into_iter().next() {
+            Some(team_name) => team_name,
+            None => {
+                send_error(ErrorCode::NotPermitted, "No Teams were left for the Bot", participant_id, room_id, app_state).await ;
+                return;
+            }
+        },
+        Err(err) => {
+            tracing::error!("error while getting the remaining teams for the bot {}", err) ;
+            send_error(ErrorCode::ServerError, "Technical Issue", participant_id, room_id, app_state).await ;
+            return;
+        }
+    } ;
+
+    let bot_id = match app_state.database_connection.add_bot_participant(room_id, &team_name).await {
+        Ok(Some(bot_id)) => bot_id,
+        Ok(None) => {
+            // some one joined with the same team or another bot was added at the same time
+            send_error(ErrorCode::Conflict, "Team was already taken, try adding the Bot again", participant_id, room_id, app_state).await ;
+            return;
+        },
+        Err(err) => {
+            tracing::error!("error while adding the bot participant {}", err) ;
+            send_error(ErrorCode::ServerError, "Technical Issue", participant_id, room_id, app_state).await ;
+            return;
+        }
+    } ;
+
+    let rules = redis_connection.get_room_rules(room_id).await.unwrap() ;
+    let bot = AuctionParticipant::new(bot_id, team_name.clone(), &rules) ;
+    redis_connection.set_participant(room_id, bot.clone()).await.expect("unable to add the bot to the room") ;
+    redis_connection.add_bot(room_id, bot_id).await.expect("unable to mark the participant as bot") ;
+    tracing::info!("bot {} joined the room with team {}", bot_id, team_name) ;
+
+    // same as a new participant joining the room
+    broadcast_server_message(&ServerMessage::ParticipantJoined(bot), room_id, app_state).await ;
+    broadcast_server_message(&ServerMessage::BotAdded { team_name }, room_id, app_state).await ;
+}
+
+
+// bots along with the teams on autopilot, these were bidding from the server
+pub async fn automated_participants(room_id: &str, app_state: &AppState) -> Vec<i32> {
+    let redis_connection = app_state.redis_connection.clone() ;
+    let mut participants = redis_connection.list_bots(room_id).await.unwrap_or_default() ;
+    participants.extend(redis_connection.list_autopilot(room_id).await.unwrap_or_default()) ;
+    participants
+}
+
+
+#[tracing::instrument(
+    name = "set_autopilot",
+    skip(app_state),
+    fields(
+        room_id = %room_id,
+        participant_id = participant_id,
+        opt_in = opt_in
+    )
+)]
+pub async fn set_autopilot(room_id: &str, participant_id: i32, app_state: &AppState, opt_in: bool) {
+    match app_state.redis_connection.set_autopilot_opt_in(room_id, participant_id, opt_in).await {
+        Ok(_) => {
+            send_server_message(&ServerMessage::AutopilotOptIn { enabled: opt_in }, participant_id, room_id, app_state).await ;
+        },
+        Err(err) => {
+            tracing::error!("error while setting the autopilot {}", err) ;
+            send_error(ErrorCode::ServerError, "Technical Issue", participant_id, room_id, app_state).await ;
+        }
+    }
+}
+
+
+// bots responds after the thinking time, called whenever a new player comes up or a new bid was placed
+pub fn schedule_bot_bids(room_id: String, app_state: Arc<AppState>) {
+    tokio::spawn(async move {
+        tokio::time::sleep(BOT_THINKING_TIME).await ;
+        crate::services::room_actor::send_command(&room_id, RoomCommand::BotsTurn, &app_state).await ;
+    });
+}
+
+
+#[tracing::instrument(
+    name = "bots_turn",
+    skip(app_state),
+    fields(
+        room_id = %room_id
+    )
+)]
+pub async fn bots_turn(room_id: &str, app_state: &Arc<AppState>) {
+    let redis_connection = app_state.redis_connection.clone() ;
+    let bots = automated_participants(room_id, app_state).await ;
+    if bots.is_empty() {
+        return;
+    }
+    let timer_key = format!("auction:timer:{}", room_id) ;
+    // bots were bidding only when normal bids were taking place
+    if !redis_connection.check_key_exists(&timer_key).await.unwrap() {
+        return;
+    }
+    let (Some(current_bid), Some(current_player)) = (
+        redis_connection.get_current_bid(room_id).await.unwrap(),
+        redis_connection.get_current_player(room_id).await.unwrap()
+    ) else {
+        return;
+    } ;
+    if current_bid.player_id != current_player.id {
+        return;
+    }
+    let room_mode = match crate::services::auction_room::room_mode(room_id, app_state).await {
+        Ok(room_mode) => room_mode,
+        Err(err) => {
+            tracing::error!("unable to get the room mode for the bots {}", err) ;
+            return;
+        }
+    } ;
+    let expiry_time = std::env::var("BID_EXPIRY").unwrap().parse::<u8>().unwrap();
+    let rules = redis_connection.get_room_rules(room_id).await.unwrap() ;
+    let next_bid_amount = rules.next_bid_amount(current_bid.bid_amount, current_bid.base_price) ;
+
+    let mut bidder: Option<(i32, f32)> = None ;
+    let mut not_interested = vec![] ;
+    for bot_id in bots {
+        if bot_id == current_bid.participant_id || redis_connection.is_skipped(room_id, bot_id).await.unwrap() {
+            continue;
+        }
+        let Some(bot) = redis_connection.get_participant(room_id, bot_id).await.unwrap() else {
+            continue;
+        } ;
+        let max_price = bot_max_price(room_id, &bot, &current_player, &rules, app_state).await ;
+        let allowed = bid_allowance_handler(next_bid_amount, bot.balance, bot.total_players_brought, room_mode, &rules).await ;
+        if allowed && max_price >= next_bid_amount {
+            if bidder.is_none_or(|(_, highest)| max_price > highest) {
+                bidder = Some((bot_id, max_price)) ;
+            }
+        } else {
+            not_interested.push(bot) ;
+        }
+    }
+
+    if let Some((bot_id, max_price)) = bidder {
+        tracing::info!("bot {} was bidding {} with max price {}", bot_id, next_bid_amount, max_price) ;
+        bid(room_id, bot_id, app_state, &timer_key, room_mode, expiry_time, None).await ;
+        run_proxy_bids(room_id, app_state, &timer_key, room_mode, expiry_time).await ;
+    }
+
+    // as the price only goes up, the bots which cannot pay the next amount will never bid for this player
+    for bot in not_interested {
+        tracing::info!("bot {} was skipping the player {}", bot.id, current_player.id) ;
+        skip(room_id, bot.id, app_state, &timer_key, room_mode, None, &bot.team_name).await ;
+    }
+
+    if bidder.is_some() {
+        // other bots can respond to this bid
+        schedule_bot_bids(room_id.to_string(), app_state.clone()) ;
+    }
+}
+
+
+// the max price a bot was willing to pay for the player, 0 means the bot was not interested
+async fn bot_max_price(room_id: &str, bot: &AuctionParticipant, player: &Player, rules: &RoomRules, app_state: &AppState) -> f32 {
+    if bot.total_players_brought >= rules.max_squad_size {
+        return 0.0 ;
+    }
+    if !player.is_indian && bot.foreign_players_brought >= rules.max_foreign_players {
+        return 0.0 ;
+    }
+
+    // a balanced squad for the min squad size, 40% batters, 40% bowlers and the remaining were all rounders
+    let (bat_count, bowl_count, ar_count) = role_counts(room_id, bot, app_state).await ;
+    let min_squad_size = rules.min_squad_size as i32 ;
+    let batters_or_bowlers = (min_squad_size * 2) / 5 ;
+    let role_needed = match player.role.as_str() {
+        "BAT" => bat_count < batters_or_bowlers,
+        "BOWL" => bowl_count < batters_or_bowlers,
+        "AR" => ar_count < min_squad_size - 2 * batters_or_bowlers,
+        _ => false
+    } ;
+
+    // balance was shared among the players still required to complete the squad
+    let players_required = (min_squad_size - bot.total_players_brought as i32).max(1) ;
+    let budget_per_player = bot.balance / players_required as f32 ;
+
+    // each bot values a player a little differently, such that all bots were not bidding in the same way
+    let temperament = (bot.id * 31 + player.id * 17).rem_euclid(10) as f32 / 10.0 ;
+    let max_price = if role_needed {
+        (player.base_price * (3.0 + 2.0 * temperament)).min(budget_per_player * 2.0)
+    } else {
+        (player.base_price * (1.0 + temperament)).min(budget_per_player)
+    } ;
+    (max_price.min(bot.balance) * 100.0).round() / 100.0
+}
+
+
+/*
+    players brought by the bot for each role, counted in redis along with the sales such that they were not lagging
+    behind the tasks still waiting to be written. rooms which were started before the counts were kept in redis
+    were loaded from sold_players once.
+*/
+async fn role_counts(room_id: &str, bot: &AuctionParticipant, app_state: &AppState) -> (i32, i32, i32) {
+    let redis_connection = app_state.redis_connection.clone() ;
+    let role_counts = match redis_connection.get_role_counts(room_id, bot.id).await {
+        Ok(role_counts) => role_counts,
+        Err(err) => {
+            tracing::error!("unable to get the role counts of the bot {} {}", bot.id, err) ;
+            return (0, 0, 0);
+        }
+    } ;
+    if role_counts.is_empty() && bot.total_players_brought > 0 {
+        let (_, bat_count, bowl_count, ar_count) = app_state.database_connection.get_team_details(bot.id, "in_progress").await
+            .unwrap_or((0, 0, 0, 0)) ;
+        if let Err(err) = redis_connection.set_role_counts(room_id, bot.id, bat_count, bowl_count, ar_count).await {
+            tracing::error!("unable to set the role counts of the bot {} {}", bot.id, err) ;
+        }
+        return (bat_count, bowl_count, ar_count);
+    }
+    let count = |role: &str| role_counts.get(role).copied().unwrap_or(0) ;
+    (count("BAT"), count("BOWL"), count("AR"))
+}
@@ -0,0 +1,63 @@
+use std::sync::Arc;
+use std::time::Duration;
+use crate::models::app_state::AppState;
+use crate::models::background_db_tasks::DBCommandsAuctionRoom;
+
+/*
+    journal of the auction room tasks:
+    -> every task sent to the auction room channel was appended to auction:db_journal:{node_id} (a redis stream) first,
+       and the executor removes it once it was executed or moved to the retries, such that a crash between the
+       broadcast of a sold player and the postgres write does not lose the sale
+    -> on start the entries left in the journal of this server were replayed
+    -> every server refreshes its heartbeat, and the journal of a server whose heartbeat was expired was taken over by
+       one of the alive servers and replayed there
+*/
+
+const JOURNAL_KEEPER_INTERVAL: Duration = Duration::from_secs(5);
+
+// called before anything was sent to the auction room channel, such that no entry was queued twice
+pub async fn replay_journal(app_state: &AppState) -> redis::RedisResult<()> {
+    app_state.redis_connection.refresh_node_heartbeat(&app_state.node_id).await?;
+    let entries = app_state.redis_connection.journal_entries(&app_state.node_id).await?;
+    if !entries.is_empty() {
+        tracing::warn!("replaying {} auction room tasks from the journal", entries.len());
+        metrics::counter!("db_journal_replayed_total").increment(entries.len() as u64);
+    }
+    requeue(app_state, entries);
+    Ok(())
+}
+
+// the heartbeat was kept while the tasks were drained on shutdown, such that the journal was not taken over meanwhile
+pub async fn run_journal_keeper(app_state: Arc<AppState>) {
+    loop {
+        if let Err(err) = keep_journal(&app_state).await {
+            tracing::error!("journal keeper failed {}", err);
+        }
+        tokio::time::sleep(JOURNAL_KEEPER_INTERVAL).await;
+    }
+}
+
+async fn keep_journal(app_state: &AppState) -> redis::RedisResult<()> {
+    app_state.redis_connection.refresh_node_heartbeat(&app_state.node_id).await?;
+    if app_state.is_shutting_down() {
+        return Ok(());
+    }
+    for orphan_node_id in app_state.redis_connection.orphaned_journals(&app_state.node_id).await? {
+        let entries = app_state.redis_connection.adopt_journal(&orphan_node_id, &app_state.node_id).await?;
+        if !entries.is_empty() {
+            tracing::warn!("took over {} auction room tasks from the journal of the server {}", entries.len(), orphan_node_id);
+            metrics::counter!("db_journal_adopted_total").increment(entries.len() as u64);
+        }
+        requeue(app_state, entries);
+    }
+    Ok(())
+}
+
+fn requeue(app_state: &AppState, entries: Vec<(String, DBCommandsAuctionRoom)>) {
+    for (entry_id, command) in entries {
+        // the entry was left in the journal, so it was replayed on the next start
+        if let Err(err) = app_state.auction_room_database_task_executor.requeue(entry_id, command) {
+            tracing::error!("unable to queue the journaled task {}", err);
+        }
+    }
+}
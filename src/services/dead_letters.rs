@@ -0,0 +1,52 @@
+use crate::models::admin_models::{DeadLetterReplay, DeadLetterReplayFailure};
+use crate::models::app_state::AppState;
+use crate::models::background_db_tasks::{DBCommandsAuction, DBCommandsAuctionRoom};
+
+// retries which were not parsed were saved with this task type, they can not be replayed, only closed
+pub const UNPARSED_RETRY_TASK: &str = "UnparsedRetry";
+
+/*
+    replaying the dead letter tasks:
+    -> the pending tasks were moved to replayed first, so a task was replayed once even if two admins replays it
+    -> the task was sent to the auction room channel (or the outside auction channel for the user tasks) with all of its retries, and with the same idempotency key, so a
+       task which was committed before it landed in the DLQ was skipped by the executor
+    -> a task which was not rebuilt or not queued was moved back to pending
+*/
+pub async fn replay_dead_letter_tasks(app_state: &AppState, ids: &[i64]) -> Result<DeadLetterReplay, sqlx::Error> {
+    let claimed = app_state.database_connection.update_dead_letter_tasks_status(ids, "pending", "replayed").await? ;
+    let mut replay = DeadLetterReplay {
+        skipped: ids.iter().filter(|id| !claimed.iter().any(|task| task.id == **id)).copied().collect(),
+        ..Default::default()
+    } ;
+    for task in claimed {
+        let queued = match DBCommandsAuction::from_dead_letter(&task.task_type, task.payload.clone()) {
+            // the tasks outside the auction room were not journaled, they were sent to their own channel
+            Some(outside_task) => outside_task.and_then(|mut command| {
+                command.reset_retries() ;
+                app_state.database_task_executor.send(command)
+                    .map_err(|_| String::from("background tasks channel was closed"))
+            }),
+            None => match DBCommandsAuctionRoom::from_dead_letter(&task.task_type, task.payload) {
+                Ok(mut command) => {
+                    command.reset_retries() ;
+                    app_state.auction_room_database_task_executor.send(command).await
+                        .map_err(|err| err.to_string())
+                },
+                Err(err) => Err(err)
+            }
+        } ;
+        match queued {
+            Ok(_) => {
+                tracing::info!("replayed the dead letter task {}", task.id) ;
+                metrics::counter!("dlq_tasks_replayed_total").increment(1) ;
+                replay.replayed.push(task.id) ;
+            },
+            Err(error) => {
+                tracing::error!("unable to replay the dead letter task {} {}", task.id, error) ;
+                app_state.database_connection.update_dead_letter_tasks_status(&[task.id], "replayed", "pending").await? ;
+                replay.failed.push(DeadLetterReplayFailure { id: task.id, error }) ;
+            }
+        }
+    }
+    Ok(replay)
+}
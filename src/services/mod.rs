@@ -3,4 +3,11 @@ pub mod auction;
 pub mod other;
 pub mod background_db_tasks_runner;
 pub mod llm_call;
-pub mod auction_logic_executor;
\ No newline at end of file
+pub mod auction_logic_executor;
+pub mod proxy_bidding;
+pub mod bot_participants;
+pub mod room_actor;
+pub mod room_rehydration;
+pub mod shutdown;
+pub mod db_journal;
+pub mod dead_letters;
@@ -0,0 +1,127 @@
+use crate::auction::{send_error, send_server_message};
+use crate::models::app_state::AppState;
+use crate::models::protocol_models::{ErrorCode, ServerMessage};
+use crate::services::auction_logic_executor::bid;
+
+/*
+    Proxy bidding: a participant registers a hidden max amount for the current or an upcoming player with
+    proxy-player_id-max_amount (eg: proxy-25-7.50) or {"type":"proxy","player_id":25,"max_amount":7.5} and cancels it with
+    proxy-cancel-player_id or {"type":"proxy-cancel","player_id":25}.
+    whenever he was outbid, the server bids on his behalf one increment at a time using the same bid logic,
+    until his max amount or his bid allowance was reached.
+*/
+
+#[tracing::instrument(
+    name = "register_proxy_bid",
+    skip(app_state),
+    fields(
+        room_id = %room_id,
+        participant_id = participant_id,
+        room_mode = room_mode,
+        expiry_time = expiry_time
+    )
+)]
+pub async fn register_proxy_bid(room_id: &str, participant_id: i32, app_state: &AppState, player_id: i32, max_amount: Option<f32>, room_mode: bool, expiry_time: u8) {
+    let redis_connection = app_state.redis_connection.clone() ;
+
+    // no max amount means cancelling the proxy bid
+    let Some(max_amount) = max_amount else {
+        if let Err(err) = redis_connection.remove_proxy_bid(room_id, player_id, participant_id).await {
+            tracing::error!("unable to remove the proxy bid {}", err) ;
+            send_error(ErrorCode::ServerError, "Technical Issue", participant_id, room_id, app_state).await ;
+            return;
+        }
+        send_server_message(&ServerMessage::ProxyCancelled { player_id }, participant_id, room_id, app_state).await ;
+        return;
+    } ;
+    if !max_amount.is_finite() || max_amount <= 0.0 {
+        tracing::warn!("invalid proxy bid amount {}", max_amount) ;
+        send_error(ErrorCode::InvalidMessage, "Invalid Proxy Bid", participant_id, room_id, app_state).await ;
+        return;
+    }
+
+    let current_player = match redis_connection.get_current_player(room_id).await {
+        Ok(current_player) => current_player,
+        Err(err) => {
+            tracing::error!("unable to get the current player {}", err) ;
+            send_error(ErrorCode::ServerError, "Technical Issue", participant_id, room_id, app_state).await ;
+            return;
+        }
+    } ;
+    if current_player.as_ref().is_some_and(|current_player| player_id < current_player.id) {
+        send_error(ErrorCode::BidRejected, "Player was already Auctioned", participant_id, room_id, app_state).await ;
+        return;
+    }
+
+    if let Err(err) = redis_connection.set_proxy_bid(room_id, player_id, participant_id, max_amount).await {
+        tracing::error!("unable to set the proxy bid {}", err) ;
+        send_error(ErrorCode::ServerError, "Technical Issue", participant_id, room_id, app_state).await ;
+        return;
+    }
+    tracing::info!("proxy bid registered for player {} up to {}", player_id, max_amount) ;
+    send_server_message(&ServerMessage::ProxyRegistered { player_id, max_amount }, participant_id, room_id, app_state).await ;
+
+    // if the player was already up for the auction, he might be outbid already or no one bid yet
+    if current_player.is_some_and(|current_player| current_player.id == player_id) {
+        let timer_key = format!("auction:timer:{}", room_id) ;
+        run_proxy_bids(room_id, app_state, &timer_key, room_mode, expiry_time).await ;
+    }
+}
+
+
+#[tracing::instrument(
+    name = "run_proxy_bids",
+    skip(app_state),
+    fields(
+        room_id = %room_id,
+        room_mode = room_mode,
+        expiry_time = expiry_time
+    )
+)]
+pub async fn run_proxy_bids(room_id: &str, app_state: &AppState, timer_key: &str, room_mode: bool, expiry_time: u8) {
+    let redis_connection = app_state.redis_connection.clone() ;
+    loop {
+        let Some(current_bid) = redis_connection.get_current_bid(room_id).await.unwrap() else {
+            return;
+        } ;
+        // proxies react when some one was outbid or the player just came up, and only while the bidding was still open
+        if current_bid.player_id <= 0 || !redis_connection.check_key_exists(timer_key).await.unwrap() {
+            return;
+        }
+        let proxy_bids = redis_connection.get_proxy_bids(room_id, current_bid.player_id).await.unwrap() ;
+        if proxy_bids.is_empty() {
+            return;
+        }
+        let rules = redis_connection.get_room_rules(room_id).await.unwrap() ;
+        let next_bid_amount = rules.next_bid_amount(current_bid.bid_amount, current_bid.base_price) ;
+
+        // proxies whose max amount was reached are cleared, such that the participant knows he was outbid
+        for (proxy_participant_id, max_amount) in proxy_bids.iter() {
+            if *proxy_participant_id != current_bid.participant_id && *max_amount < next_bid_amount {
+                redis_connection.remove_proxy_bid(room_id, current_bid.player_id, *proxy_participant_id).await.unwrap() ;
+                send_server_message(&ServerMessage::ProxyOutbid { max_amount: *max_amount }, *proxy_participant_id, room_id, app_state).await ;
+            }
+        }
+
+        // the proxy with the highest max amount bids first
+        let Some(proxy_participant_id) = proxy_bids.iter()
+            .filter(|(proxy_participant_id, max_amount)| **proxy_participant_id != current_bid.participant_id && **max_amount >= next_bid_amount)
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .map(|(proxy_participant_id, _)| *proxy_participant_id) else {
+            return;
+        } ;
+
+        tracing::info!("proxy bid of participant {} was bidding {}", proxy_participant_id, next_bid_amount) ;
+        bid(room_id, proxy_participant_id, app_state, timer_key, room_mode, expiry_time, None).await ;
+
+        let bid_taken = redis_connection.get_current_bid(room_id).await.unwrap()
+            .is_some_and(|bid| bid.participant_id == proxy_participant_id) ;
+        if !bid_taken {
+            // bid was rejected (bid allowance, foreign players limit, skipped ...), so the proxy cannot continue
+            tracing::info!("proxy bid of participant {} was rejected, clearing it", proxy_participant_id) ;
+            redis_connection.remove_proxy_bid(room_id, current_bid.player_id, proxy_participant_id).await.unwrap() ;
+            send_server_message(&ServerMessage::ProxyStopped, proxy_participant_id, room_id, app_state).await ;
+            return;
+        }
+    }
+}
@@ -0,0 +1,273 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use axum::extract::ws::Message;
+use tokio::sync::{mpsc, oneshot};
+use crate::models::app_state::AppState;
+use crate::models::room_models::{FanOut, RoomCommand, RoomHandle};
+
+// well within the presence ttl, such that a missed refresh will not drop the participants of this server
+const PRESENCE_HEARTBEAT: std::time::Duration = std::time::Duration::from_secs(5) ;
+
+/*
+    Room actor: every live room was owned by two tasks,
+    -> the room actor, which receives the commands (client messages, timer expiries, bots turn ...) and applies them
+       one after the other, such that no two commands of the same room reads and writes the redis state at a time.
+    -> the fan out task, which owns the senders of all the connections of the room and sends the messages to them.
+       it never waits on a command, so the messages can be sent even while a command was being applied.
+    app_state.rooms was only used to get the handle of the room, no lock was held while sending the messages.
+
+    several servers can serve the same room, so the messages of the room were published to the redis channel of
+    the room and listen_for_room_events of every server hands them to its own fan out task. the fan out task keeps
+    the presence of its connections in redis, which was used to count the participants across the servers.
+
+    a room without connections on this server was retired: the room actor removes its handle from app_state.rooms
+    under the write lock, and both the tasks forwards whatever was already sent to them to the next room actor of
+    the room, until all the senders of the old handle were dropped. the next room_handle starts a new room actor.
+*/
+
+// handle of the room, the room actor was created if it was not running on this server. only the sockets joining the room starts a room actor
+pub async fn room_handle(room_id: &str, app_state: &Arc<AppState>) -> RoomHandle {
+    if let Some(handle) = app_state.rooms.read().await.get(room_id) {
+        return handle.clone();
+    }
+    let mut rooms = app_state.rooms.write().await ;
+    rooms.entry(room_id.to_string())
+        .or_insert_with(|| spawn_room(room_id.to_string(), app_state.clone()))
+        .clone()
+}
+
+// handle of the room only if it was running on this server
+pub async fn existing_room(room_id: &str, app_state: &AppState) -> Option<RoomHandle> {
+    app_state.rooms.read().await.get(room_id).cloned()
+}
+
+/*
+    commands of a room without connections on this server (timer expiries claimed by this server, bots turn, leaves
+    after the grace period) were applied in a task of their own, without starting a room actor that retires right
+    after. the redis scripts and the expiry lock keeps them consistent with the room actors of the other servers.
+*/
+pub async fn send_command(room_id: &str, command: RoomCommand, app_state: &Arc<AppState>) {
+    let command = match existing_room(room_id, app_state).await {
+        Some(handle) => match handle.commands.send(command) {
+            Ok(_) => return,
+            Err(err) => {
+                tracing::warn!("room actor of the room {} was stopped, applying the command without it", room_id) ;
+                err.0
+            }
+        },
+        None => command
+    } ;
+    if matches!(command, RoomCommand::Retire) {
+        return;
+    }
+    metrics::counter!("room_commands_without_actor_total").increment(1) ;
+    let room_id = room_id.to_string() ;
+    let app_state = app_state.clone() ;
+    tokio::spawn(async move {
+        apply_command(&room_id, command, &app_state).await ;
+    });
+}
+
+pub async fn fan_out(room_id: &str, fan_out: FanOut, app_state: &AppState) {
+    let Some(handle) = existing_room(room_id, app_state).await else {
+        tracing::info!("no connections of the room {} on this server", room_id) ;
+        return;
+    } ;
+    if handle.fan_out.send(fan_out).is_err() {
+        tracing::error!("fan out task of the room {} was stopped", room_id) ;
+    }
+}
+
+// participants connected to this room on this server
+pub async fn connected_participants(room_id: &str, app_state: &AppState) -> Vec<i32> {
+    let (reply, response) = oneshot::channel() ;
+    fan_out(room_id, FanOut::Connected { reply }, app_state).await ;
+    response.await.unwrap_or_default()
+}
+
+// participants connected to this room on any of the servers
+pub async fn present_participants(room_id: &str, app_state: &AppState) -> Vec<i32> {
+    // the fan out task updates the presence before replying, so the connects and disconnects of this server were in it
+    let mut participants = connected_participants(room_id, app_state).await ;
+    match app_state.redis_connection.present_participants(room_id).await {
+        Ok(present) => participants.extend(present),
+        Err(err) => tracing::error!("unable to get the presence of the room {} {}", room_id, err)
+    }
+    participants.sort_unstable() ;
+    participants.dedup() ;
+    participants
+}
+
+
+fn spawn_room(room_id: String, app_state: Arc<AppState>) -> RoomHandle {
+    tracing::info!("starting the room actor of the room {}", room_id) ;
+    let (commands, commands_receiver) = mpsc::unbounded_channel::<RoomCommand>() ;
+    let (fan_out, fan_out_receiver) = mpsc::unbounded_channel::<FanOut>() ;
+    let connections = Arc::new(AtomicUsize::new(0)) ;
+    tokio::spawn(run_fan_out(room_id.clone(), app_state.clone(), fan_out_receiver, commands.clone(), connections.clone())) ;
+    tokio::spawn(run_room_actor(room_id, app_state, commands_receiver)) ;
+    RoomHandle { commands, fan_out, connections }
+}
+
+
+#[tracing::instrument(
+    name = "room_actor",
+    skip(app_state, commands),
+    fields(
+        room_id = %room_id
+    )
+)]
+async fn run_room_actor(room_id: String, app_state: Arc<AppState>, mut commands: mpsc::UnboundedReceiver<RoomCommand>) {
+    while let Some(command) = commands.recv().await {
+        if !matches!(command, RoomCommand::Retire) {
+            let room_id = room_id.clone() ;
+            let app_state = app_state.clone() ;
+            // each command runs in its own task, such that a panic in a command will not stop the room actor,
+            // still the next command starts only after this one was completed
+            let result = tokio::spawn(async move {
+                apply_command(&room_id, command, &app_state).await ;
+            }).await ;
+            if let Err(err) = result {
+                tracing::error!("command failed in the room actor {}", err) ;
+                metrics::counter!("room_actor_command_failures_total").increment(1) ;
+            }
+        }
+        if commands.is_empty() && retire_if_idle(&room_id, &app_state, &commands).await {
+            break;
+        }
+    }
+    // commands sent with the handle taken before the room was retired
+    while let Some(command) = commands.recv().await {
+        if !matches!(command, RoomCommand::Retire) {
+            send_command(&room_id, command, &app_state).await ;
+        }
+    }
+    tracing::info!("room actor stopped") ;
+}
+
+/*
+    true if the handle of the room was removed, no connections and no commands were left. the connections were
+    counted by the fan out task, nothing was awaited while the write lock was held, such that the other rooms were
+    not blocked on this room. a connect which was still in the fan out queue was forwarded by the fan out task
+    to the next room actor, once it sees the retire after it.
+*/
+async fn retire_if_idle(room_id: &str, app_state: &AppState, commands: &mpsc::UnboundedReceiver<RoomCommand>) -> bool {
+    let Some(handle) = existing_room(room_id, app_state).await else {
+        return false;
+    } ;
+    // checked without the write lock first, such that the other rooms were not blocked after every command
+    if handle.connections.load(Ordering::SeqCst) != 0 {
+        return false;
+    }
+    let mut rooms = app_state.rooms.write().await ;
+    let idle = rooms.get(room_id).is_some_and(|handle| handle.connections.load(Ordering::SeqCst) == 0) && commands.is_empty() ;
+    if !idle {
+        return false;
+    }
+    if let Some(handle) = rooms.remove(room_id) {
+        let _ = handle.fan_out.send(FanOut::Retired) ;
+    }
+    tracing::info!("no connections of the room {} on this server, retired its room actor", room_id) ;
+    true
+}
+
+async fn apply_command(room_id: &str, command: RoomCommand, app_state: &Arc<AppState>) {
+    match command {
+        RoomCommand::Client { participant_id, team_name, room_mode, message } => {
+            crate::auction::handle_client_message(room_id.to_string(), participant_id, team_name, room_mode, message, app_state.clone()).await ;
+        },
+        RoomCommand::TimerExpired { timer_key, claim, is_rtm, bid_expiry } => {
+            crate::services::auction_room::handle_timer_claim(app_state, room_id, &timer_key, &claim, is_rtm, bid_expiry).await ;
+        },
+        RoomCommand::BotsTurn => {
+            crate::services::bot_participants::bots_turn(room_id, app_state).await ;
+        },
+        RoomCommand::ParticipantLeft { participant_id, team_name } => {
+            crate::auction::participant_left(room_id, participant_id, team_name, app_state).await ;
+        },
+        RoomCommand::Retire => {}
+    }
+}
+
+
+async fn run_fan_out(room_id: String, app_state: Arc<AppState>, mut fan_out: mpsc::UnboundedReceiver<FanOut>, commands: mpsc::UnboundedSender<RoomCommand>, connection_count: Arc<AtomicUsize>) {
+    // i32 is participant id, a participant can have more than one connection (multiple tabs)
+    let mut connections: Vec<(i32, mpsc::UnboundedSender<Message>)> = vec![] ;
+    let mut heartbeat = tokio::time::interval(PRESENCE_HEARTBEAT) ;
+    let mut retired = false ;
+    loop {
+        let message = tokio::select! {
+            message = fan_out.recv() => message,
+            _ = heartbeat.tick(), if !retired => {
+                refresh_presence(&room_id, &connections, &app_state).await ;
+                continue;
+            }
+        } ;
+        let Some(message) = message else {
+            break;
+        } ;
+        if retired {
+            forward_fan_out(&room_id, message, &app_state).await ;
+            continue;
+        }
+        let was_connected = !connections.is_empty() ;
+        match message {
+            FanOut::Connect { participant_id, sender } => {
+                connections.push((participant_id, sender)) ;
+                refresh_presence(&room_id, &connections, &app_state).await ;
+            },
+            FanOut::Disconnect { participant_id, sender } => {
+                connections.retain(|(id, connection)| !(*id == participant_id && connection.same_channel(&sender))) ;
+                // other tabs of the participant on this server keeps him present
+                if !connections.iter().any(|(id, _)| *id == participant_id)
+                    && let Err(err) = app_state.redis_connection.remove_presence(&room_id, participant_id, &app_state.node_id).await {
+                    tracing::error!("unable to remove the presence of the participant {} {}", participant_id, err) ;
+                }
+            },
+            FanOut::Broadcast(message) => {
+                // closed connections were removed, such that the messages will not pile up for them
+                connections.retain(|(_, connection)| connection.send(message.clone()).is_ok()) ;
+            },
+            FanOut::Send { participant_id, message } => {
+                connections.retain(|(id, connection)| *id != participant_id || connection.send(message.clone()).is_ok()) ;
+            },
+            FanOut::Connected { reply } => {
+                let _ = reply.send(connections.iter().map(|(id, _)| *id).collect()) ;
+            },
+            FanOut::Retired => {
+                retired = true ;
+                // connected after the room actor checked, they were moved to the next room actor
+                for (participant_id, sender) in connections.drain(..) {
+                    forward_fan_out(&room_id, FanOut::Connect { participant_id, sender }, &app_state).await ;
+                }
+            }
+        }
+        connection_count.store(connections.len(), Ordering::SeqCst) ;
+        // the last connection of this server was closed, the room actor retires the room once it was idle
+        if was_connected && connections.is_empty() && !retired {
+            let _ = commands.send(RoomCommand::Retire) ;
+        }
+    }
+    tracing::info!("fan out task of the room {} stopped", room_id) ;
+}
+
+async fn forward_fan_out(room_id: &str, message: FanOut, app_state: &Arc<AppState>) {
+    match message {
+        FanOut::Connect { .. } => {
+            if room_handle(room_id, app_state).await.fan_out.send(message).is_err() {
+                tracing::error!("fan out task of the room {} was stopped", room_id) ;
+            }
+        },
+        FanOut::Retired => {},
+        message => fan_out(room_id, message, app_state).await
+    }
+}
+
+async fn refresh_presence(room_id: &str, connections: &[(i32, mpsc::UnboundedSender<Message>)], app_state: &AppState) {
+    let mut participants: Vec<i32> = connections.iter().map(|(id, _)| *id).collect() ;
+    participants.sort_unstable() ;
+    participants.dedup() ;
+    if let Err(err) = app_state.redis_connection.refresh_presence(room_id, &participants, &app_state.node_id).await {
+        tracing::error!("unable to refresh the presence of the room {} {}", room_id, err) ;
+    }
+}
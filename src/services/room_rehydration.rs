@@ -0,0 +1,156 @@
+use crate::models::app_state::AppState;
+use crate::models::auction_models::{AuctionParticipant, RoomMeta};
+use crate::models::room_models::{RehydratedRoom, RehydrationError};
+use crate::services::auction_room::REHYDRATION_LOCK_MS;
+use crate::services::background_db_tasks_runner::execute_auction_room_task;
+
+/*
+    balances, squad counts, rtms and foreign players of the participants were only in redis while the auction was
+    running, postgres was having the purse and rtms of the participants along with the sold and unsold players.
+    if redis lost the room, the room was rebuilt from postgres, such that the auction resumes from the next player
+    which was not auctioned yet. the room was rebuilt in paused state, the creator starts it again.
+    skips of the next player and of its pool were journaled to skip_votes, so they were restored as well.
+    proxy bids, autopilot opt ins and the bid on the player who was in the auction at the time of the loss were not
+    in postgres, so they were lost. the rebuild was done under a lock in redis, only one server rebuilds the room.
+*/
+#[tracing::instrument(
+    name = "rehydrate_room",
+    skip(app_state),
+    fields(
+        room_id = %room_id
+    )
+)]
+pub async fn rehydrate_room(room_id: &str, app_state: &AppState) -> Result<RehydratedRoom, RehydrationError> {
+    let redis_connection = app_state.redis_connection.clone() ;
+    // two joins at the same time were not rebuilding the room twice, the second one finds the participants after the first one
+    let token = format!("{}:{}", app_state.node_id, uuid::Uuid::new_v4()) ;
+    if !redis_connection.lock_rehydration(room_id, &token).await.map_err(|err| RehydrationError::Failed(err.to_string()))? {
+        return Err(RehydrationError::InProgress) ;
+    }
+    let result = rebuild_room(room_id, app_state).await ;
+    if let Err(err) = redis_connection.unlock_rehydration(room_id, &token).await {
+        tracing::error!("unable to release the rehydration lock of the room {} {}", room_id, err) ;
+    }
+    result
+}
+
+// waits for the rebuild of the room by another join, such that the participant was checked against the rebuilt room
+pub async fn wait_for_rehydration(room_id: &str, app_state: &AppState) {
+    let deadline = tokio::time::Instant::now() + std::time::Duration::from_millis(REHYDRATION_LOCK_MS as u64) ;
+    while tokio::time::Instant::now() < deadline && app_state.redis_connection.is_rehydrating(room_id).await.unwrap_or(false) {
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await ;
+    }
+}
+
+async fn rebuild_room(room_id: &str, app_state: &AppState) -> Result<RehydratedRoom, RehydrationError> {
+    let database = app_state.database_connection.clone() ;
+    let redis_connection = app_state.redis_connection.clone() ;
+    let failed = |err: &dyn std::fmt::Display| RehydrationError::Failed(err.to_string()) ;
+
+    let room_status = database.get_room_status(room_id.to_string()).await.map_err(|err| failed(&err))? ;
+    if room_status != "in_progress" {
+        return Err(RehydrationError::RoomNotInProgress) ;
+    }
+    if !redis_connection.list_participants(room_id).await.map_err(|err| failed(&err))?.is_empty() {
+        return Err(RehydrationError::RoomStateExists) ;
+    }
+    // the sales which were broadcasted but not yet written were committed first, such that the balances and squads were not stale
+    let participant_ids: Vec<i32> = database.get_participants_state(room_id).await.map_err(|err| failed(&err))?
+        .iter().map(|participant| participant.id).collect() ;
+    commit_pending_tasks(room_id, &participant_ids, app_state).await.map_err(|err| failed(&err))? ;
+    let participants = database.get_participants_state(room_id).await.map_err(|err| failed(&err))? ;
+    // the creator resumes the auction, rooms whose creator was not a participant were given to the first team
+    let Some(room_creator_id) = participants.iter().find(|participant| participant.is_creator)
+        .or_else(|| participants.iter().find(|participant| !participant.is_bot))
+        .map(|participant| participant.id) else {
+        return Err(RehydrationError::NoParticipants) ;
+    } ;
+
+    // players were loaded on startup, a flushed redis was not having them as well
+    if redis_connection.get_player(1, "").await.is_err() {
+        tracing::info!("players were not in redis, loading them again") ;
+        let players = database.get_players().await.map_err(|err| failed(&err))? ;
+        redis_connection.load_players_to_redis(players).await.map_err(|err| failed(&err))? ;
+    }
+    let next_player_id = database.get_last_auctioned_player(room_id).await.map_err(|err| failed(&err))?.unwrap_or(0) + 1 ;
+    let next_player = match redis_connection.get_player(next_player_id, "").await {
+        Ok(player) => player,
+        Err(err) if err.to_string().contains("Player not found") => return Err(RehydrationError::AuctionFinished),
+        Err(err) => return Err(failed(&err))
+    } ;
+
+    // left overs of the lost room, like the meta created by a participant who joined after the loss
+    redis_connection.auction_clean_up(room_id).await.map_err(|err| failed(&err))? ;
+    redis_connection.cancel_timer(&format!("auction:timer:{}", room_id)).await.map_err(|err| failed(&err))? ;
+    redis_connection.cancel_timer(&format!("auction:timer:rtms:{}", room_id)).await.map_err(|err| failed(&err))? ;
+
+    let rules = database.get_room_rules(room_id).await.map_err(|err| failed(&err))? ;
+    redis_connection.set_room_rules(room_id, &rules).await.map_err(|err| failed(&err))? ;
+    redis_connection.set_room_meta(room_id, RoomMeta {
+        room_creator_id,
+        pause: true
+    }).await.map_err(|err| failed(&err))? ;
+    for participant in participants.iter() {
+        redis_connection.set_participant(room_id, AuctionParticipant {
+            id: participant.id,
+            team_name: participant.team_selected.clone(),
+            balance: participant.purse_remaining,
+            total_players_brought: participant.players_brought as u8,
+            remaining_rtms: participant.remaining_rtms,
+            is_unmuted: true,
+            foreign_players_brought: participant.foreign_players_brought as u8
+        }).await.map_err(|err| failed(&err))? ;
+        let (_, bat_count, bowl_count, ar_count) = database.get_team_details(participant.id, "in_progress").await.map_err(|err| failed(&err))? ;
+        redis_connection.set_role_counts(room_id, participant.id, bat_count, bowl_count, ar_count).await.map_err(|err| failed(&err))? ;
+        if participant.is_bot {
+            redis_connection.add_bot(room_id, participant.id).await.map_err(|err| failed(&err))? ;
+        }
+    }
+    // skips of the player and of its pool were restored, the skips of the earlier players and pools were done with
+    let pool_no = i32::from(next_player.pool_no) ;
+    redis_connection.set_current_player(room_id, next_player).await.map_err(|err| failed(&err))? ;
+    for skipped_id in database.get_skip_votes(room_id, "player", next_player_id).await.map_err(|err| failed(&err))? {
+        redis_connection.mark_skipped(room_id, skipped_id).await.map_err(|err| failed(&err))? ;
+    }
+    for skipped_id in database.get_skip_votes(room_id, "pool", pool_no).await.map_err(|err| failed(&err))? {
+        redis_connection.mark_participant_skipped_pool(room_id, skipped_id).await.map_err(|err| failed(&err))? ;
+    }
+
+    tracing::info!("room {} was rebuilt with {} participants, resumes from the player {}", room_id, participants.len(), next_player_id) ;
+    metrics::counter!("rooms_rehydrated_total").increment(1) ;
+    Ok(RehydratedRoom {
+        room_id: room_id.to_string(),
+        participants: participants.len(),
+        next_player_id
+    })
+}
+
+/*
+    tasks of the room which were in the journals of the servers or waiting for their retry were executed here, before
+    the room was read from postgres. they were left where they were, their idempotency keys makes the executor skip
+    them later. the rebuild was stopped if any of them was failing, as the room would be rebuilt from stale rows.
+*/
+async fn commit_pending_tasks(room_id: &str, participant_ids: &[i32], app_state: &AppState) -> Result<(), String> {
+    let redis_connection = app_state.redis_connection.clone() ;
+    let mut pending = vec![] ;
+    for node_id in redis_connection.journal_nodes().await.map_err(|err| err.to_string())? {
+        let entries = redis_connection.journal_entries(&node_id).await.map_err(|err| err.to_string())? ;
+        pending.extend(entries.into_iter().map(|(_, command)| command)) ;
+    }
+    pending.extend(redis_connection.pending_retries().await.map_err(|err| err.to_string())?) ;
+    pending.retain(|command| command.belongs_to_room(room_id, participant_ids)) ;
+    if !pending.is_empty() {
+        tracing::warn!("committing {} pending tasks of the room {} before rebuilding it", pending.len(), room_id) ;
+    }
+    for command in pending {
+        match execute_auction_room_task(&app_state.database_connection, &command).await {
+            Ok(applied) => {
+                if applied {
+                    metrics::counter!("rehydration_pending_tasks_applied_total", "task_type" => command.task_type()).increment(1) ;
+                }
+            },
+            Err(err) => return Err(format!("pending {} task {} was not committed, {}", command.task_type(), command.idempotency_key(), err))
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,97 @@
+use std::sync::Arc;
+use std::time::Duration;
+use axum::extract::ws::{CloseFrame, Message};
+use crate::models::app_state::{AppState, TaskQueue};
+use crate::models::protocol_models::ServerMessage;
+use crate::models::room_models::FanOut;
+
+/*
+    shutdown of the server:
+    -> on SIGTERM or ctrl-c the new websockets were rejected, the timer scheduler and the retries listener stops
+    -> every room on this server was told about the maintenance, the timers of the rooms which were not served by
+       any other server were paused with their remaining time and the connections were closed, such that the clients
+       reconnects (may be to another server)
+    -> once axum stops, the background tasks channels were drained, such that no sold player or balance update was lost,
+       the auction room tasks which were not drained were still in the journal and taken over by the other servers
+*/
+
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c().await.expect("unable to listen for ctrl-c");
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("unable to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {}
+    }
+    tracing::warn!("shutdown signal received");
+}
+
+pub async fn prepare_shutdown(app_state: &Arc<AppState>) {
+    app_state.shutdown.send_replace(true);
+    let rooms: Vec<_> = app_state.rooms.read().await.iter()
+        .map(|(room_id, room)| (room_id.clone(), room.clone()))
+        .collect();
+    tracing::info!("notifying {} rooms about the shutdown", rooms.len());
+    let notice = ServerMessage::Maintenance { message: String::from("Server was restarting, reconnecting you") };
+    for (room_id, room) in rooms {
+        // only the connections of this server were told, the other servers keeps serving the room
+        let _ = room.fan_out.send(FanOut::Broadcast(notice.to_message()));
+        match app_state.redis_connection.is_served_by_other_nodes(&room_id, &app_state.node_id).await {
+            Ok(true) => {},
+            Ok(false) => match app_state.redis_connection.pause_timers(&room_id).await {
+                Ok(paused) => tracing::info!("paused {} timers of the room {}", paused, room_id),
+                Err(err) => tracing::error!("unable to pause the timers of the room {} {}", room_id, err)
+            },
+            Err(err) => tracing::error!("unable to get the presence of the room {} {}", room_id, err)
+        }
+        let _ = room.fan_out.send(FanOut::Broadcast(Message::Close(Some(CloseFrame {
+            code: 1012, // service restart
+            reason: "server restarting".into(),
+        }))));
+    }
+}
+
+pub async fn drain_background_tasks(app_state: &AppState, retries: tokio::task::JoinHandle<()>) {
+    let timeout = drain_timeout();
+    // the retries listener moves the due retries to the channel, so it was stopped before draining
+    if let Err(err) = retries.await {
+        tracing::error!("retries listener failed {}", err);
+    }
+    // failed tasks of the auction room channel were sent to the DLQ channel, so that was drained after it
+    drain("auction room", app_state.auction_room_database_task_executor.tasks(), timeout).await;
+    drain("DLQ", &app_state.dlq_task_executor, timeout).await;
+    drain("outside auction", &app_state.database_task_executor, timeout).await;
+    // the auction room tasks which were still pending were left in the journal for the other servers
+    match app_state.redis_connection.retire_journal(&app_state.node_id).await {
+        Ok(true) => tracing::info!("journal of the auction room tasks was removed"),
+        Ok(false) => tracing::warn!("journal of the auction room tasks was left for the other servers"),
+        Err(err) => tracing::error!("unable to retire the journal of the auction room tasks {}", err)
+    }
+}
+
+async fn drain<T>(name: &str, queue: &TaskQueue<T>, timeout: Duration) {
+    tracing::info!("draining {} background tasks, {} pending", name, queue.pending());
+    if queue.drain(timeout).await {
+        tracing::info!("{} background tasks were drained", name);
+    } else {
+        tracing::error!("{} background tasks were still pending after {:?}, {} of them were lost", name, timeout, queue.pending());
+        metrics::counter!("shutdown_lost_background_tasks_total").increment(queue.pending() as u64);
+    }
+}
+
+fn drain_timeout() -> Duration {
+    let seconds = std::env::var("SHUTDOWN_DRAIN_SECS").ok()
+        .and_then(|seconds| seconds.parse::<u64>().ok())
+        .unwrap_or(30);
+    Duration::from_secs(seconds)
+}